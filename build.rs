@@ -13,7 +13,7 @@ fn main() {
 
     let mut seed = [0u8; 32];
     OsRng.try_fill_bytes(&mut seed).expect("OS RNG unavailable");
-    let digest = Sha3_512::digest(&seed);
+    let digest = Sha3_512::digest(seed);
     let token = URL_SAFE_NO_PAD.encode(&digest[..6]);
 
     println!("cargo:rustc-env=APP_TOKEN={token}");
@@ -3,7 +3,7 @@
 //! This utility encrypts the JSON configuration data and outputs the
 //! ciphertext in a format that can be embedded in the constants.rs file.
 
-use claude_token_provider::crypto::{constants::ORIGINAL_JSON, encrypt_data};
+use claude_token_provider::crypto::{constants::ORIGINAL_JSON, encrypt_data, Algorithm};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== Claude Token Provider - Constant Generator ===\n");
@@ -17,7 +17,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!();
 
     let json_bytes = ORIGINAL_JSON.as_bytes();
-    let encrypted = encrypt_data(json_bytes, &key, &nonce)?;
+    let encrypted = encrypt_data(json_bytes, key, nonce, Algorithm::Aes256Gcm)?;
 
     println!("Encrypted ciphertext ({} bytes):", encrypted.len());
     println!();
@@ -52,7 +52,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     use claude_token_provider::crypto::decrypt_data;
 
     println!("Verification: Attempting to decrypt...");
-    let decrypted = decrypt_data(&encrypted, &key, &nonce)?;
+    let decrypted = decrypt_data(&encrypted, key, nonce, Algorithm::Aes256Gcm)?;
     let decrypted_text = String::from_utf8(decrypted)?;
 
     if decrypted_text == ORIGINAL_JSON {
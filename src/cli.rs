@@ -0,0 +1,611 @@
+//! Command-line interface
+//!
+//! Defines the flags accepted by the binary and how they map onto the
+//! library's input and configuration options. With no flags the tool runs
+//! its default interactive flow.
+
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+use std::borrow::Cow;
+use std::env;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+use crate::config::expand::EnvExpansion;
+use crate::config::file_ops::{ApplyOptions, DEFAULT_MAX_PLAINTEXT_SIZE, DEFAULT_WRITE_ATTEMPTS};
+use crate::config::format::FormatSelection;
+use crate::config::merger::MergeStrategy;
+use crate::crypto::nonce_guard::{check_nonce_strength, NonceReusePolicy};
+use crate::crypto::{
+    decode_key_auto, decode_nonce_auto, decode_payload_base64, SecretKey, NONCE_SIZE,
+};
+use crate::input::{
+    get_nonce_with_options, get_secret_key_from_keyring, get_secret_key_with_options, is_truthy,
+    load_encrypted_payload, parse_combined_credentials, read_credentials_from, PromptOptions,
+    DEFAULT_MAX_ATTEMPTS, KEYRING_SERVICE, PAYLOAD_ENV_VAR,
+};
+use crate::output::OutputFormat;
+use crate::self_deletion::{self_deletion_requested, SELF_DELETE_ENV_VAR};
+use crate::Result;
+
+/// Environment variable that enables quiet mode when set to a truthy value
+pub const QUIET_ENV_VAR: &str = "CLAUDE_TOKEN_QUIET";
+
+/// Command-line arguments for the token provider
+#[derive(Debug, Parser)]
+#[command(
+    name = "claude-token-provider",
+    version,
+    about = "Decrypts and applies Claude configuration settings"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Base64- or hex-encoded secret key; skips the interactive key prompt.
+    /// Note that command-line arguments may be visible to other local users.
+    #[arg(long, value_name = "ENCODED", global = true)]
+    pub key: Option<String>,
+
+    /// Base64- or hex-encoded IV/Nonce; skips the interactive nonce prompt
+    #[arg(long, value_name = "ENCODED", global = true)]
+    pub nonce: Option<String>,
+
+    /// Read the key from this account in the OS keyring; skips the key prompt
+    #[arg(
+        long,
+        value_name = "ACCOUNT",
+        global = true,
+        conflicts_with_all = ["key", "combined", "stdin"]
+    )]
+    pub keyring: Option<String>,
+
+    /// Keyring service used with --keyring and store-key
+    #[arg(long, value_name = "SERVICE", default_value = KEYRING_SERVICE, global = true)]
+    pub keyring_service: String,
+
+    /// Key and nonce in one `KEY:NONCE` string; skips both prompts
+    #[arg(
+        long,
+        value_name = "KEY:NONCE",
+        global = true,
+        conflicts_with_all = ["key", "nonce", "stdin"]
+    )]
+    pub combined: Option<String>,
+
+    /// Refuse an all-zero or repetitive nonce instead of only warning about it
+    #[arg(long, global = true)]
+    pub strict_nonce: bool,
+
+    /// What `encrypt` does when --nonce or --combined repeats a nonce already used with the key
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t,
+        global = true
+    )]
+    pub nonce_reuse: NonceReusePolicy,
+
+    /// Read the key and nonce from the first two lines of stdin instead of prompting
+    #[arg(long, global = true, conflicts_with_all = ["key", "nonce"])]
+    pub stdin: bool,
+
+    /// Fail if an interactive prompt gets no input within this many seconds
+    #[arg(long, value_name = "SECONDS", global = true)]
+    pub prompt_timeout: Option<u64>,
+
+    /// Give up after this many invalid key or nonce entries
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_ATTEMPTS, global = true)]
+    pub max_attempts: u32,
+
+    /// Show the entered key's fingerprint and ask for confirmation before using it
+    #[arg(long, global = true)]
+    pub confirm_fingerprint: bool,
+
+    /// Read the encrypted payload from this file instead of the compiled-in
+    /// constant (also settable via CLAUDE_TOKEN_PAYLOAD)
+    #[arg(long, value_name = "PATH", global = true)]
+    pub payload: Option<PathBuf>,
+
+    /// Apply a bundle file holding the nonce and ciphertext; only the key is prompted for
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["payload", "payload_b64", "nonce", "stdin"]
+    )]
+    pub bundle: Option<PathBuf>,
+
+    /// Merge this plaintext JSON file instead of decrypting a payload, for testing merges
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = [
+            "key", "nonce", "keyring", "combined", "stdin", "payload", "payload_b64", "bundle"
+        ]
+    )]
+    pub merge_file: Option<PathBuf>,
+
+    /// Base64-encoded encrypted payload given inline instead of as a file
+    #[arg(long, value_name = "BASE64", global = true, conflicts_with = "payload")]
+    pub payload_b64: Option<String>,
+
+    /// Target configuration file instead of the default: on Unix
+    /// $XDG_CONFIG_HOME/.claude/settings.json when XDG_CONFIG_HOME is an
+    /// absolute path, otherwise ~/.claude/settings.json
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config_path: Option<PathBuf>,
+
+    /// Print the merged configuration without writing it
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Allow a non-object configuration to replace the existing file
+    #[arg(long)]
+    pub force: bool,
+
+    /// Merge the payload at this JSON Pointer (e.g. /settings/env) instead of the root
+    #[arg(long, value_name = "POINTER")]
+    pub at: Option<String>,
+
+    /// Write timestamped backups into this directory instead of beside the config;
+    /// with `rollback`, restore the newest backup from it
+    #[arg(long, value_name = "PATH", global = true)]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Keep only the newest N backups in --backup-dir
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "backup_dir",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub keep_backups: Option<usize>,
+
+    /// Abort instead of merging when a valid config already exists
+    #[arg(long)]
+    pub no_clobber: bool,
+
+    /// How arrays present in both the existing and the new config are combined
+    #[arg(long, value_enum, default_value_t = MergeStrategy::ReplaceArrays)]
+    pub merge_strategy: MergeStrategy,
+
+    /// Delete a key from the existing config where the new config sets it to null
+    #[arg(long)]
+    pub null_deletes: bool,
+
+    /// Accept comments and trailing commas (JSON5) in an existing settings.json
+    #[arg(long)]
+    pub json5: bool,
+
+    /// How the existing config's format is chosen: by extension, or detected from its content
+    #[arg(long, value_enum, default_value_t = FormatSelection::Extension)]
+    pub config_format: FormatSelection,
+
+    /// Reject a decrypted config larger than this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_PLAINTEXT_SIZE)]
+    pub max_plaintext_size: usize,
+
+    /// Tries at writing the config while another process has it locked
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_WRITE_ATTEMPTS,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub write_attempts: u32,
+
+    /// Restrict the written config file to mode 0600 (Unix only)
+    #[arg(long)]
+    pub secure_perms: bool,
+
+    /// Substitute `${VAR}` references in the payload from the environment
+    #[arg(long)]
+    pub expand_env: bool,
+
+    /// Like --expand-env, but fail when a referenced variable is unset
+    #[arg(long)]
+    pub strict_env: bool,
+
+    /// Delete the executable after running (also enabled by SELF_DELETE=1)
+    #[arg(long)]
+    pub self_delete: bool,
+
+    /// Overwrite the executable with random data before self-deleting
+    #[arg(long)]
+    pub secure_delete: bool,
+
+    /// Skip confirmation prompts
+    #[arg(long)]
+    pub yes: bool,
+
+    /// How the final result is reported; `json` prints one JSON object on stdout
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, global = true)]
+    pub format: OutputFormat,
+
+    /// Print how long decryption, parsing, merging, and writing took
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Print the build token and exit without doing anything else
+    #[arg(long)]
+    pub print_app_token: bool,
+
+    /// Print the config file that would be modified and exit
+    #[arg(long)]
+    pub show_config_path: bool,
+
+    /// Suppress the banner and progress output; errors and a one-line
+    /// summary are still reported (also enabled by CLAUDE_TOKEN_QUIET=1)
+    #[arg(long, short, global = true)]
+    pub quiet: bool,
+}
+
+/// Subcommands; with none given the configuration is applied
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Check that the key and nonce decrypt the payload, without writing anything
+    Verify,
+    /// Print the decrypted configuration as JSON, without applying it
+    Decrypt,
+    /// Show the diff and overwritten settings an apply would produce, without writing;
+    /// exits 12 when nothing would change
+    Plan,
+    /// Restore the config file from its backup, undoing the last apply; with
+    /// --backup-dir the newest timestamped backup there is used
+    Rollback,
+    /// Check that the existing config file parses, without decrypting or writing anything
+    Check {
+        /// Also validate the config against the settings schema
+        #[arg(long)]
+        schema: bool,
+    },
+    /// Save the key from --key or the prompt in the OS keyring for later --keyring use
+    StoreKey {
+        /// Keyring account to store the key under
+        #[arg(value_name = "ACCOUNT")]
+        account: String,
+    },
+    /// Run an encrypt/decrypt round trip with throwaway keys to check this build
+    Selftest,
+    /// Encrypt a JSON file and print base64(nonce || ciphertext)
+    ///
+    /// Uses a random nonce unless --nonce or --combined supplies one, in
+    /// which case the nonce is checked for reuse with the key (see
+    /// --nonce-reuse).
+    Encrypt {
+        /// Plaintext JSON file; reads stdin when omitted
+        #[arg(value_name = "PATH")]
+        input: Option<PathBuf>,
+    },
+}
+
+impl Cli {
+    /// Returns the secret key from `--key` or `--keyring`, or falls back to the interactive prompt
+    pub fn secret_key(&self) -> Result<SecretKey> {
+        if let Some(combined) = &self.combined {
+            return parse_combined_credentials(combined).map(|(key, _)| key);
+        }
+        if let Some(account) = &self.keyring {
+            return get_secret_key_from_keyring(&self.keyring_service, account);
+        }
+
+        match &self.key {
+            Some(key) => decode_key_auto(key.trim()),
+            None => get_secret_key_with_options(&self.prompt_options()),
+        }
+    }
+
+    /// Returns the nonce from `--nonce`, or falls back to the interactive prompt
+    pub fn nonce(&self) -> Result<Zeroizing<[u8; NONCE_SIZE]>> {
+        match &self.nonce {
+            Some(nonce) => decode_nonce_auto(nonce.trim()),
+            None => get_nonce_with_options(&self.prompt_options()),
+        }
+    }
+
+    /// Returns the key and nonce from `--combined`, stdin in `--stdin` mode, or flags and prompts
+    ///
+    /// A weak nonce is warned about, or rejected with `--strict-nonce`.
+    pub fn credentials(&self) -> Result<(SecretKey, Zeroizing<[u8; NONCE_SIZE]>)> {
+        let (key, nonce) = if let Some(combined) = &self.combined {
+            parse_combined_credentials(combined)?
+        } else if self.stdin {
+            read_credentials_from(io::stdin().lock())?
+        } else {
+            (self.secret_key()?, self.nonce()?)
+        };
+
+        check_nonce_strength(&nonce, self.strict_nonce)?;
+        Ok((key, nonce))
+    }
+
+    /// Builds the interactive prompt options selected by the flags
+    pub fn prompt_options(&self) -> PromptOptions {
+        PromptOptions {
+            timeout: self.prompt_timeout.map(Duration::from_secs),
+            max_attempts: self.max_attempts,
+            confirm_fingerprint: self.confirm_fingerprint,
+        }
+    }
+
+    /// Returns the payload file from `--payload` or `CLAUDE_TOKEN_PAYLOAD`, if any
+    pub fn payload_path(&self) -> Option<PathBuf> {
+        self.payload
+            .clone()
+            .or_else(|| env::var_os(PAYLOAD_ENV_VAR).map(PathBuf::from))
+    }
+
+    /// Loads the encrypted payload, falling back to the compiled-in constant
+    ///
+    /// An inline `--payload-b64` takes precedence over a payload file.
+    pub fn encrypted_payload(&self) -> Result<Cow<'static, [u8]>> {
+        if let Some(payload_b64) = &self.payload_b64 {
+            return Ok(Cow::Owned(decode_payload_base64(payload_b64)?));
+        }
+
+        load_encrypted_payload(self.payload_path().as_deref())
+    }
+
+    /// Environment variable expansion mode selected by the flags
+    pub fn env_expansion(&self) -> EnvExpansion {
+        if self.strict_env {
+            EnvExpansion::Strict
+        } else if self.expand_env {
+            EnvExpansion::Lenient
+        } else {
+            EnvExpansion::Off
+        }
+    }
+
+    /// Builds the configuration apply options selected by the flags
+    pub fn apply_options(&self) -> ApplyOptions {
+        ApplyOptions {
+            config_path: self.config_path.clone(),
+            dry_run: self.dry_run,
+            force: self.force,
+            env_expansion: self.env_expansion(),
+            merge_at: self.at.clone(),
+            secure_permissions: self.secure_perms,
+            allow_json5: self.json5,
+            max_plaintext_size: self.max_plaintext_size,
+            format_selection: self.config_format,
+            no_clobber: self.no_clobber,
+            merge_strategy: self.merge_strategy,
+            null_deletes: self.null_deletes,
+            backup_dir: self.backup_dir.clone(),
+            keep_backups: self.keep_backups,
+            write_attempts: self.write_attempts,
+            ..ApplyOptions::default()
+        }
+    }
+
+    /// Whether results are reported as JSON
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    /// Whether non-error output should be suppressed
+    pub fn is_quiet(&self) -> bool {
+        self.quiet || env::var(QUIET_ENV_VAR).is_ok_and(|value| is_truthy(&value))
+    }
+
+    /// Builds the logger for this run
+    ///
+    /// Progress is logged at `info` unless `RUST_LOG` says otherwise. Quiet
+    /// mode overrides both and only lets errors through.
+    pub fn logger(&self) -> env_logger::Builder {
+        let mut builder =
+            env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+        builder.format_timestamp(None).format_target(false);
+        if self.is_quiet() {
+            builder.filter_level(LevelFilter::Error);
+        }
+        builder
+    }
+
+    /// Whether the executable should delete itself after running
+    pub fn should_self_delete(&self) -> bool {
+        self_deletion_requested(
+            self.self_delete,
+            env::var(SELF_DELETE_ENV_VAR).ok().as_deref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KEY_SIZE;
+    use crate::TokenProviderError;
+
+    const KEY: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+    const NONCE: &str = "AgICAgICAgICAgIC";
+
+    #[test]
+    fn test_no_flags_keeps_interactive_defaults() {
+        let cli = Cli::try_parse_from(["claude-token-provider"]).unwrap();
+
+        assert!(cli.key.is_none());
+        assert!(cli.nonce.is_none());
+        assert!(cli.payload.is_none());
+        assert!(!cli.yes);
+        assert!(!cli.self_delete);
+        assert!(!cli.quiet);
+        assert_eq!(cli.prompt_options(), PromptOptions::default());
+        assert_eq!(cli.apply_options(), ApplyOptions::default());
+    }
+
+    #[test]
+    fn test_key_and_nonce_flags_skip_prompts() {
+        let cli =
+            Cli::try_parse_from(["claude-token-provider", "--key", KEY, "--nonce", NONCE]).unwrap();
+
+        assert_eq!(cli.secret_key().unwrap().expose(), &[1u8; KEY_SIZE]);
+        assert_eq!(*cli.nonce().unwrap(), [2u8; NONCE_SIZE]);
+    }
+
+    #[test]
+    fn test_invalid_key_flag_errors_without_prompting() {
+        let cli = Cli::try_parse_from(["claude-token-provider", "--key", "dGVzdA=="]).unwrap();
+
+        assert!(matches!(
+            cli.secret_key(),
+            Err(TokenProviderError::InvalidKeyLength { actual: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_payload_b64_flag_is_decoded() {
+        let cli = Cli::try_parse_from(["claude-token-provider", "--payload-b64", "AQID"]).unwrap();
+
+        assert_eq!(cli.encrypted_payload().unwrap().as_ref(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_flags_map_to_options() {
+        let cli = Cli::try_parse_from([
+            "claude-token-provider",
+            "--config-path",
+            "/tmp/profile/settings.json",
+            "--dry-run",
+            "--force",
+            "--secure-perms",
+            "--at",
+            "/settings/env",
+            "--self-delete",
+            "--yes",
+            "--prompt-timeout",
+            "30",
+            "--max-attempts",
+            "5",
+            "--merge-strategy",
+            "concat-unique-arrays",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cli.prompt_options(),
+            PromptOptions {
+                timeout: Some(Duration::from_secs(30)),
+                max_attempts: 5,
+                confirm_fingerprint: false,
+            }
+        );
+        let options = cli.apply_options();
+        assert_eq!(
+            options.config_path,
+            Some(PathBuf::from("/tmp/profile/settings.json"))
+        );
+        assert!(options.dry_run);
+        assert!(options.force);
+        assert!(options.secure_permissions);
+        assert_eq!(options.merge_at.as_deref(), Some("/settings/env"));
+        assert_eq!(options.merge_strategy, MergeStrategy::ConcatUniqueArrays);
+        assert!(options.backup);
+        assert!(cli.should_self_delete());
+        assert!(cli.yes);
+    }
+
+    #[test]
+    fn test_quiet_logger_only_emits_errors() {
+        use log::{Level, Log, Metadata};
+
+        let cli = Cli::try_parse_from(["claude-token-provider", "--quiet"]).unwrap();
+        let logger = cli.logger().build();
+        let enabled = |level| logger.enabled(&Metadata::builder().level(level).build());
+
+        assert!(cli.is_quiet());
+        assert!(!enabled(Level::Info));
+        assert!(!enabled(Level::Warn));
+        assert!(enabled(Level::Error));
+    }
+
+    #[test]
+    fn test_env_expansion_flags() {
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from(
+                std::iter::once("claude-token-provider").chain(args.iter().copied()),
+            )
+            .unwrap()
+            .env_expansion()
+        };
+
+        assert_eq!(parse(&[]), EnvExpansion::Off);
+        assert_eq!(parse(&["--expand-env"]), EnvExpansion::Lenient);
+        assert_eq!(parse(&["--strict-env"]), EnvExpansion::Strict);
+    }
+
+    #[test]
+    fn test_verify_subcommand_accepts_credentials() {
+        let cli = Cli::try_parse_from([
+            "claude-token-provider",
+            "verify",
+            "--key",
+            "MDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDA=",
+            "--nonce",
+            "MDAwMDAwMDAwMDAw",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.command, Some(Command::Verify));
+        assert!(cli.secret_key().is_ok());
+        assert!(cli.nonce().is_ok());
+    }
+
+    #[test]
+    fn test_stdin_conflicts_with_key_flag() {
+        let result = Cli::try_parse_from(["claude-token-provider", "--stdin", "--key", KEY]);
+
+        assert!(result.is_err());
+        assert!(
+            Cli::try_parse_from(["claude-token-provider", "--stdin"])
+                .unwrap()
+                .stdin
+        );
+    }
+
+    #[test]
+    fn test_merge_file_conflicts_with_decrypt_options() {
+        for flag in [
+            ["--key", KEY],
+            ["--payload", "payload.bin"],
+            ["--bundle", "a.bundle"],
+        ] {
+            let mut args = vec!["claude-token-provider", "--merge-file", "local.json"];
+            args.extend(flag);
+            assert!(Cli::try_parse_from(args).is_err(), "{:?}", flag);
+        }
+
+        let cli =
+            Cli::try_parse_from(["claude-token-provider", "--merge-file", "local.json"]).unwrap();
+        assert_eq!(cli.merge_file, Some(PathBuf::from("local.json")));
+    }
+
+    #[test]
+    fn test_encrypt_subcommand_takes_optional_input() {
+        let cli = Cli::try_parse_from(["claude-token-provider", "encrypt", "config.json"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::Encrypt {
+                input: Some(PathBuf::from("config.json"))
+            })
+        );
+
+        let cli = Cli::try_parse_from(["claude-token-provider", "encrypt"]).unwrap();
+        assert_eq!(cli.command, Some(Command::Encrypt { input: None }));
+        assert_eq!(cli.nonce_reuse, NonceReusePolicy::Refuse);
+
+        let cli =
+            Cli::try_parse_from(["claude-token-provider", "encrypt", "--nonce-reuse", "warn"])
+                .unwrap();
+        assert_eq!(cli.nonce_reuse, NonceReusePolicy::Warn);
+    }
+
+    #[test]
+    fn test_unknown_flag_rejected() {
+        assert!(Cli::try_parse_from(["claude-token-provider", "--bogus"]).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use serde_json::Value;
+
+/// Label used for the document root in diff output
+const ROOT_LABEL: &str = "(root)";
+
+/// Renders a human-readable diff between two JSON values
+///
+/// Each changed leaf is printed on its own line with its dot-separated path:
+/// added keys are prefixed with `+`, removed keys with `-`, and modified
+/// values appear as a `-` line for the old value followed by a `+` line for
+/// the new one. Objects are compared key by key; arrays and primitives are
+/// compared as whole values. Returns an empty string when nothing changed.
+pub fn render_diff(old: &Value, new: &Value) -> String {
+    let mut lines = Vec::new();
+    diff_values(old, new, "", true, &mut lines);
+    lines.join("\n")
+}
+
+/// Lists the paths that differ between two JSON values, without their values
+///
+/// Added paths are prefixed with `+`, removed ones with `-`, and modified
+/// ones with `~`, in the same order as [`render_diff`]. Safe to log, since
+/// the values may contain tokens.
+pub fn render_changed_paths(old: &Value, new: &Value) -> String {
+    let mut lines = Vec::new();
+    diff_values(old, new, "", false, &mut lines);
+    lines.join("\n")
+}
+
+/// Recursively compares two values, appending diff lines for each change
+fn diff_values(old: &Value, new: &Value, path: &str, show_values: bool, lines: &mut Vec<String>) {
+    let line = |sign: char, path: &str, value: &Value| {
+        if show_values {
+            format!("{} {}: {}", sign, path, value)
+        } else {
+            format!("{} {}", sign, path)
+        }
+    };
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let child_path = join_path(path, key);
+                match new_map.get(key) {
+                    Some(new_value) => {
+                        diff_values(old_value, new_value, &child_path, show_values, lines)
+                    }
+                    None => lines.push(line('-', &child_path, old_value)),
+                }
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    lines.push(line('+', &join_path(path, key), new_value));
+                }
+            }
+        }
+        (old, new) if old != new => {
+            let label = if path.is_empty() { ROOT_LABEL } else { path };
+            if show_values {
+                lines.push(line('-', label, old));
+                lines.push(line('+', label, new));
+            } else {
+                lines.push(format!("~ {}", label));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Counts the keys present in `new` but not in `old`
+///
+/// Keys are counted where they first appear, matching the `+` lines of
+/// [`render_diff`]: a newly added object counts once, not once per nested key.
+pub fn count_added_keys(old: &Value, new: &Value) -> usize {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => new_map
+            .iter()
+            .map(|(key, new_value)| match old_map.get(key) {
+                Some(old_value) => count_added_keys(old_value, new_value),
+                None => 1,
+            })
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Appends a key to a dot-separated JSON path
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_added_keys() {
+        let old = json!({"settings": {"retries": 3}});
+        let new = json!({"settings": {"retries": 3, "timeout_ms": 5000}, "version": 2});
+
+        assert_eq!(
+            render_diff(&old, &new),
+            "+ settings.timeout_ms: 5000\n+ version: 2"
+        );
+    }
+
+    #[test]
+    fn test_diff_removed_keys() {
+        let old = json!({"settings": {"retries": 3, "legacy": {"flag": true}}});
+        let new = json!({"settings": {"retries": 3}});
+
+        assert_eq!(
+            render_diff(&old, &new),
+            "- settings.legacy: {\"flag\":true}"
+        );
+    }
+
+    #[test]
+    fn test_diff_modified_keys() {
+        let old = json!({"env": {"nested": {"level": 1}}, "list": [1, 2]});
+        let new = json!({"env": {"nested": {"level": 2}}, "list": [1, 2, 3]});
+
+        assert_eq!(
+            render_diff(&old, &new),
+            "- env.nested.level: 1\n+ env.nested.level: 2\n- list: [1,2]\n+ list: [1,2,3]"
+        );
+    }
+
+    #[test]
+    fn test_diff_identical_is_empty() {
+        let value = json!({"settings": {"retries": 3}});
+
+        assert_eq!(render_diff(&value, &value), "");
+    }
+
+    #[test]
+    fn test_diff_root_type_change() {
+        assert_eq!(
+            render_diff(&json!([1]), &json!({})),
+            "- (root): [1]\n+ (root): {}"
+        );
+    }
+
+    #[test]
+    fn test_changed_paths_omit_values() {
+        let old = json!({"env": {"TOKEN": "sk-old", "gone": 1}, "model": "sonnet"});
+        let new = json!({"env": {"TOKEN": "sk-new"}, "model": "sonnet", "theme": "dark"});
+
+        assert_eq!(
+            render_changed_paths(&old, &new),
+            "~ env.TOKEN\n- env.gone\n+ theme"
+        );
+        assert_eq!(render_changed_paths(&old, &old), "");
+    }
+
+    #[test]
+    fn test_count_added_keys() {
+        let old = json!({"a": 1, "nested": {"x": 1}});
+        let new = json!({"a": 2, "nested": {"x": 1, "y": 2}, "fresh": {"p": 1, "q": 2}});
+
+        assert_eq!(count_added_keys(&old, &new), 2);
+        assert_eq!(count_added_keys(&json!({}), &new), 3);
+        assert_eq!(count_added_keys(&new, &new), 0);
+    }
+}
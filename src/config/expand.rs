@@ -0,0 +1,186 @@
+//! Environment variable expansion in config values
+//!
+//! String values may reference process environment variables as `${VAR}`.
+//! Only string values are expanded; object keys and other types are left
+//! unchanged, and a bare `$VAR` without braces is not treated as a reference.
+
+use serde_json::Value;
+use std::env;
+
+use crate::{Result, TokenProviderError};
+
+/// How `${VAR}` references are handled when applying a config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnvExpansion {
+    /// Leave `${VAR}` references as literal text
+    #[default]
+    Off,
+    /// Expand set variables and leave unknown references untouched
+    Lenient,
+    /// Expand set variables and fail on any unknown reference
+    Strict,
+}
+
+/// Expands `${VAR}` references in every string value from the process environment
+///
+/// With `strict` set, a reference to an unset variable is an error;
+/// otherwise it is left as-is.
+pub fn expand_env_vars(value: &mut Value, strict: bool) -> Result<()> {
+    expand_env_vars_with(value, strict, &|name| env::var(name).ok())
+}
+
+/// Expands `${VAR}` references using `lookup` instead of the process environment
+pub fn expand_env_vars_with(
+    value: &mut Value,
+    strict: bool,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<()> {
+    expand_value(value, "", strict, lookup)
+}
+
+/// Recursively expands string values, tracking the JSON path for errors
+fn expand_value(
+    value: &mut Value,
+    path: &str,
+    strict: bool,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<()> {
+    match value {
+        Value::String(text) => {
+            if let Some(expanded) = expand_string(text, path, strict, lookup)? {
+                *text = expanded;
+            }
+            Ok(())
+        }
+        Value::Object(map) => map.iter_mut().try_for_each(|(key, child)| {
+            let child_path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", path, key)
+            };
+            expand_value(child, &child_path, strict, lookup)
+        }),
+        Value::Array(items) => items.iter_mut().enumerate().try_for_each(|(index, child)| {
+            expand_value(child, &format!("{}[{}]", path, index), strict, lookup)
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Expands the references in one string, returning `None` if it has none
+fn expand_string(
+    text: &str,
+    path: &str,
+    strict: bool,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<Option<String>> {
+    if !text.contains("${") {
+        return Ok(None);
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+
+        // An unterminated `${` is kept as literal text
+        let Some(end) = after.find('}') else {
+            output.push_str(&rest[start..]);
+            return Ok(Some(output));
+        };
+
+        let name = &after[..end];
+        match lookup(name) {
+            Some(replacement) if !name.is_empty() => output.push_str(&replacement),
+            _ if strict => {
+                return Err(TokenProviderError::MissingEnvVar {
+                    name: name.to_string(),
+                    path: path.to_string(),
+                })
+            }
+            _ => output.push_str(&rest[start..start + 2 + end + 1]),
+        }
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+
+    Ok(Some(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lookup(name: &str) -> Option<String> {
+        (name == "CLAUDE_HOST").then(|| "https://api.example.com".to_string())
+    }
+
+    #[test]
+    fn test_expands_matched_variable() {
+        let mut config = json!({
+            "api_base": "${CLAUDE_HOST}/v1",
+            "hosts": ["${CLAUDE_HOST}"],
+            "retries": 3
+        });
+
+        expand_env_vars_with(&mut config, true, &lookup).unwrap();
+
+        assert_eq!(
+            config,
+            json!({
+                "api_base": "https://api.example.com/v1",
+                "hosts": ["https://api.example.com"],
+                "retries": 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_missing_variable_lenient_left_untouched() {
+        let mut config = json!({"env": {"TOKEN": "${MISSING_VAR}", "HOST": "${CLAUDE_HOST}"}});
+
+        expand_env_vars_with(&mut config, false, &lookup).unwrap();
+
+        assert_eq!(
+            config,
+            json!({"env": {"TOKEN": "${MISSING_VAR}", "HOST": "https://api.example.com"}})
+        );
+    }
+
+    #[test]
+    fn test_missing_variable_strict_errors_with_path() {
+        let mut config = json!({"env": {"TOKEN": "${MISSING_VAR}"}});
+
+        match expand_env_vars_with(&mut config, true, &lookup) {
+            Err(TokenProviderError::MissingEnvVar { name, path }) => {
+                assert_eq!(name, "MISSING_VAR");
+                assert_eq!(path, "env.TOKEN");
+            }
+            other => panic!("expected MissingEnvVar, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_string_without_placeholders_unchanged() {
+        let mut config = json!({"command": "echo $HOME", "partial": "${UNTERMINATED"});
+        let original = config.clone();
+
+        expand_env_vars_with(&mut config, true, &lookup).unwrap();
+
+        assert_eq!(config, original);
+    }
+
+    #[test]
+    fn test_expands_from_process_environment() {
+        env::set_var("CLAUDE_TOKEN_TEST_EXPAND_HOST", "localhost");
+        let mut config = json!({"api_base": "http://${CLAUDE_TOKEN_TEST_EXPAND_HOST}:8080"});
+
+        let result = expand_env_vars(&mut config, true);
+        env::remove_var("CLAUDE_TOKEN_TEST_EXPAND_HOST");
+
+        result.unwrap();
+        assert_eq!(config, json!({"api_base": "http://localhost:8080"}));
+    }
+}
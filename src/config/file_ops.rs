@@ -1,89 +1,1189 @@
 use dirs;
-use serde_json::{to_string_pretty, Value};
-use std::fs;
+use log::{info, warn};
+use serde_json::{Map, Value};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use super::merger::deep_merge_json;
-use crate::{Result, TokenProviderError};
+use super::diff::{count_added_keys, render_changed_paths, render_diff};
+use super::expand::{expand_env_vars, EnvExpansion};
+use super::format::{detect_format, ConfigFormat, FormatSelection};
+use super::merger::{
+    deep_merge_json_reporting_with_options, deep_merge_json_with_options, json_type_name,
+    MergeConflict, MergeOptions, MergeStrategy,
+};
+use super::migrate::{config_version, MigrationRegistry};
+use super::pointer::{check_pointer_parents, nest_at, parse_pointer};
+use super::types::AppConfig;
+use crate::{interrupt, Result, TokenProviderError};
+
+#[cfg(feature = "tokio")]
+pub mod async_ops;
 
 /// Default configuration directory and file paths
 const CONFIG_DIR: &str = ".claude";
 const CONFIG_FILE: &str = "settings.json";
 
+/// Environment variable naming the XDG base config directory on Unix
+pub const XDG_CONFIG_HOME_VAR: &str = "XDG_CONFIG_HOME";
+
 /// Gets the target configuration file path
+///
+/// On Unix, `$XDG_CONFIG_HOME/.claude/settings.json` is used when
+/// `XDG_CONFIG_HOME` is set to an absolute path. Otherwise, and on other
+/// platforms, the path is `~/.claude/settings.json`.
 pub fn get_config_path() -> Result<PathBuf> {
-    let home_dir = dirs::home_dir().ok_or_else(|| {
-        TokenProviderError::IoError(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Home directory not found",
-        ))
-    })?;
+    let xdg_config_home = if cfg!(unix) {
+        std::env::var_os(XDG_CONFIG_HOME_VAR)
+    } else {
+        None
+    };
+
+    config_path_for(dirs::home_dir(), xdg_config_home)
+}
+
+/// Resolves the config path from the home directory and `XDG_CONFIG_HOME`
+///
+/// Per the XDG spec, an empty or relative `XDG_CONFIG_HOME` is ignored.
+fn config_path_for(
+    home_dir: Option<PathBuf>,
+    xdg_config_home: Option<OsString>,
+) -> Result<PathBuf> {
+    let xdg_dir = xdg_config_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute());
+    if let Some(dir) = xdg_dir {
+        return Ok(dir.join(CONFIG_DIR).join(CONFIG_FILE));
+    }
+
+    let home_dir = home_dir.ok_or(TokenProviderError::HomeDirUnavailable)?;
 
     Ok(home_dir.join(CONFIG_DIR).join(CONFIG_FILE))
 }
 
+/// Gets the target configuration file path, honoring an explicit override
+///
+/// Falls back to the default from [`get_config_path`] when no override is
+/// given: `$XDG_CONFIG_HOME/.claude/settings.json` on Unix when
+/// `XDG_CONFIG_HOME` is an absolute path, and `~/.claude/settings.json`
+/// otherwise.
+pub fn get_config_path_with_override(path_override: Option<PathBuf>) -> Result<PathBuf> {
+    match path_override {
+        Some(path) => Ok(path),
+        None => get_config_path(),
+    }
+}
+
 /// Ensures the configuration directory exists
 pub fn ensure_config_dir(config_path: &Path) -> Result<()> {
     if let Some(parent) = config_path.parent() {
-        fs::create_dir_all(parent)?;
+        fs::create_dir_all(parent).map_err(|e| TokenProviderError::file_io(parent, e))?;
     }
     Ok(())
 }
 
-/// Reads existing configuration file if it exists and is valid JSON
+/// Reads existing configuration file if it exists and is valid
+///
+/// The file is parsed as YAML or JSON depending on its extension.
 pub fn read_existing_config(config_path: &Path) -> Result<Option<Value>> {
+    read_existing_config_with(config_path, false)
+}
+
+/// Like [`read_existing_config`], optionally accepting JSON5 in a JSON file
+///
+/// With `allow_json5` set, a `.json` file that is not strict JSON is parsed
+/// again as JSON5, so comments and trailing commas do not cause a
+/// hand-edited file to be replaced. The comments are not kept when the
+/// merged config is written back as standard JSON.
+pub fn read_existing_config_with(config_path: &Path, allow_json5: bool) -> Result<Option<Value>> {
+    let format = ConfigFormat::from_path(config_path);
+    read_existing_config_collecting(config_path, format, allow_json5, &mut Vec::new())
+}
+
+/// Logs a warning and records it for the end-of-run summary
+fn record_warning(warnings: &mut Vec<String>, message: String) {
+    warn!("{}", message);
+    warnings.push(message);
+}
+
+/// Chooses the format an existing config is read and written in
+///
+/// With [`FormatSelection::Auto`] the file's content decides, falling back
+/// to JSON with a warning when it matches no format. A missing file, or
+/// [`FormatSelection::Extension`], uses the file extension.
+fn resolve_format(
+    config_path: &Path,
+    selection: FormatSelection,
+    warnings: &mut Vec<String>,
+) -> Result<ConfigFormat> {
+    if selection == FormatSelection::Extension || !config_path.exists() {
+        return Ok(ConfigFormat::from_path(config_path));
+    }
+
+    let content =
+        fs::read_to_string(config_path).map_err(|e| TokenProviderError::file_io(config_path, e))?;
+    Ok(format_from_content(config_path, &content, warnings))
+}
+
+/// Detects the format of an existing config's content, falling back to JSON
+fn format_from_content(
+    config_path: &Path,
+    content: &str,
+    warnings: &mut Vec<String>,
+) -> ConfigFormat {
+    match detect_format(content) {
+        Some(format) => {
+            info!("Detected {} in {}", format, config_path.display());
+            format
+        }
+        None => {
+            record_warning(
+                warnings,
+                format!(
+                    "Could not detect the format of {}; treating it as JSON",
+                    config_path.display()
+                ),
+            );
+            ConfigFormat::Json
+        }
+    }
+}
+
+/// Reads the existing config in `format`, appending any warnings to `warnings`
+fn read_existing_config_collecting(
+    config_path: &Path,
+    format: ConfigFormat,
+    allow_json5: bool,
+    warnings: &mut Vec<String>,
+) -> Result<Option<Value>> {
     if !config_path.exists() {
         return Ok(None);
     }
 
-    let content = fs::read_to_string(config_path)?;
+    let content =
+        fs::read_to_string(config_path).map_err(|e| TokenProviderError::file_io(config_path, e))?;
+    Ok(parse_existing_config(
+        config_path,
+        &content,
+        format,
+        allow_json5,
+        warnings,
+    ))
+}
+
+/// Parses existing config content, or returns `None` if it should be replaced
+fn parse_existing_config(
+    config_path: &Path,
+    content: &str,
+    format: ConfigFormat,
+    allow_json5: bool,
+    warnings: &mut Vec<String>,
+) -> Option<Value> {
+    // Try to parse in the file's format, falling back to JSON5 if allowed
+    let parsed = match format.parse(content) {
+        Err(_) if allow_json5 && format == ConfigFormat::Json => {
+            let parsed = json5::from_str::<Value>(content)
+                .map_err(|e| TokenProviderError::ValidationError(e.to_string()));
+            if parsed.is_ok() {
+                record_warning(
+                    warnings,
+                    format!(
+                        "{} parsed as JSON5; comments will not be kept when it is rewritten",
+                        config_path.display()
+                    ),
+                );
+            }
+            parsed
+        }
+        parsed => parsed,
+    };
 
-    // Try to parse as JSON
-    match serde_json::from_str::<Value>(&content) {
-        Ok(json) => Ok(Some(json)),
+    match parsed {
+        Ok(config) => Some(config),
         Err(_) => {
-            // File exists but is not valid JSON - will be overwritten
-            eprintln!("Warning: Existing config file is not valid JSON and will be replaced");
-            Ok(None)
+            // File exists but is not valid - will be overwritten
+            record_warning(
+                warnings,
+                format!(
+                    "Existing config file is not valid {} and will be replaced",
+                    format
+                ),
+            );
+            None
+        }
+    }
+}
+
+/// Checks that an existing config file parses, without modifying it
+///
+/// Unlike [`read_existing_config`], a missing or unparsable file is an
+/// error rather than something to replace. With `validate_schema` set the
+/// parsed config must also satisfy [`AppConfig`]. Returns the parsed config.
+pub fn check_config(config_path: &Path, validate_schema: bool) -> Result<Value> {
+    if !config_path.is_file() {
+        return Err(TokenProviderError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No configuration file found at {}", config_path.display()),
+        )));
+    }
+
+    let content =
+        fs::read_to_string(config_path).map_err(|e| TokenProviderError::file_io(config_path, e))?;
+    let config = ConfigFormat::from_path(config_path).parse(&content)?;
+
+    if validate_schema {
+        AppConfig::validate(&config)?;
+    }
+
+    Ok(config)
+}
+
+/// Returns a sibling path with `suffix` appended to the file name
+fn sibling_path(config_path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = config_path
+        .file_name()
+        .map(OsString::from)
+        .unwrap_or_default();
+    file_name.push(suffix);
+    config_path.with_file_name(file_name)
+}
+
+/// Returns the sibling temp file path used for atomic writes (e.g. `settings.json.tmp`)
+fn temp_path_for(config_path: &Path) -> PathBuf {
+    sibling_path(config_path, ".tmp")
+}
+
+/// Returns the backup path for a configuration file (e.g. `settings.json.bak`)
+pub fn backup_path_for(config_path: &Path) -> PathBuf {
+    sibling_path(config_path, ".bak")
+}
+
+/// Copies the current configuration file to its backup path
+///
+/// Any previous backup is overwritten. Returns the backup path.
+pub fn backup_config(config_path: &Path) -> Result<PathBuf> {
+    let backup_path = backup_path_for(config_path);
+    fs::copy(config_path, &backup_path)
+        .map_err(|e| TokenProviderError::file_io(&backup_path, e))?;
+    Ok(backup_path)
+}
+
+/// Copies the current configuration file into `backup_dir` under a timestamped name
+///
+/// The backup is named `<stem>-<UTC time>.<ext>`, for example
+/// `settings-20240501T120000Z.json`, so earlier backups are kept. The
+/// timestamp is ISO 8601 basic format, which has no `:` and so is a valid
+/// file name on Windows too, and sorts chronologically as text. The
+/// directory is created if needed. Returns the backup path.
+pub fn backup_config_in(config_path: &Path, backup_dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(backup_dir).map_err(|e| TokenProviderError::file_io(backup_dir, e))?;
+
+    let timestamp = basic_utc_timestamp(SystemTime::now());
+    let (stem, extension) = backup_name_parts(config_path);
+    let backup_path = backup_dir.join(format!("{}-{}{}", stem, timestamp, extension));
+    fs::copy(config_path, &backup_path)
+        .map_err(|e| TokenProviderError::file_io(&backup_path, e))?;
+    Ok(backup_path)
+}
+
+/// Splits a config path into the stem and `.ext` suffix used for backup names
+fn backup_name_parts(config_path: &Path) -> (String, String) {
+    let stem = config_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = config_path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (stem, extension)
+}
+
+/// Returns the timestamp of a [`backup_config_in`] file name, or `None` if it is not one
+fn backup_timestamp<'a>(file_name: &'a str, stem: &str, extension: &str) -> Option<&'a str> {
+    let timestamp = file_name
+        .strip_prefix(stem)?
+        .strip_prefix('-')?
+        .strip_suffix(extension)?;
+
+    // `YYYYMMDDTHHMMSSZ`
+    let well_formed = timestamp.len() == 16
+        && timestamp
+            .bytes()
+            .enumerate()
+            .all(|(index, byte)| match index {
+                8 => byte == b'T',
+                15 => byte == b'Z',
+                _ => byte.is_ascii_digit(),
+            });
+    well_formed.then_some(timestamp)
+}
+
+/// Deletes the oldest timestamped backups of `config_path` so only `keep` remain
+///
+/// Only files named like those written by [`backup_config_in`] are
+/// considered, ordered by the timestamp in their name; anything else in
+/// `backup_dir` is left alone. Returns the paths that were removed.
+pub fn prune_backups(config_path: &Path, backup_dir: &Path, keep: usize) -> Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    for path in timestamped_backups(config_path, backup_dir)?
+        .into_iter()
+        .skip(keep)
+    {
+        fs::remove_file(&path).map_err(|e| TokenProviderError::file_io(&path, e))?;
+        removed.push(path);
+    }
+    Ok(removed)
+}
+
+/// Lists the [`backup_config_in`] backups of `config_path` in `backup_dir`, newest first
+fn timestamped_backups(config_path: &Path, backup_dir: &Path) -> Result<Vec<PathBuf>> {
+    let (stem, extension) = backup_name_parts(config_path);
+
+    let mut backups = Vec::new();
+    let file_io = |e| TokenProviderError::file_io(backup_dir, e);
+    for entry in fs::read_dir(backup_dir).map_err(file_io)? {
+        let entry = entry.map_err(file_io)?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(timestamp) = backup_timestamp(&file_name, &stem, &extension) {
+            backups.push((timestamp.to_string(), entry.path()));
         }
     }
+
+    // Newest first; the fixed-width timestamps sort chronologically as text
+    backups.sort_by(|a, b| b.0.cmp(&a.0));
+    Ok(backups.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Formats a time as an ISO 8601 basic UTC timestamp, e.g. `20240501T120000Z`
+fn basic_utc_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, time_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3_600,
+        time_of_day % 3_600 / 60,
+        time_of_day % 60
+    )
+}
+
+/// Restores a configuration file from its backup, undoing the last apply
+///
+/// The backup is parsed in the config file's format first; an invalid
+/// backup is an error and the current file is left untouched. The copy goes
+/// through a temp file and rename, like [`write_config_as`]. Returns the
+/// backup path that was restored.
+pub fn restore_backup(config_path: &Path) -> Result<PathBuf> {
+    restore_backup_from(config_path, None)
+}
+
+/// Like [`restore_backup`], restoring the newest timestamped backup in `backup_dir` if given
+///
+/// Without a directory the sibling `.bak` file is used, matching where
+/// [`apply_config_update`] put the backup.
+pub fn restore_backup_from(config_path: &Path, backup_dir: Option<&Path>) -> Result<PathBuf> {
+    let backup_path = match backup_dir {
+        Some(backup_dir) => {
+            let newest = if backup_dir.is_dir() {
+                timestamped_backups(config_path, backup_dir)?
+                    .into_iter()
+                    .next()
+            } else {
+                None
+            };
+            newest.ok_or_else(|| {
+                TokenProviderError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!(
+                        "No backup of {} found in {}",
+                        config_path.display(),
+                        backup_dir.display()
+                    ),
+                ))
+            })?
+        }
+        None => backup_path_for(config_path),
+    };
+    if !backup_path.exists() {
+        return Err(TokenProviderError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No backup found at {}", backup_path.display()),
+        )));
+    }
+
+    let content = fs::read_to_string(&backup_path)
+        .map_err(|e| TokenProviderError::file_io(&backup_path, e))?;
+    ConfigFormat::from_path(config_path).parse(&content)?;
+
+    write_atomically(config_path, content.as_bytes())?;
+
+    Ok(backup_path)
 }
 
 /// Writes configuration to file with pretty formatting
+///
+/// The output format follows the file extension, so an existing YAML
+/// config is written back as YAML.
 pub fn write_config(config_path: &Path, config: &Value) -> Result<()> {
-    let pretty_json = to_string_pretty(config)?;
-    fs::write(config_path, pretty_json)?;
+    write_config_as(config_path, config, ConfigFormat::from_path(config_path))
+}
+
+/// Writes configuration to file in an explicitly chosen format
+///
+/// The content is written to a sibling temp file first and then renamed
+/// into place. Rename is atomic on the same filesystem, so a process killed
+/// mid-write never leaves a truncated config behind. The temp file is
+/// removed if either step fails.
+pub fn write_config_as(config_path: &Path, config: &Value, format: ConfigFormat) -> Result<()> {
+    let content = format.serialize(config)?;
+    write_atomically(config_path, content.as_bytes())
+}
+
+/// Like [`write_config`], making up to `attempts` tries at the write
+///
+/// An access-denied or sharing-violation error is retried with exponential
+/// backoff starting at 50 ms, since on Windows it usually means another
+/// process has the file open for a moment. Other errors fail immediately.
+/// [`write_config`] uses [`DEFAULT_WRITE_ATTEMPTS`].
+pub fn write_config_with_attempts(config_path: &Path, config: &Value, attempts: u32) -> Result<()> {
+    let content = ConfigFormat::from_path(config_path).serialize(config)?;
+    write_atomically_with_attempts(config_path, content.as_bytes(), attempts)
+}
+
+/// A writer whose contents can be flushed to stable storage
+trait SyncWrite: Write {
+    fn sync_all(&mut self) -> io::Result<()>;
+}
+
+impl SyncWrite for File {
+    fn sync_all(&mut self) -> io::Result<()> {
+        File::sync_all(self)
+    }
+}
+
+/// Writes all of `content` and waits until it reaches stable storage
+fn write_and_sync(file: &mut impl SyncWrite, content: &[u8]) -> io::Result<()> {
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+/// Permissions for a temp file that is renamed over a file with `existing` metadata
+///
+/// The rename replaces the file, so its mode would otherwise be lost to the
+/// umask default. A new file is made 0600 on Unix, since it may hold tokens.
+fn replacement_permissions(existing: Option<fs::Metadata>) -> Option<fs::Permissions> {
+    match existing {
+        Some(metadata) => Some(metadata.permissions()),
+        #[cfg(unix)]
+        None => {
+            use std::os::unix::fs::PermissionsExt;
+            Some(fs::Permissions::from_mode(0o600))
+        }
+        #[cfg(not(unix))]
+        None => None,
+    }
+}
+
+/// Creates the temp file for an atomic write of `path`, keeping its permissions
+fn create_temp_file(temp_path: &Path, path: &Path) -> io::Result<File> {
+    let permissions = replacement_permissions(fs::metadata(path).ok());
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options.open(temp_path)?;
+    // Also covers a stale temp file left with another mode
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    Ok(file)
+}
+
+/// Default number of attempts at writing a config before giving up
+pub const DEFAULT_WRITE_ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry of a write; it doubles on each further retry
+const WRITE_RETRY_BASE_DELAY: Duration = Duration::from_millis(50);
+
+/// Whether a failed write is worth retrying
+///
+/// Antivirus and indexers on Windows briefly open freshly written files,
+/// which surfaces as access denied or a sharing/lock violation. Elsewhere
+/// a permission error is permanent, so nothing is retried.
+fn is_transient_write_error(error: &io::Error) -> bool {
+    cfg!(windows)
+        && (error.kind() == io::ErrorKind::PermissionDenied
+            // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+            || matches!(error.raw_os_error(), Some(32) | Some(33)))
+}
+
+/// Runs `op` up to `attempts` times, sleeping with exponential backoff
+/// between tries while it fails with an error `is_transient` accepts
+///
+/// Any other error is returned at once. `attempts` of 0 is treated as 1.
+fn retry_transient<T>(
+    attempts: u32,
+    mut sleep: impl FnMut(Duration),
+    is_transient: impl Fn(&io::Error) -> bool,
+    mut op: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut delay = WRITE_RETRY_BASE_DELAY;
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e) if attempt < attempts && is_transient(&e) => {
+                warn!(
+                    "Write attempt {} of {} failed ({}); retrying in {:?}",
+                    attempt, attempts, e, delay
+                );
+                sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Writes `content` to a sibling temp file, then renames it over `path`
+///
+/// The temp file is synced before the rename, and on Unix the parent
+/// directory is synced after it, so a reported success survives power loss.
+/// The file keeps its permissions; a new one is created 0600 on Unix.
+fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    write_atomically_with_attempts(path, content, DEFAULT_WRITE_ATTEMPTS)
+}
+
+/// Like [`write_atomically`], retrying transient failures up to `attempts` times
+fn write_atomically_with_attempts(path: &Path, content: &[u8], attempts: u32) -> Result<()> {
+    let temp_path = temp_path_for(path);
+
+    retry_transient(
+        attempts,
+        std::thread::sleep,
+        is_transient_write_error,
+        || {
+            let result = create_temp_file(&temp_path, path)
+                .and_then(|mut file| write_and_sync(&mut file, content))
+                .and_then(|()| abandon_if_interrupted())
+                .and_then(|()| fs::rename(&temp_path, path));
+            if result.is_err() {
+                let _ = fs::remove_file(&temp_path);
+            }
+            result
+        },
+    )
+    .map_err(|e| write_error(path, e))?;
+
+    #[cfg(unix)]
+    sync_parent_dir(path)?;
+
+    Ok(())
+}
+
+/// Fails a write that has not been renamed into place yet after Ctrl-C
+fn abandon_if_interrupted() -> io::Result<()> {
+    if interrupt::was_interrupted() {
+        return Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "write abandoned after interrupt",
+        ));
+    }
+    Ok(())
+}
+
+/// Wraps a failed write of `path`, reporting an abandoned one as an interrupt
+fn write_error(path: &Path, error: io::Error) -> TokenProviderError {
+    if error.kind() == io::ErrorKind::Interrupted && interrupt::was_interrupted() {
+        return TokenProviderError::Interrupted;
+    }
+    TokenProviderError::file_io(path, error)
+}
+
+/// Syncs the directory containing `path` so a rename into it is durable
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|e| TokenProviderError::file_io(parent, e))
+}
+
+/// Atomically writes `content` to `path`, then runs `after_write` on it
+///
+/// The prior file contents are snapshotted first. If the write or
+/// `after_write` fails, the snapshot is put back byte for byte (or the file
+/// is removed if none existed) and the original error is returned, so a
+/// failed apply never leaves a partial change behind.
+pub fn transactional_write<F>(path: &Path, content: &[u8], after_write: F) -> Result<()>
+where
+    F: FnOnce(&Path) -> Result<()>,
+{
+    transactional_write_with_attempts(path, content, DEFAULT_WRITE_ATTEMPTS, after_write)
+}
+
+/// Like [`transactional_write`], retrying a transient write failure up to `attempts` times
+fn transactional_write_with_attempts<F>(
+    path: &Path,
+    content: &[u8],
+    attempts: u32,
+    after_write: F,
+) -> Result<()>
+where
+    F: FnOnce(&Path) -> Result<()>,
+{
+    let snapshot = match fs::read(path) {
+        Ok(bytes) => Some(bytes),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(TokenProviderError::file_io(path, e)),
+    };
+
+    let result =
+        write_atomically_with_attempts(path, content, attempts).and_then(|()| after_write(path));
+    if let Err(e) = result {
+        let rollback = match &snapshot {
+            Some(bytes) => write_atomically(path, bytes),
+            None if path.is_file() => fs::remove_file(path).map_err(Into::into),
+            None => Ok(()),
+        };
+        if let Err(rollback_error) = rollback {
+            warn!("Failed to roll back {}: {}", path.display(), rollback_error);
+        }
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Checks that a config file is not readable by group or others
+///
+/// The settings file may hold tokens, so any group/other permission bits
+/// are reported. With `tighten` set the file is changed to mode 0600
+/// instead of only warning.
+#[cfg(unix)]
+pub fn ensure_secure_permissions(path: &Path, tighten: bool) -> Result<()> {
+    if let Some(message) = secure_permissions_warning(path, tighten)? {
+        warn!("{}", message);
+    }
     Ok(())
 }
 
+/// Like [`ensure_secure_permissions`], returning the warning instead of logging it
+#[cfg(unix)]
+fn secure_permissions_warning(path: &Path, tighten: bool) -> Result<Option<String>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)
+        .map_err(|e| TokenProviderError::file_io(path, e))?
+        .permissions()
+        .mode()
+        & 0o777;
+    if mode & 0o077 == 0 {
+        return Ok(None);
+    }
+
+    if tighten {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .map_err(|e| TokenProviderError::file_io(path, e))?;
+        info!(
+            "Restricted permissions on {} from {:o} to 600",
+            path.display(),
+            mode
+        );
+        Ok(None)
+    } else {
+        Ok(Some(format!(
+            "{} is accessible by other users (mode {:o}); use --secure-perms to restrict it to 600",
+            path.display(),
+            mode
+        )))
+    }
+}
+
+/// Options controlling how [`apply_config_update`] applies a new config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplyOptions {
+    /// Target file; the default `~/.claude/settings.json` is used when `None`
+    pub config_path: Option<PathBuf>,
+    /// Copy an existing valid config to `<name>.bak` before writing
+    pub backup: bool,
+    /// Merge and print the result without touching the filesystem
+    pub dry_run: bool,
+    /// Reject the update unless the merged config matches the `AppConfig` schema
+    pub validate_schema: bool,
+    /// Allow a new config that is not a JSON object to replace the existing one
+    pub force: bool,
+    /// Whether `${VAR}` references in the new config are expanded
+    pub env_expansion: EnvExpansion,
+    /// JSON Pointer (e.g. `/settings/env`) of the subtree to merge into; root when `None`
+    pub merge_at: Option<String>,
+    /// Restrict the written file to mode 0600 instead of only warning (Unix only)
+    pub secure_permissions: bool,
+    /// How arrays present in both configs are combined
+    pub merge_strategy: MergeStrategy,
+    /// Treat a `null` in the new config as an instruction to delete the key
+    pub null_deletes: bool,
+    /// Parse an existing `.json` file as JSON5 if it is not strict JSON
+    pub allow_json5: bool,
+    /// Largest decrypted config, in bytes, accepted before JSON parsing
+    pub max_plaintext_size: usize,
+    /// Whether the existing file's format comes from its extension or its content
+    pub format_selection: FormatSelection,
+    /// Fail with [`TokenProviderError::ConfigExists`] instead of merging into a valid config
+    pub no_clobber: bool,
+    /// Keep timestamped backups in this directory instead of a sibling `.bak` file
+    pub backup_dir: Option<PathBuf>,
+    /// Number of timestamped backups kept in `backup_dir`; all are kept when `None`
+    pub keep_backups: Option<usize>,
+    /// Tries at writing the config while it fails with a transient lock error
+    pub write_attempts: u32,
+    /// Migrations run when the new `config_version` is higher than the existing one
+    pub migrations: Option<Arc<MigrationRegistry>>,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> Self {
+        Self {
+            config_path: None,
+            backup: true,
+            dry_run: false,
+            validate_schema: false,
+            force: false,
+            env_expansion: EnvExpansion::Off,
+            merge_at: None,
+            secure_permissions: false,
+            merge_strategy: MergeStrategy::ReplaceArrays,
+            null_deletes: false,
+            allow_json5: false,
+            max_plaintext_size: DEFAULT_MAX_PLAINTEXT_SIZE,
+            format_selection: FormatSelection::Extension,
+            no_clobber: false,
+            backup_dir: None,
+            keep_backups: None,
+            write_attempts: DEFAULT_WRITE_ATTEMPTS,
+            migrations: None,
+        }
+    }
+}
+
+impl ApplyOptions {
+    /// The merge behaviour selected by `merge_strategy` and `null_deletes`
+    pub fn merge_options(&self) -> MergeOptions {
+        MergeOptions {
+            strategy: self.merge_strategy,
+            null_deletes: self.null_deletes,
+        }
+    }
+}
+
+/// Default limit on the decrypted config size (1 MiB)
+///
+/// A real settings file is a few kilobytes; anything this large is more
+/// likely a crafted payload meant to exhaust memory during parsing.
+pub const DEFAULT_MAX_PLAINTEXT_SIZE: usize = 1024 * 1024;
+
+/// Result of applying a configuration update
+///
+/// Everything a caller may want to report is returned here rather than
+/// printed, so the binary decides what to show and how.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplyOutcome {
+    /// The target configuration file
+    pub path: PathBuf,
+    /// Whether the file did not exist before (or, in a dry run, would be created)
+    pub created: bool,
+    /// Whether the file existed but could not be parsed, so it was (or, in a
+    /// dry run, would be) replaced rather than merged into
+    pub replaced_invalid: bool,
+    /// Number of keys added that were not in the previous config
+    pub keys_added: usize,
+    /// Number of existing values overwritten with a different value
+    pub keys_overwritten: usize,
+    /// The merged configuration (written to disk unless this was a dry run)
+    pub config: Value,
+    /// Existing values that were overwritten by the merge
+    pub conflicts: Vec<MergeConflict>,
+    /// Where the previous config was backed up, if a backup was made
+    pub backup_path: Option<PathBuf>,
+    /// Paths changed between the previous and merged config, without their
+    /// values, which may contain tokens; empty if unchanged
+    pub changes: String,
+    /// Diff with the old and new values, only filled in on a dry run, where
+    /// the caller asked to see what would change; empty otherwise
+    pub diff: String,
+    /// Time spent backing up and writing the file; zero on a dry run
+    pub write_time: Duration,
+    /// Warnings raised while applying, in order, for an end-of-run summary
+    pub warnings: Vec<String>,
+    /// Whether the file was written; false on a dry run or when nothing changed
+    pub written: bool,
+}
+
+/// What an apply did to the target file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyAction {
+    /// The merge changed nothing, so nothing was written
+    Unchanged,
+    /// A dry run found changes but wrote nothing
+    Planned,
+    /// The changes were written
+    Applied,
+}
+
+impl fmt::Display for ApplyAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ApplyAction::Unchanged => "unchanged",
+            ApplyAction::Planned => "planned",
+            ApplyAction::Applied => "applied",
+        })
+    }
+}
+
+impl ApplyOutcome {
+    /// Whether the config was (or, on a dry run, would be) created or modified
+    pub fn has_changes(&self) -> bool {
+        self.created || self.replaced_invalid || !self.changes.is_empty()
+    }
+
+    /// What happened to the file
+    pub fn action(&self) -> ApplyAction {
+        if self.written {
+            ApplyAction::Applied
+        } else if self.has_changes() {
+            ApplyAction::Planned
+        } else {
+            ApplyAction::Unchanged
+        }
+    }
+
+    /// Compact single-line form for CI logs, e.g. `applied path=... added=2 overwritten=1`
+    ///
+    /// The line starts with [`ApplyOutcome::action`].
+    pub fn summary_line(&self) -> String {
+        format!(
+            "{} path={} added={} overwritten={}",
+            self.action(),
+            self.path.display(),
+            self.keys_added,
+            self.keys_overwritten
+        )
+    }
+}
+
+/// Multi-line human-readable summary; see [`ApplyOutcome::summary_line`] for CI
+impl fmt::Display for ApplyOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.created {
+            "Created"
+        } else if self.replaced_invalid {
+            "Replaced unparsable"
+        } else {
+            "Merged into"
+        };
+        write!(f, "{} {}", verb, self.path.display())?;
+        if !self.written {
+            write!(f, " (nothing written)")?;
+        }
+        write!(
+            f,
+            "\n  {} setting(s) added, {} overwritten",
+            self.keys_added, self.keys_overwritten
+        )?;
+        if let Some(backup_path) = &self.backup_path {
+            write!(f, "\n  Backup: {}", backup_path.display())?;
+        }
+        Ok(())
+    }
+}
+
 /// Handles the complete file operation workflow
-pub fn apply_config_update(new_config: Value) -> Result<()> {
-    let config_path = get_config_path()?;
+///
+/// Reads the existing config at the target path, deep merges `new_config`
+/// into it, and writes the result. The new config must be a JSON object
+/// unless `options.force` is set. Its `${VAR}` references are expanded first
+/// when `options.env_expansion` enables it, and it is merged at the
+/// `options.merge_at` JSON Pointer rather than the root when one is given. In dry-run mode nothing on disk
+/// is created or modified; the merged config is only returned.
+///
+/// The merged config is migrated with `options.migrations` when one is
+/// set; see [`apply_config_update_with_migrations`].
+pub fn apply_config_update(new_config: Value, options: &ApplyOptions) -> Result<ApplyOutcome> {
+    apply_config_update_with_migrations(new_config, options, options.migrations.as_deref())
+}
 
-    // Ensure directory exists
-    ensure_config_dir(&config_path)?;
+/// Like [`apply_config_update`], migrating the merged config when its version changes
+///
+/// When both the existing and the new config carry a `config_version` and
+/// the new one is higher, `migrations` transforms the merged value from the
+/// old version to the new one before it is validated and written. A missing
+/// migration path is an error. `migrations` is used instead of
+/// `options.migrations`.
+pub fn apply_config_update_with_migrations(
+    new_config: Value,
+    options: &ApplyOptions,
+    migrations: Option<&MigrationRegistry>,
+) -> Result<ApplyOutcome> {
+    let config_path = get_config_path_with_override(options.config_path.clone())?;
+    let new_config = prepare_new_config(new_config, options)?;
+
+    let mut warnings = Vec::new();
+    let created = !config_path.exists();
 
     // Read existing configuration
-    let final_config = match read_existing_config(&config_path)? {
+    let format = resolve_format(&config_path, options.format_selection, &mut warnings)?;
+    let config =
+        read_existing_config_collecting(&config_path, format, options.allow_json5, &mut warnings)?;
+
+    let existing = ExistingConfig {
+        path: config_path,
+        created,
+        format,
+        config,
+        warnings,
+    };
+    let merged = merge_update(existing, new_config, options, migrations)?;
+    write_merged_update(merged, options)
+}
+
+/// What was read from the target path before merging
+struct ExistingConfig {
+    /// The target configuration file
+    path: PathBuf,
+    /// Whether the file did not exist
+    created: bool,
+    /// Format the file is read and written in
+    format: ConfigFormat,
+    /// The parsed config; `None` if the file is missing or will be replaced
+    config: Option<Value>,
+    /// Warnings raised while reading
+    warnings: Vec<String>,
+}
+
+/// Checks that the incoming config may be merged and expands its `${VAR}` references
+fn prepare_new_config(mut new_config: Value, options: &ApplyOptions) -> Result<Value> {
+    // A non-object root would replace the whole existing config when merged
+    if !new_config.is_object() && !options.force {
+        return Err(TokenProviderError::ValidationError(format!(
+            "new configuration must be a JSON object, found {}; use --force to replace the existing config",
+            json_type_name(&new_config)
+        )));
+    }
+
+    // Only the incoming values are expanded; the existing file is kept verbatim
+    match options.env_expansion {
+        EnvExpansion::Off => {}
+        EnvExpansion::Lenient => expand_env_vars(&mut new_config, false)?,
+        EnvExpansion::Strict => expand_env_vars(&mut new_config, true)?,
+    }
+    Ok(new_config)
+}
+
+/// A merged config that has not been written yet
+struct MergedUpdate {
+    /// The outcome of a dry run; writing fills in the backup, timing, and `written`
+    outcome: ApplyOutcome,
+    /// Format the config is written in
+    format: ConfigFormat,
+    /// Whether the merged config equals the existing one
+    unchanged: bool,
+}
+
+/// Merges `new_config` into the existing config in memory, touching no files
+fn merge_update(
+    existing: ExistingConfig,
+    mut new_config: Value,
+    options: &ApplyOptions,
+    migrations: Option<&MigrationRegistry>,
+) -> Result<MergedUpdate> {
+    let ExistingConfig {
+        path: config_path,
+        created,
+        format,
+        config: existing_config,
+        warnings,
+    } = existing;
+    let mut conflicts = Vec::new();
+
+    let had_existing = existing_config.is_some();
+    // An unparsable file is replaced as usual; only a usable config is protected
+    if options.no_clobber && had_existing {
+        return Err(TokenProviderError::ConfigExists(config_path));
+    }
+    let previous_config = existing_config
+        .clone()
+        .unwrap_or_else(|| Value::Object(Map::new()));
+    let from_version = config_version(&previous_config);
+    let to_version = config_version(&new_config);
+
+    // Merging at a pointer is a root merge of the value wrapped in its parents
+    if let Some(pointer) = &options.merge_at {
+        let segments = parse_pointer(pointer)?;
+        check_pointer_parents(&previous_config, &segments)?;
+        new_config = nest_at(new_config, &segments);
+    }
+
+    let merge_options = options.merge_options();
+    let mut final_config = match existing_config {
         Some(mut existing) => {
             // Deep merge new config into existing
-            deep_merge_json(&mut existing, new_config)?;
+            conflicts =
+                deep_merge_json_reporting_with_options(&mut existing, new_config, merge_options)?;
             existing
         }
+        None if options.null_deletes => {
+            // Delete directives have nothing to delete, so only drop them
+            let mut empty = Value::Object(Map::new());
+            deep_merge_json_with_options(&mut empty, new_config, merge_options)?;
+            empty
+        }
         None => {
             // No existing config or invalid JSON - use new config directly
             new_config
         }
     };
 
-    // Write the final configuration
-    write_config(&config_path, &final_config)?;
+    if let (Some(registry), Some(from), Some(to)) = (migrations, from_version, to_version) {
+        if to > from {
+            registry.migrate(&mut final_config, from, to)?;
+        }
+    }
+
+    if options.validate_schema {
+        AppConfig::validate(&final_config)?;
+    }
+
+    // Compared as parsed values, so formatting and key order do not count
+    let unchanged = had_existing && previous_config == final_config;
+
+    Ok(MergedUpdate {
+        outcome: ApplyOutcome {
+            changes: render_changed_paths(&previous_config, &final_config),
+            diff: if options.dry_run {
+                render_diff(&previous_config, &final_config)
+            } else {
+                String::new()
+            },
+            keys_added: count_added_keys(&previous_config, &final_config),
+            keys_overwritten: conflicts.len(),
+            path: config_path,
+            created,
+            replaced_invalid: !had_existing && !created,
+            config: final_config,
+            conflicts,
+            backup_path: None,
+            write_time: Duration::ZERO,
+            warnings,
+            written: false,
+        },
+        format,
+        unchanged,
+    })
+}
+
+/// Backs up the existing config and writes the merged one, unless this is a dry run
+fn write_merged_update(merged: MergedUpdate, options: &ApplyOptions) -> Result<ApplyOutcome> {
+    let MergedUpdate {
+        mut outcome,
+        format,
+        unchanged,
+    } = merged;
+    let config_path = outcome.path.clone();
+
+    if options.dry_run {
+        return Ok(outcome);
+    }
+
+    if unchanged {
+        info!(
+            "No changes to {}; leaving it untouched",
+            config_path.display()
+        );
+        #[cfg(unix)]
+        if let Some(message) = secure_permissions_warning(&config_path, options.secure_permissions)?
+        {
+            record_warning(&mut outcome.warnings, message);
+        }
+        return Ok(outcome);
+    }
+
+    let write_started = Instant::now();
+
+    // Ensure directory exists
+    ensure_config_dir(&config_path)?;
+
+    // An unparsable file is backed up too, since it is about to be replaced
+    if options.backup && !outcome.created {
+        let path = match &options.backup_dir {
+            Some(backup_dir) => backup_config_in(&config_path, backup_dir)?,
+            None => backup_config(&config_path)?,
+        };
+        info!("Existing configuration backed up to: {}", path.display());
+        if let (Some(backup_dir), Some(keep)) = (&options.backup_dir, options.keep_backups) {
+            for removed in prune_backups(&config_path, backup_dir, keep)? {
+                info!("Removed old backup: {}", removed.display());
+            }
+        }
+        outcome.backup_path = Some(path);
+    }
+
+    // Write the final configuration, undoing the write if a later step fails
+    let content = format.serialize(&outcome.config)?;
+    let warnings = &mut outcome.warnings;
+    transactional_write_with_attempts(
+        &config_path,
+        content.as_bytes(),
+        options.write_attempts,
+        |_path| {
+            #[cfg(unix)]
+            if let Some(message) = secure_permissions_warning(_path, options.secure_permissions)? {
+                record_warning(warnings, message);
+            }
+            Ok(())
+        },
+    )?;
 
-    println!(
+    info!(
         "Configuration successfully updated at: {}",
         config_path.display()
     );
-    Ok(())
+    outcome.write_time = write_started.elapsed();
+    outcome.written = true;
+    Ok(outcome)
 }
 
 #[cfg(test)]
@@ -118,4 +1218,1396 @@ mod tests {
         let result = read_existing_config(temp_file.path()).unwrap();
         assert_eq!(result, None); // Should return None for invalid JSON
     }
+
+    #[test]
+    fn test_config_path_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let override_path = dir
+            .path()
+            .join("profiles")
+            .join("work")
+            .join("settings.json");
+
+        let options = ApplyOptions {
+            config_path: Some(override_path.clone()),
+            ..ApplyOptions::default()
+        };
+        apply_config_update(json!({"profile": "work"}), &options).unwrap();
+
+        let written = read_existing_config(&override_path).unwrap();
+        assert_eq!(written, Some(json!({"profile": "work"})));
+    }
+
+    #[test]
+    fn test_config_path_without_override_uses_default() {
+        let path = get_config_path_with_override(None).unwrap();
+
+        assert_eq!(path, get_config_path().unwrap());
+        assert!(path.ends_with(".claude/settings.json"));
+    }
+
+    #[test]
+    fn test_config_path_honors_xdg_config_home() {
+        let path = config_path_for(
+            Some(PathBuf::from("/home/user")),
+            Some(OsString::from("/home/user/.config")),
+        )
+        .unwrap();
+
+        assert_eq!(
+            path,
+            PathBuf::from("/home/user/.config/.claude/settings.json")
+        );
+    }
+
+    #[test]
+    fn test_config_path_without_xdg_uses_home() {
+        let home = Some(PathBuf::from("/home/user"));
+        let expected = PathBuf::from("/home/user/.claude/settings.json");
+
+        assert_eq!(config_path_for(home.clone(), None).unwrap(), expected);
+        // Empty and relative values are ignored, as the XDG spec requires
+        assert_eq!(
+            config_path_for(home.clone(), Some(OsString::new())).unwrap(),
+            expected
+        );
+        assert_eq!(
+            config_path_for(home, Some(OsString::from("relative/config"))).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_config_path_without_home_is_home_dir_unavailable() {
+        let result = config_path_for(None, None);
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::HomeDirUnavailable)
+        ));
+        // An absolute XDG_CONFIG_HOME does not need a home directory
+        assert!(config_path_for(None, Some(OsString::from("/etc/xdg"))).is_ok());
+    }
+
+    #[test]
+    fn test_write_config_replaces_existing_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"version": 1, "data": "old"})).unwrap();
+
+        let updated = json!({"version": 2, "data": "new", "extra": [1, 2, 3]});
+        write_config(&config_path, &updated).unwrap();
+
+        let read_config = read_existing_config(&config_path).unwrap();
+        assert_eq!(read_config, Some(updated));
+        assert!(!temp_path_for(&config_path).exists());
+    }
+
+    #[test]
+    fn test_write_config_cleans_up_temp_file_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        // A directory at the target path makes the final rename fail
+        let config_path = dir.path().join("settings.json");
+        fs::create_dir(&config_path).unwrap();
+
+        let result = write_config(&config_path, &json!({"version": 1}));
+
+        assert!(matches!(result, Err(TokenProviderError::FileIo { .. })));
+        assert!(!temp_path_for(&config_path).exists());
+    }
+
+    #[test]
+    fn test_write_error_names_the_config_path() {
+        let dir = tempfile::tempdir().unwrap();
+        // A file where the parent directory should be makes the path unwritable
+        let blocker = dir.path().join("not-a-dir");
+        fs::write(&blocker, "").unwrap();
+        let config_path = blocker.join("settings.json");
+
+        let error = write_config(&config_path, &json!({"version": 1})).unwrap_err();
+
+        match &error {
+            TokenProviderError::FileIo { path, .. } => assert_eq!(path, &config_path),
+            other => panic!("expected FileIo, got {:?}", other),
+        }
+        assert!(error
+            .user_message()
+            .contains(&config_path.display().to_string()));
+        assert!(matches!(
+            ensure_config_dir(&config_path),
+            Err(TokenProviderError::FileIo { path, .. }) if path == blocker
+        ));
+    }
+
+    #[test]
+    fn test_check_config_accepts_valid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        fs::write(&config_path, r#"{"model": "opus"}"#).unwrap();
+
+        let config = check_config(&config_path, false).unwrap();
+
+        assert_eq!(config, json!({"model": "opus"}));
+    }
+
+    #[test]
+    fn test_check_config_rejects_invalid_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        fs::write(&config_path, "{ not json").unwrap();
+
+        let result = check_config(&config_path, false);
+
+        assert!(matches!(result, Err(TokenProviderError::JsonError(_))));
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), "{ not json");
+    }
+
+    #[test]
+    fn test_check_config_reports_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+
+        match check_config(&config_path, false) {
+            Err(TokenProviderError::IoError(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+                assert!(e.to_string().contains("No configuration file found"));
+            }
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+        assert!(!config_path.exists());
+    }
+
+    #[test]
+    fn test_transactional_write_restores_original_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let original = b"{\n  \"version\": 1,\n  \"token\": \"old\"\n}";
+        fs::write(&config_path, original).unwrap();
+
+        let result = transactional_write(&config_path, b"{\"version\": 2}", |path| {
+            // The new content is in place when the failing step runs
+            assert_eq!(fs::read(path).unwrap(), b"{\"version\": 2}");
+            Err(TokenProviderError::MigrationError("forced".to_string()))
+        });
+
+        assert!(matches!(result, Err(TokenProviderError::MigrationError(_))));
+        assert_eq!(fs::read(&config_path).unwrap(), original);
+        assert!(!temp_path_for(&config_path).exists());
+    }
+
+    #[test]
+    fn test_transactional_write_removes_new_file_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+
+        let result = transactional_write(&config_path, b"{}", |_| {
+            Err(TokenProviderError::ValidationError("forced".to_string()))
+        });
+
+        assert!(result.is_err());
+        assert!(!config_path.exists());
+    }
+
+    #[test]
+    fn test_transactional_write_keeps_content_on_success() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        fs::write(&config_path, "{}").unwrap();
+
+        transactional_write(&config_path, b"{\"version\": 2}", |_| Ok(())).unwrap();
+
+        assert_eq!(fs::read(&config_path).unwrap(), b"{\"version\": 2}");
+    }
+
+    /// Records writes and syncs so tests can check their order
+    #[derive(Default)]
+    struct RecordingFile {
+        written: Vec<u8>,
+        synced: Option<usize>,
+    }
+
+    impl Write for RecordingFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SyncWrite for RecordingFile {
+        fn sync_all(&mut self) -> io::Result<()> {
+            self.synced = Some(self.written.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_and_sync_syncs_after_all_content() {
+        let mut file = RecordingFile::default();
+
+        write_and_sync(&mut file, b"{\"version\": 1}").unwrap();
+
+        assert_eq!(file.written, b"{\"version\": 1}");
+        assert_eq!(file.synced, Some(file.written.len()));
+    }
+
+    /// Stands in for the Windows predicate, so retries can be tested anywhere
+    fn is_permission_denied(error: &io::Error) -> bool {
+        error.kind() == io::ErrorKind::PermissionDenied
+    }
+
+    #[test]
+    fn test_permission_denied_is_transient_only_on_windows() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+
+        assert_eq!(is_transient_write_error(&denied), cfg!(windows));
+        assert!(!is_transient_write_error(&io::Error::from(
+            io::ErrorKind::NotFound
+        )));
+    }
+
+    #[test]
+    fn test_retry_transient_succeeds_after_two_lock_failures() {
+        let mut calls = 0;
+        let mut delays = Vec::new();
+
+        let result = retry_transient(
+            DEFAULT_WRITE_ATTEMPTS,
+            |delay| delays.push(delay),
+            is_permission_denied,
+            || {
+                calls += 1;
+                if calls <= 2 {
+                    Err(io::Error::from(io::ErrorKind::PermissionDenied))
+                } else {
+                    Ok("written")
+                }
+            },
+        );
+
+        assert_eq!(result.unwrap(), "written");
+        assert_eq!(calls, 3);
+        assert_eq!(delays, [WRITE_RETRY_BASE_DELAY, WRITE_RETRY_BASE_DELAY * 2]);
+    }
+
+    #[test]
+    fn test_retry_transient_gives_up_and_skips_permanent_errors() {
+        let mut calls = 0;
+        let result: io::Result<()> = retry_transient(
+            3,
+            |_| {},
+            is_permission_denied,
+            || {
+                calls += 1;
+                Err(io::Error::from(io::ErrorKind::PermissionDenied))
+            },
+        );
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result: io::Result<()> = retry_transient(
+            3,
+            |_| panic!("a permanent error must not be retried"),
+            is_permission_denied,
+            || {
+                calls += 1;
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            },
+        );
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_write_config_content_is_present_after_return() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+
+        write_config(&config_path, &json!({"version": 1})).unwrap();
+
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(json!({"version": 1}))
+        );
+        assert!(!temp_path_for(&config_path).exists());
+    }
+
+    #[test]
+    fn test_backup_contains_pre_merge_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let original = json!({"version": 1, "data": "test"});
+        write_config(&config_path, &original).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            backup: true,
+            ..ApplyOptions::default()
+        };
+        apply_config_update(json!({"version": 2}), &options).unwrap();
+
+        let backup = read_existing_config(&backup_path_for(&config_path)).unwrap();
+        assert_eq!(backup, Some(original));
+        let merged = read_existing_config(&config_path).unwrap();
+        assert_eq!(merged, Some(json!({"version": 2, "data": "test"})));
+    }
+
+    #[test]
+    fn test_apply_null_deletes_removes_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"env": {"OLD": "1"}, "model": "opus"})).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            null_deletes: true,
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"env": {"OLD": null}}), &options).unwrap();
+
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(json!({"env": {}, "model": "opus"}))
+        );
+
+        // With nothing to delete from, the directives are simply dropped
+        let new_path = dir.path().join("new.json");
+        let options = ApplyOptions {
+            config_path: Some(new_path.clone()),
+            ..options
+        };
+        apply_config_update(json!({"model": "opus", "theme": null}), &options).unwrap();
+        assert_eq!(
+            read_existing_config(&new_path).unwrap(),
+            Some(json!({"model": "opus"}))
+        );
+    }
+
+    #[test]
+    fn test_backup_dir_holds_timestamped_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let backup_dir = dir.path().join("backups");
+        let original = json!({"version": 1, "data": "test"});
+        write_config(&config_path, &original).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            backup_dir: Some(backup_dir.clone()),
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"version": 2}), &options).unwrap();
+
+        let backup_path = outcome.backup_path.unwrap();
+        assert_eq!(backup_path.parent(), Some(backup_dir.as_path()));
+        let name = backup_path.file_name().unwrap().to_string_lossy();
+        assert!(name.starts_with("settings-") && name.ends_with("Z.json"));
+        assert!(!name.contains(':'));
+        assert_eq!(read_existing_config(&backup_path).unwrap(), Some(original));
+        assert!(!backup_path_for(&config_path).exists());
+    }
+
+    #[test]
+    fn test_keep_backups_prunes_oldest() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let backup_dir = dir.path().join("backups");
+        fs::create_dir(&backup_dir).unwrap();
+        for name in [
+            "settings-20200101T000000Z.json",
+            "settings-20220101T000000Z.json",
+            "settings-20210101T000000Z.json",
+            "settings-latest.json",
+            "settings-2019-01-01T00:00:00Z.json",
+            "notes.txt",
+        ] {
+            fs::write(backup_dir.join(name), "{}").unwrap();
+        }
+        write_config(&config_path, &json!({"version": 1})).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            backup_dir: Some(backup_dir.clone()),
+            keep_backups: Some(2),
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"version": 2}), &options).unwrap();
+
+        let mut remaining: Vec<_> = fs::read_dir(&backup_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        remaining.sort();
+        let mut expected = vec![
+            backup_dir.join("notes.txt"),
+            backup_dir.join("settings-20220101T000000Z.json"),
+            backup_dir.join("settings-latest.json"),
+            backup_dir.join("settings-2019-01-01T00:00:00Z.json"),
+            outcome.backup_path.unwrap(),
+        ];
+        expected.sort();
+        assert_eq!(remaining, expected);
+    }
+
+    #[test]
+    fn test_basic_utc_timestamp() {
+        assert_eq!(basic_utc_timestamp(UNIX_EPOCH), "19700101T000000Z");
+        assert_eq!(
+            basic_utc_timestamp(UNIX_EPOCH + Duration::from_secs(951_827_696)),
+            "20000229T123456Z"
+        );
+    }
+
+    #[test]
+    fn test_restore_from_backup_dir_uses_newest_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let backup_dir = dir.path().join("backups");
+        write_config(&config_path, &json!({"version": 1})).unwrap();
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            backup_dir: Some(backup_dir.clone()),
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"version": 2}), &options).unwrap();
+
+        let restored = restore_backup_from(&config_path, Some(&backup_dir)).unwrap();
+        assert_eq!(Some(restored), outcome.backup_path);
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(json!({"version": 1}))
+        );
+
+        for (name, version) in [
+            ("settings-20200101T000000Z.json", 3),
+            ("settings-99990101T000000Z.json", 4),
+            ("settings-latest.json", 5),
+        ] {
+            fs::write(
+                backup_dir.join(name),
+                json!({ "version": version }).to_string(),
+            )
+            .unwrap();
+        }
+        let restored = restore_backup_from(&config_path, Some(&backup_dir)).unwrap();
+        assert_eq!(restored, backup_dir.join("settings-99990101T000000Z.json"));
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(json!({"version": 4}))
+        );
+
+        let empty_dir = dir.path().join("empty");
+        match restore_backup_from(&config_path, Some(&empty_dir)) {
+            Err(TokenProviderError::IoError(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::NotFound)
+            }
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_restore_backup_undoes_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"version": 1})).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+        apply_config_update(json!({"version": 2}), &options).unwrap();
+
+        let restored_from = restore_backup(&config_path).unwrap();
+
+        assert_eq!(restored_from, backup_path_for(&config_path));
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(json!({"version": 1}))
+        );
+    }
+
+    #[test]
+    fn test_restore_without_backup_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"version": 2})).unwrap();
+
+        match restore_backup(&config_path) {
+            Err(TokenProviderError::IoError(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+                assert!(e.to_string().contains("No backup found"));
+            }
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_restore_refuses_invalid_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"version": 2})).unwrap();
+        fs::write(backup_path_for(&config_path), "{ not json").unwrap();
+
+        let result = restore_backup(&config_path);
+
+        assert!(matches!(result, Err(TokenProviderError::JsonError(_))));
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(json!({"version": 2}))
+        );
+    }
+
+    #[test]
+    fn test_backup_disabled_writes_no_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"version": 1})).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            backup: false,
+            ..ApplyOptions::default()
+        };
+        apply_config_update(json!({"version": 2}), &options).unwrap();
+
+        assert!(!backup_path_for(&config_path).exists());
+    }
+
+    #[test]
+    fn test_json5_existing_config_is_merged_when_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        fs::write(
+            &config_path,
+            "{\n  // hand-edited\n  \"theme\": \"dark\",\n  \"env\": {\"A\": \"1\",},\n}\n",
+        )
+        .unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            allow_json5: true,
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"env": {"B": "2"}}), &options).unwrap();
+
+        let expected = json!({"theme": "dark", "env": {"A": "1", "B": "2"}});
+        assert_eq!(outcome.config, expected);
+        assert!(!outcome.created);
+        // Written back as standard JSON
+        let written: Value =
+            serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn test_json5_existing_config_is_replaced_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        fs::write(&config_path, "{\"theme\": \"dark\", // note\n}").unwrap();
+
+        assert_eq!(read_existing_config(&config_path).unwrap(), None);
+        assert_eq!(
+            read_existing_config_with(&config_path, true).unwrap(),
+            Some(json!({"theme": "dark"}))
+        );
+    }
+
+    #[test]
+    fn test_invalid_existing_config_warning_is_returned() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        fs::write(&config_path, "{ not json").unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path),
+            dry_run: true,
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"model": "opus"}), &options).unwrap();
+
+        assert_eq!(
+            outcome.warnings,
+            vec!["Existing config file is not valid JSON and will be replaced".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_replacing_unparsable_config_is_a_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        fs::write(&config_path, r#"{"keep": 1,}"#).unwrap();
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+
+        let planned = apply_config_update(
+            json!({}),
+            &ApplyOptions {
+                dry_run: true,
+                ..options.clone()
+            },
+        )
+        .unwrap();
+        assert!(planned.replaced_invalid);
+        assert!(planned.has_changes());
+        assert_eq!(planned.action(), ApplyAction::Planned);
+
+        let applied = apply_config_update(json!({}), &options).unwrap();
+        assert!(applied.written);
+        assert!(applied.has_changes());
+        assert_eq!(applied.action(), ApplyAction::Applied);
+        assert!(applied.summary_line().starts_with("applied "));
+        assert_eq!(read_existing_config(&config_path).unwrap(), Some(json!({})));
+        let backup_path = applied.backup_path.unwrap();
+        assert_eq!(fs::read_to_string(backup_path).unwrap(), r#"{"keep": 1,}"#);
+    }
+
+    #[test]
+    fn test_unchanged_merge_skips_write_and_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+
+        let first =
+            apply_config_update(json!({"model": "opus", "env": {"A": "1"}}), &options).unwrap();
+        assert!(first.written);
+
+        // Same content in a different layout and key order
+        let compact = r#"{"env":{"A":"1"},"model":"opus"}"#;
+        fs::write(&config_path, compact).unwrap();
+
+        let second =
+            apply_config_update(json!({"model": "opus", "env": {"A": "1"}}), &options).unwrap();
+
+        assert!(!second.written);
+        assert!(second.changes.is_empty());
+        assert_eq!(second.backup_path, None);
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), compact);
+        assert!(!backup_path_for(&config_path).exists());
+    }
+
+    #[test]
+    fn test_dry_run_leaves_file_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"version": 1, "data": "test"})).unwrap();
+        let before = fs::read_to_string(&config_path).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            dry_run: true,
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"version": 2}), &options).unwrap();
+
+        assert_eq!(outcome.config, json!({"version": 2, "data": "test"}));
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), before);
+        assert!(!backup_path_for(&config_path).exists());
+    }
+
+    #[test]
+    fn test_dry_run_creates_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("nested").join("settings.json");
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            dry_run: true,
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"version": 2}), &options).unwrap();
+
+        assert_eq!(outcome.config, json!({"version": 2}));
+        assert!(!config_path.parent().unwrap().exists());
+    }
+
+    #[test]
+    fn test_apply_returns_report_for_caller() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"version": 1})).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"version": 2}), &options).unwrap();
+
+        assert_eq!(outcome.path, config_path);
+        assert_eq!(outcome.backup_path, Some(backup_path_for(&config_path)));
+        assert_eq!(outcome.changes, "~ version");
+        assert_eq!(outcome.diff, "");
+    }
+
+    #[test]
+    fn test_changes_never_contain_secret_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(
+            &config_path,
+            &json!({"env": {"ANTHROPIC_AUTH_TOKEN": "sk-old-secret"}}),
+        )
+        .unwrap();
+        let update = json!({"env": {"ANTHROPIC_AUTH_TOKEN": "sk-NEW-SECRET"}});
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+
+        let planned = apply_config_update(
+            update.clone(),
+            &ApplyOptions {
+                dry_run: true,
+                ..options.clone()
+            },
+        )
+        .unwrap();
+        let applied = apply_config_update(update, &options).unwrap();
+
+        for outcome in [&planned, &applied] {
+            assert_eq!(outcome.changes, "~ env.ANTHROPIC_AUTH_TOKEN");
+            assert!(!outcome.changes.contains("sk-old-secret"));
+            assert!(!outcome.changes.contains("sk-NEW-SECRET"));
+        }
+        assert!(applied.diff.is_empty());
+        assert!(planned.diff.contains("sk-NEW-SECRET"));
+    }
+
+    #[test]
+    fn test_fresh_write_counts_all_keys_added() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(
+            json!({"version": 1, "settings": {"timeout_ms": 5000}}),
+            &options,
+        )
+        .unwrap();
+
+        assert!(outcome.created);
+        assert_eq!(outcome.keys_added, 2);
+        assert_eq!(outcome.keys_overwritten, 0);
+    }
+
+    #[test]
+    fn test_merge_counts_added_and_overwritten_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(
+            &config_path,
+            &json!({"version": 1, "settings": {"timeout_ms": 5000, "debug": false}}),
+        )
+        .unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(
+            json!({
+                "version": 2,
+                "settings": {"timeout_ms": 5000, "retries": 3},
+                "profile": "work"
+            }),
+            &options,
+        )
+        .unwrap();
+
+        assert!(!outcome.created);
+        assert_eq!(outcome.keys_added, 2);
+        assert_eq!(outcome.keys_overwritten, 1);
+    }
+
+    #[test]
+    fn test_object_config_accepted() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"theme": "dark"})).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"version": 2}), &options).unwrap();
+
+        assert_eq!(outcome.config, json!({"theme": "dark", "version": 2}));
+    }
+
+    #[test]
+    fn test_top_level_array_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"theme": "dark"})).unwrap();
+        let before = fs::read_to_string(&config_path).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+        let result = apply_config_update(json!(["a", "b"]), &options);
+
+        match result {
+            Err(TokenProviderError::ValidationError(message)) => {
+                assert!(message.contains("an array"))
+            }
+            other => panic!("expected ValidationError, got {:?}", other),
+        }
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), before);
+    }
+
+    #[test]
+    fn test_force_allows_non_object_replacement() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"theme": "dark"})).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            force: true,
+            ..ApplyOptions::default()
+        };
+        apply_config_update(json!(["a", "b"]), &options).unwrap();
+
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(json!(["a", "b"]))
+        );
+    }
+
+    fn rename_timeout_registry() -> MigrationRegistry {
+        MigrationRegistry::new().with(1, 2, |config| {
+            let settings = config["settings"].as_object_mut().unwrap();
+            if let Some(timeout) = settings.remove("timeout") {
+                settings.insert("timeout_ms".to_string(), timeout);
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_migration_runs_when_version_increases() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(
+            &config_path,
+            &json!({"config_version": 1, "settings": {"timeout": 1000}}),
+        )
+        .unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update_with_migrations(
+            json!({"config_version": 2}),
+            &options,
+            Some(&rename_timeout_registry()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            outcome.config,
+            json!({"config_version": 2, "settings": {"timeout_ms": 1000}})
+        );
+    }
+
+    #[test]
+    fn test_migrations_in_options_run_on_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(
+            &config_path,
+            &json!({"config_version": 1, "settings": {"timeout": 1000}}),
+        )
+        .unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            migrations: Some(Arc::new(rename_timeout_registry())),
+            ..ApplyOptions::default()
+        };
+        apply_config_update(json!({"config_version": 2}), &options).unwrap();
+
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(json!({"config_version": 2, "settings": {"timeout_ms": 1000}}))
+        );
+    }
+
+    #[test]
+    fn test_migration_skipped_when_version_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(
+            &config_path,
+            &json!({"config_version": 2, "settings": {"timeout": 1000}}),
+        )
+        .unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update_with_migrations(
+            json!({"config_version": 2}),
+            &options,
+            Some(&rename_timeout_registry()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            outcome.config,
+            json!({"config_version": 2, "settings": {"timeout": 1000}})
+        );
+    }
+
+    #[test]
+    fn test_missing_migration_path_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"config_version": 1, "settings": {}})).unwrap();
+        let before = fs::read_to_string(&config_path).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+        let result = apply_config_update_with_migrations(
+            json!({"config_version": 3}),
+            &options,
+            Some(&rename_timeout_registry()),
+        );
+
+        assert!(matches!(result, Err(TokenProviderError::MigrationError(_))));
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), before);
+    }
+
+    #[test]
+    fn test_key_order_preserved_with_new_keys_appended() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        fs::write(
+            &config_path,
+            r#"{"zeta": 1, "alpha": {"second": 2, "first": 1}, "middle": true}"#,
+        )
+        .unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            backup: false,
+            ..ApplyOptions::default()
+        };
+        apply_config_update(json!({"alpha": {"third": 3}, "trailing": "new"}), &options).unwrap();
+
+        let written: Value =
+            serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&written).unwrap(),
+            r#"{"zeta":1,"alpha":{"second":2,"first":1,"third":3},"middle":true,"trailing":"new"}"#
+        );
+    }
+
+    #[test]
+    fn test_env_expansion_applies_to_new_config() {
+        std::env::set_var("CLAUDE_TOKEN_TEST_APPLY_HOST", "https://api.example.com");
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(
+            &config_path,
+            &json!({"note": "${CLAUDE_TOKEN_TEST_APPLY_HOST}"}),
+        )
+        .unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            env_expansion: EnvExpansion::Lenient,
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(
+            json!({"api_base": "${CLAUDE_TOKEN_TEST_APPLY_HOST}"}),
+            &options,
+        );
+        std::env::remove_var("CLAUDE_TOKEN_TEST_APPLY_HOST");
+
+        assert_eq!(
+            outcome.unwrap().config,
+            json!({
+                "note": "${CLAUDE_TOKEN_TEST_APPLY_HOST}",
+                "api_base": "https://api.example.com"
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_loose_permissions_tightened_to_0600() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"env": {"TOKEN": "abc"}})).unwrap();
+        fs::set_permissions(&config_path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        ensure_secure_permissions(&config_path, false).unwrap();
+        let mode = fs::metadata(&config_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+
+        ensure_secure_permissions(&config_path, true).unwrap();
+        let mode = fs::metadata(&config_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_config_keeps_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"env": {"TOKEN": "abc"}})).unwrap();
+        let mode = fs::metadata(&config_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::set_permissions(&config_path, fs::Permissions::from_mode(0o600)).unwrap();
+        write_config(&config_path, &json!({"env": {"TOKEN": "def"}})).unwrap();
+        let mode = fs::metadata(&config_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::set_permissions(&config_path, fs::Permissions::from_mode(0o640)).unwrap();
+        write_config(&config_path, &json!({"env": {"TOKEN": "ghi"}})).unwrap();
+        let mode = fs::metadata(&config_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn test_merge_at_nested_pointer() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(
+            &config_path,
+            &json!({"theme": "dark", "settings": {"timeout_ms": 5000}}),
+        )
+        .unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            merge_at: Some("/settings/env".to_string()),
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"TOKEN": "abc"}), &options).unwrap();
+
+        assert_eq!(
+            outcome.config,
+            json!({
+                "theme": "dark",
+                "settings": {"timeout_ms": 5000, "env": {"TOKEN": "abc"}}
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_at_root_pointer_matches_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"theme": "dark"})).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            merge_at: Some(String::new()),
+            dry_run: true,
+            ..ApplyOptions::default()
+        };
+        let outcome = apply_config_update(json!({"profile": "work"}), &options).unwrap();
+
+        assert_eq!(outcome.config, json!({"theme": "dark", "profile": "work"}));
+    }
+
+    #[test]
+    fn test_merge_at_pointer_below_non_object_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"settings": ["a"]})).unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            merge_at: Some("/settings/env".to_string()),
+            ..ApplyOptions::default()
+        };
+        let result = apply_config_update(json!({"TOKEN": "abc"}), &options);
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::ValidationError(_))
+        ));
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(json!({"settings": ["a"]}))
+        );
+    }
+
+    #[test]
+    fn test_yaml_config_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.yaml");
+        fs::write(
+            &config_path,
+            "settings:\n  retries: 3\n  timeout_ms: 1000\nfeatures:\n  feature_a: true\n",
+        )
+        .unwrap();
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            backup: false,
+            ..ApplyOptions::default()
+        };
+        apply_config_update(json!({"settings": {"timeout_ms": 5000}}), &options).unwrap();
+
+        let written = fs::read_to_string(&config_path).unwrap();
+        assert!(written.contains("timeout_ms: 5000"));
+        assert!(serde_json::from_str::<Value>(&written).is_err());
+
+        let read_config = read_existing_config(&config_path).unwrap();
+        let expected = json!({
+            "settings": {"retries": 3, "timeout_ms": 5000},
+            "features": {"feature_a": true}
+        });
+        assert_eq!(read_config, Some(expected));
+    }
+
+    #[test]
+    fn test_no_clobber_refuses_existing_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        fs::write(&config_path, r#"{"model": "sonnet"}"#).unwrap();
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            no_clobber: true,
+            ..ApplyOptions::default()
+        };
+
+        let result = apply_config_update(json!({"model": "opus"}), &options);
+
+        match result {
+            Err(TokenProviderError::ConfigExists(path)) => assert_eq!(path, config_path),
+            other => panic!("expected ConfigExists, got {:?}", other),
+        }
+        assert_eq!(
+            fs::read_to_string(&config_path).unwrap(),
+            r#"{"model": "sonnet"}"#
+        );
+        assert!(!backup_path_for(&config_path).exists());
+    }
+
+    #[test]
+    fn test_no_clobber_writes_when_absent() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            no_clobber: true,
+            ..ApplyOptions::default()
+        };
+
+        let outcome = apply_config_update(json!({"model": "opus"}), &options).unwrap();
+
+        assert!(outcome.created);
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(json!({"model": "opus"}))
+        );
+    }
+
+    #[test]
+    fn test_auto_format_writes_back_detected_format() {
+        let dir = tempfile::tempdir().unwrap();
+        // The extension says JSON, but the content is TOML
+        let config_path = dir.path().join("settings.json");
+        fs::write(&config_path, "[settings]\nretries = 3\n").unwrap();
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            backup: false,
+            format_selection: FormatSelection::Auto,
+            ..ApplyOptions::default()
+        };
+
+        let outcome =
+            apply_config_update(json!({"settings": {"timeout_ms": 5000}}), &options).unwrap();
+
+        let expected = json!({"settings": {"retries": 3, "timeout_ms": 5000}});
+        assert_eq!(outcome.config, expected);
+        let written = fs::read_to_string(&config_path).unwrap();
+        assert_eq!(ConfigFormat::Toml.parse(&written).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_auto_format_falls_back_to_json_with_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.conf");
+        fs::write(&config_path, "not a config").unwrap();
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            backup: false,
+            format_selection: FormatSelection::Auto,
+            ..ApplyOptions::default()
+        };
+
+        let outcome = apply_config_update(json!({"model": "opus"}), &options).unwrap();
+
+        assert!(outcome
+            .warnings
+            .iter()
+            .any(|warning| warning.contains("Could not detect the format")));
+        let written = fs::read_to_string(&config_path).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&written).unwrap(),
+            json!({"model": "opus"})
+        );
+    }
+
+    #[test]
+    fn test_write_config_as_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.conf");
+        let config = json!({"settings": {"retries": 5}});
+
+        write_config_as(&config_path, &config, ConfigFormat::Toml).unwrap();
+
+        let written = fs::read_to_string(&config_path).unwrap();
+        assert_eq!(ConfigFormat::Toml.parse(&written).unwrap(), config);
+    }
+
+    #[test]
+    fn test_schema_validation_passes_valid_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let new_config = json!({
+            "config_version": 2,
+            "settings": {"retries": 5, "timeout_ms": 5000},
+            "features": {"beta_feature_x": true}
+        });
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            validate_schema: true,
+            ..ApplyOptions::default()
+        };
+        apply_config_update(new_config.clone(), &options).unwrap();
+
+        assert_eq!(
+            read_existing_config(&config_path).unwrap(),
+            Some(new_config)
+        );
+    }
+
+    #[test]
+    fn test_schema_validation_rejects_missing_field() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let new_config = json!({
+            "config_version": 2,
+            "settings": {"retries": 5},
+            "features": {}
+        });
+
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            validate_schema: true,
+            ..ApplyOptions::default()
+        };
+        let result = apply_config_update(new_config, &options);
+
+        match result {
+            Err(TokenProviderError::ValidationError(message)) => {
+                assert!(message.contains("timeout_ms"))
+            }
+            other => panic!("expected ValidationError, got {:?}", other),
+        }
+        assert!(!config_path.exists());
+    }
+
+    fn sample_outcome() -> ApplyOutcome {
+        ApplyOutcome {
+            path: PathBuf::from("/home/user/.claude/settings.json"),
+            created: false,
+            replaced_invalid: false,
+            keys_added: 2,
+            keys_overwritten: 1,
+            config: json!({}),
+            conflicts: Vec::new(),
+            backup_path: Some(PathBuf::from("/home/user/.claude/settings.json.bak")),
+            changes: String::new(),
+            diff: String::new(),
+            write_time: Duration::ZERO,
+            warnings: Vec::new(),
+            written: true,
+        }
+    }
+
+    #[test]
+    fn test_apply_outcome_display() {
+        assert_eq!(
+            sample_outcome().to_string(),
+            "Merged into /home/user/.claude/settings.json\n  \
+             2 setting(s) added, 1 overwritten\n  \
+             Backup: /home/user/.claude/settings.json.bak"
+        );
+
+        let dry_run = ApplyOutcome {
+            created: true,
+            written: false,
+            backup_path: None,
+            ..sample_outcome()
+        };
+        assert_eq!(
+            dry_run.to_string(),
+            "Created /home/user/.claude/settings.json (nothing written)\n  \
+             2 setting(s) added, 1 overwritten"
+        );
+    }
+
+    #[test]
+    fn test_apply_outcome_summary_line() {
+        let applied = ApplyOutcome {
+            changes: "~ model".to_string(),
+            ..sample_outcome()
+        };
+        assert_eq!(
+            applied.summary_line(),
+            "applied path=/home/user/.claude/settings.json added=2 overwritten=1"
+        );
+
+        let planned = ApplyOutcome {
+            written: false,
+            ..applied.clone()
+        };
+        assert_eq!(
+            planned.summary_line(),
+            "planned path=/home/user/.claude/settings.json added=2 overwritten=1"
+        );
+
+        let unchanged = ApplyOutcome {
+            keys_added: 0,
+            keys_overwritten: 0,
+            written: false,
+            ..sample_outcome()
+        };
+        assert_eq!(unchanged.action(), ApplyAction::Unchanged);
+        assert_eq!(
+            unchanged.summary_line(),
+            "unchanged path=/home/user/.claude/settings.json added=0 overwritten=0"
+        );
+    }
 }
@@ -0,0 +1,211 @@
+//! Async variants of the config file operations for Tokio applications
+//!
+//! Available with the `tokio` feature. Files are read and written through
+//! `tokio::fs`, so the caller's runtime is not blocked; parsing, merging,
+//! and validation run the same code as the sync functions in [`super`].
+
+use serde_json::Value;
+use std::io;
+use std::path::Path;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use super::{
+    abandon_if_interrupted, format_from_content, get_config_path_with_override,
+    is_transient_write_error, merge_update, parse_existing_config, prepare_new_config,
+    replacement_permissions, temp_path_for, write_error, write_merged_update, ApplyOptions,
+    ApplyOutcome, ExistingConfig, DEFAULT_WRITE_ATTEMPTS, WRITE_RETRY_BASE_DELAY,
+};
+use crate::config::format::{ConfigFormat, FormatSelection};
+use crate::{Result, TokenProviderError};
+
+/// Reads a file as text, or returns `None` if it does not exist
+async fn read_if_exists(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(TokenProviderError::file_io(path, e)),
+    }
+}
+
+/// Async form of [`super::read_existing_config`]
+pub async fn read_existing_config(config_path: &Path) -> Result<Option<Value>> {
+    let format = ConfigFormat::from_path(config_path);
+    Ok(read_if_exists(config_path).await?.and_then(|content| {
+        parse_existing_config(config_path, &content, format, false, &mut Vec::new())
+    }))
+}
+
+/// Async form of [`super::write_config`]
+///
+/// The content goes to a synced sibling temp file that is then renamed
+/// into place, and transient lock errors are retried with backoff, as in
+/// the sync version.
+pub async fn write_config(config_path: &Path, config: &Value) -> Result<()> {
+    let content = ConfigFormat::from_path(config_path).serialize(config)?;
+
+    let mut delay = WRITE_RETRY_BASE_DELAY;
+    let mut attempt = 1;
+    loop {
+        match write_atomically(config_path, content.as_bytes()).await {
+            Err(e) if attempt < DEFAULT_WRITE_ATTEMPTS && is_transient_write_error(&e) => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result.map_err(|e| write_error(config_path, e)),
+        }
+    }
+}
+
+/// Writes `content` to a sibling temp file, then renames it over `path`
+async fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let temp_path = temp_path_for(path);
+
+    let result = async {
+        let permissions = replacement_permissions(fs::metadata(path).await.ok());
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&temp_path).await?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions).await?;
+        }
+        file.write_all(content).await?;
+        file.sync_all().await?;
+        abandon_if_interrupted()?;
+        fs::rename(&temp_path, path).await
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path).await;
+        return result;
+    }
+
+    // Makes the rename durable, as the sync version does
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(parent).await?.sync_all().await?;
+    }
+    Ok(())
+}
+
+/// Async form of [`super::apply_config_update`]
+///
+/// The existing config is read with `tokio::fs` and merged in memory. The
+/// backup, write, and permission steps then run on Tokio's blocking pool,
+/// where `tokio::fs` does its work as well, so a failed step is rolled back
+/// exactly as in the sync version. `options.migrations` is applied as well.
+pub async fn apply_config_update(
+    new_config: Value,
+    options: &ApplyOptions,
+) -> Result<ApplyOutcome> {
+    let config_path = get_config_path_with_override(options.config_path.clone())?;
+    let new_config = prepare_new_config(new_config, options)?;
+
+    let mut warnings = Vec::new();
+    let content = read_if_exists(&config_path).await?;
+    let format = match (&content, options.format_selection) {
+        (Some(content), FormatSelection::Auto) => {
+            format_from_content(&config_path, content, &mut warnings)
+        }
+        _ => ConfigFormat::from_path(&config_path),
+    };
+    let created = content.is_none();
+    let config = content.and_then(|content| {
+        parse_existing_config(
+            &config_path,
+            &content,
+            format,
+            options.allow_json5,
+            &mut warnings,
+        )
+    });
+
+    let existing = ExistingConfig {
+        path: config_path,
+        created,
+        format,
+        config,
+        warnings,
+    };
+    let merged = merge_update(existing, new_config, options, options.migrations.as_deref())?;
+
+    let options = options.clone();
+    tokio::task::spawn_blocking(move || write_merged_update(merged, &options))
+        .await
+        .map_err(|e| TokenProviderError::IoError(io::Error::other(e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_async_apply_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        write_config(&config_path, &json!({"model": "sonnet", "theme": "dark"}))
+            .await
+            .unwrap();
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+
+        let outcome = apply_config_update(json!({"model": "opus"}), &options)
+            .await
+            .unwrap();
+
+        assert!(outcome.written);
+        assert_eq!(outcome.keys_overwritten, 1);
+        assert_eq!(
+            read_existing_config(&config_path).await.unwrap(),
+            Some(json!({"model": "opus", "theme": "dark"}))
+        );
+        assert_eq!(
+            super::super::read_existing_config(&config_path).unwrap(),
+            Some(json!({"model": "opus", "theme": "dark"}))
+        );
+        assert_eq!(
+            outcome.backup_path,
+            Some(config_path.with_extension("json.bak"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_apply_creates_missing_config_and_dry_run_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("nested").join("settings.json");
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            dry_run: true,
+            ..ApplyOptions::default()
+        };
+
+        let outcome = apply_config_update(json!({"model": "opus"}), &options)
+            .await
+            .unwrap();
+        assert!(outcome.created);
+        assert!(!outcome.written);
+        assert_eq!(read_existing_config(&config_path).await.unwrap(), None);
+
+        let options = ApplyOptions {
+            dry_run: false,
+            ..options
+        };
+        apply_config_update(json!({"model": "opus"}), &options)
+            .await
+            .unwrap();
+        assert_eq!(
+            read_existing_config(&config_path).await.unwrap(),
+            Some(json!({"model": "opus"}))
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use clap::ValueEnum;
+use serde_json::{to_string_pretty, Value};
+use std::fmt;
+use std::path::Path;
+
+use crate::{Result, TokenProviderError};
+
+/// On-disk serialization format of a configuration file
+///
+/// Merging always happens on `serde_json::Value`; the format only affects
+/// how a file is parsed and written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConfigFormat {
+    /// Pretty-printed JSON
+    #[default]
+    Json,
+    /// YAML (`.yaml` / `.yml`)
+    Yaml,
+    /// TOML (`.toml`); the document root must be a table and nulls are not allowed
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Determines the format from a file extension, defaulting to JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    /// Parses configuration content in this format
+    pub fn parse(self, content: &str) -> Result<Value> {
+        Ok(match self {
+            ConfigFormat::Json => serde_json::from_str(content)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+            ConfigFormat::Toml => {
+                toml::from_str(content).map_err(|e| TokenProviderError::TomlError(e.to_string()))?
+            }
+        })
+    }
+
+    /// Serializes a configuration value in this format
+    pub fn serialize(self, config: &Value) -> Result<String> {
+        Ok(match self {
+            ConfigFormat::Json => to_string_pretty(config)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(config)?,
+            ConfigFormat::Toml => {
+                check_toml_compatible(config)?;
+                toml::to_string_pretty(config)
+                    .map_err(|e| TokenProviderError::TomlError(e.to_string()))?
+            }
+        })
+    }
+}
+
+/// How the format of an existing config file is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FormatSelection {
+    /// Use the file extension, as [`ConfigFormat::from_path`] does
+    #[default]
+    Extension,
+    /// Inspect the existing file's content with [`detect_format`]
+    Auto,
+}
+
+/// Guesses the format of config content by trying each parser in turn
+///
+/// JSON is tried first since every JSON document is also YAML, then the
+/// stricter TOML, then YAML. Only a document whose root is an object counts,
+/// because YAML accepts almost any text as a plain string. Returns `None`
+/// when no parser yields an object, or for blank content, which is an empty
+/// TOML table but says nothing about the intended format.
+pub fn detect_format(content: &str) -> Option<ConfigFormat> {
+    if content.trim().is_empty() {
+        return None;
+    }
+
+    [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml]
+        .into_iter()
+        .find(|format| format.parse(content).is_ok_and(|value| value.is_object()))
+}
+
+/// Rejects JSON constructs that have no TOML equivalent
+///
+/// TOML documents must be tables at the root and have no null value, so
+/// these are reported with their path instead of failing deep inside the
+/// serializer.
+fn check_toml_compatible(config: &Value) -> Result<()> {
+    if !config.is_object() {
+        return Err(TokenProviderError::TomlError(
+            "the top-level value must be an object to be written as TOML".to_string(),
+        ));
+    }
+    check_no_nulls(config, "")
+}
+
+/// Recursively ensures a value contains no nulls
+fn check_no_nulls(value: &Value, path: &str) -> Result<()> {
+    match value {
+        Value::Null => Err(TokenProviderError::TomlError(format!(
+            "null value at `{}` cannot be represented in TOML",
+            path
+        ))),
+        Value::Object(map) => map.iter().try_for_each(|(key, child)| {
+            let child_path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", path, key)
+            };
+            check_no_nulls(child, &child_path)
+        }),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, child)| check_no_nulls(child, &format!("{}[{}]", path, index))),
+        _ => Ok(()),
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigFormat::Json => write!(f, "JSON"),
+            ConfigFormat::Yaml => write!(f, "YAML"),
+            ConfigFormat::Toml => write!(f, "TOML"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("settings.json")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("settings.yaml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("settings.YML")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("settings.toml")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("settings")),
+            ConfigFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_detect_format_json() {
+        assert_eq!(
+            detect_format(r#"{"model": "opus", "retries": 3}"#),
+            Some(ConfigFormat::Json)
+        );
+    }
+
+    #[test]
+    fn test_detect_format_yaml_and_toml() {
+        assert_eq!(
+            detect_format("model: opus\nsettings:\n  retries: 3\n"),
+            Some(ConfigFormat::Yaml)
+        );
+        assert_eq!(
+            detect_format("model = \"opus\"\n\n[settings]\nretries = 3\n"),
+            Some(ConfigFormat::Toml)
+        );
+    }
+
+    #[test]
+    fn test_detect_format_rejects_ambiguous_content() {
+        // Valid YAML, but a plain string rather than a config object
+        assert_eq!(detect_format("just some text"), None);
+        assert_eq!(detect_format("{\"unterminated\": "), None);
+        assert_eq!(detect_format(""), None);
+    }
+
+    #[test]
+    fn test_yaml_serialize_parse_roundtrip() {
+        let config = json!({"settings": {"retries": 3, "hosts": ["a", "b"]}, "enabled": true});
+
+        let yaml = ConfigFormat::Yaml.serialize(&config).unwrap();
+        assert!(yaml.contains("retries: 3"));
+
+        assert_eq!(ConfigFormat::Yaml.parse(&yaml).unwrap(), config);
+    }
+
+    #[test]
+    fn test_toml_nested_roundtrip() {
+        let config = json!({
+            "config_version": 2,
+            "settings": {"retries": 5, "timeout_ms": 5000},
+            "permissions": {"allow": ["read", "write"], "deny": []}
+        });
+
+        let toml = ConfigFormat::Toml.serialize(&config).unwrap();
+        assert!(toml.contains("[settings]"));
+
+        assert_eq!(ConfigFormat::Toml.parse(&toml).unwrap(), config);
+    }
+
+    #[test]
+    fn test_toml_rejects_top_level_array() {
+        let result = ConfigFormat::Toml.serialize(&json!([1, 2, 3]));
+
+        assert!(matches!(result, Err(TokenProviderError::TomlError(_))));
+    }
+
+    #[test]
+    fn test_toml_rejects_null_with_path() {
+        let result = ConfigFormat::Toml.serialize(&json!({"env": {"TOKEN": null}}));
+
+        match result {
+            Err(TokenProviderError::TomlError(message)) => assert!(message.contains("env.TOKEN")),
+            other => panic!("expected TomlError, got {:?}", other),
+        }
+    }
+}
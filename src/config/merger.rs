@@ -1,14 +1,173 @@
-use crate::Result;
+use crate::{Result, TokenProviderError};
+use clap::ValueEnum;
 use serde_json::{Map, Value};
+use std::fmt;
+
+/// How arrays are combined when both sides of a merge hold an array
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum MergeStrategy {
+    /// Replace the existing array with the new one
+    #[default]
+    ReplaceArrays,
+    /// Append the new elements after the existing ones
+    ConcatArrays,
+    /// Append only the new elements not already present
+    ConcatUniqueArrays,
+    /// Replace arrays, but fail instead of replacing a value with one of another JSON type
+    StrictTypes,
+}
+
+/// An existing value that was overwritten during a merge
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeConflict {
+    /// Dot-separated JSON path of the overwritten key (e.g. `settings.timeout_ms`)
+    pub path: String,
+    /// The value before the merge
+    pub old_value: Value,
+    /// The value that replaced it
+    pub new_value: Value,
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.path, self.old_value, self.new_value)
+    }
+}
 
 /// Performs deep merge of JSON values
 ///
 /// The `new_value` takes precedence over `existing_value` for conflicts.
 /// Objects are merged recursively, arrays and primitives are replaced.
 pub fn deep_merge_json(existing: &mut Value, new: Value) -> Result<()> {
+    deep_merge_json_with_strategy(existing, new, MergeStrategy::ReplaceArrays)
+}
+
+/// Returns the deep merge of `new` into `existing`, leaving both untouched
+///
+/// Produces the same result as [`deep_merge_json`] on a clone of
+/// `existing`, so the before and after configs can be compared.
+pub fn merge_json(existing: &Value, new: &Value) -> Result<Value> {
+    let mut merged = existing.clone();
+    deep_merge_json(&mut merged, new.clone())?;
+    Ok(merged)
+}
+
+/// Options controlling how [`deep_merge_json_with_options`] combines values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeOptions {
+    /// How arrays are combined when both sides are arrays
+    pub strategy: MergeStrategy,
+    /// Treat a `null` new value as an instruction to delete the key
+    pub null_deletes: bool,
+}
+
+/// Performs deep merge of JSON values using the given array strategy
+///
+/// Objects are merged recursively and primitives are replaced. Arrays are
+/// combined according to `strategy` only when both sides are arrays; a
+/// type mismatch always replaces the existing value.
+pub fn deep_merge_json_with_strategy(
+    existing: &mut Value,
+    new: Value,
+    strategy: MergeStrategy,
+) -> Result<()> {
+    deep_merge_json_with_options(
+        existing,
+        new,
+        MergeOptions {
+            strategy,
+            ..MergeOptions::default()
+        },
+    )
+}
+
+/// Performs deep merge of JSON values with full control over merge behavior
+///
+/// With `null_deletes` enabled, a `null` in the new object removes the
+/// corresponding key from the existing object (at any depth) instead of
+/// storing an explicit null. Deleting a key that does not exist is a no-op.
+///
+/// With [`MergeStrategy::StrictTypes`], every value whose JSON type would
+/// change is reported in one `ValidationError` and `existing` is left
+/// untouched.
+pub fn deep_merge_json_with_options(
+    existing: &mut Value,
+    new: Value,
+    options: MergeOptions,
+) -> Result<()> {
+    if options.strategy == MergeStrategy::StrictTypes {
+        let mut mismatches = Vec::new();
+        collect_type_mismatches(existing, &new, "", options.null_deletes, &mut mismatches);
+        if !mismatches.is_empty() {
+            return Err(TokenProviderError::ValidationError(format!(
+                "merge would change the type of {}",
+                mismatches.join(", ")
+            )));
+        }
+    }
+
+    merge_values(existing, new, options)
+}
+
+/// Describes the JSON type of a value for error messages
+pub fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Walks both trees and describes each existing value the new one would change type
+fn collect_type_mismatches(
+    existing: &Value,
+    new: &Value,
+    path: &str,
+    null_deletes: bool,
+    out: &mut Vec<String>,
+) {
+    match (existing, new) {
+        (Value::Object(existing_map), Value::Object(new_map)) => {
+            for (key, new_value) in new_map {
+                if let Some(existing_value) = existing_map.get(key) {
+                    let child_path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    collect_type_mismatches(
+                        existing_value,
+                        new_value,
+                        &child_path,
+                        null_deletes,
+                        out,
+                    );
+                }
+            }
+        }
+        // A delete directive removes the key rather than storing a null
+        (_, Value::Null) if null_deletes => {}
+        (existing, new) if json_type_name(existing) != json_type_name(new) => out.push(format!(
+            "`{}` from {} to {}",
+            path,
+            json_type_name(existing),
+            json_type_name(new)
+        )),
+        _ => {}
+    }
+}
+
+/// Merges `new` into `existing` once any strict type check has passed
+fn merge_values(existing: &mut Value, new: Value, options: MergeOptions) -> Result<()> {
     match (existing, new) {
         (Value::Object(existing_map), Value::Object(new_map)) => {
-            merge_objects(existing_map, new_map)?;
+            merge_objects(existing_map, new_map, options)?;
+        }
+        (Value::Array(existing_items), Value::Array(new_items)) => {
+            merge_arrays(existing_items, new_items, options.strategy);
         }
         (existing, new) => {
             // Replace existing value with new value
@@ -18,16 +177,132 @@ pub fn deep_merge_json(existing: &mut Value, new: Value) -> Result<()> {
     Ok(())
 }
 
+/// Performs deep merge of JSON values and reports every overwritten value
+///
+/// Merges exactly like [`deep_merge_json`], returning a [`MergeConflict`] for
+/// each existing primitive or array that was replaced by a different value.
+/// Objects that merge recursively and newly added keys are not conflicts.
+pub fn deep_merge_json_reporting(existing: &mut Value, new: Value) -> Result<Vec<MergeConflict>> {
+    deep_merge_json_reporting_with_strategy(existing, new, MergeStrategy::ReplaceArrays)
+}
+
+/// Like [`deep_merge_json_reporting`], combining arrays according to `strategy`
+///
+/// With a concatenating strategy, arrays are extended rather than
+/// overwritten, so they are not reported as conflicts.
+pub fn deep_merge_json_reporting_with_strategy(
+    existing: &mut Value,
+    new: Value,
+    strategy: MergeStrategy,
+) -> Result<Vec<MergeConflict>> {
+    deep_merge_json_reporting_with_options(
+        existing,
+        new,
+        MergeOptions {
+            strategy,
+            ..MergeOptions::default()
+        },
+    )
+}
+
+/// Like [`deep_merge_json_reporting`], with full control over merge behavior
+///
+/// With `null_deletes` enabled, a key removed by a `null` is a deletion, not
+/// an overwrite, so it is not reported as a conflict.
+pub fn deep_merge_json_reporting_with_options(
+    existing: &mut Value,
+    new: Value,
+    options: MergeOptions,
+) -> Result<Vec<MergeConflict>> {
+    let mut conflicts = Vec::new();
+    collect_conflicts(existing, &new, "", options, &mut conflicts);
+    deep_merge_json_with_options(existing, new, options)?;
+    Ok(conflicts)
+}
+
+/// Walks both trees and records values the merge would overwrite
+fn collect_conflicts(
+    existing: &Value,
+    new: &Value,
+    path: &str,
+    options: MergeOptions,
+    out: &mut Vec<MergeConflict>,
+) {
+    match (existing, new) {
+        (Value::Object(existing_map), Value::Object(new_map)) => {
+            for (key, new_value) in new_map {
+                if let Some(existing_value) = existing_map.get(key) {
+                    let child_path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    collect_conflicts(existing_value, new_value, &child_path, options, out);
+                }
+            }
+        }
+        // A delete directive removes the key rather than overwriting it
+        (_, Value::Null) if options.null_deletes => {}
+        (Value::Array(_), Value::Array(_))
+            if matches!(
+                options.strategy,
+                MergeStrategy::ConcatArrays | MergeStrategy::ConcatUniqueArrays
+            ) => {}
+        (existing, new) if existing != new => out.push(MergeConflict {
+            path: path.to_string(),
+            old_value: existing.clone(),
+            new_value: new.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// Combines two JSON arrays according to the merge strategy
+fn merge_arrays(existing: &mut Vec<Value>, new: Vec<Value>, strategy: MergeStrategy) {
+    match strategy {
+        MergeStrategy::ReplaceArrays | MergeStrategy::StrictTypes => *existing = new,
+        MergeStrategy::ConcatArrays => existing.extend(new),
+        MergeStrategy::ConcatUniqueArrays => {
+            for item in new {
+                if !existing.contains(&item) {
+                    existing.push(item);
+                }
+            }
+        }
+    }
+}
+
+/// Removes null-valued keys from nested objects, mirroring delete directives
+fn strip_nulls(value: &mut Value) {
+    if let Value::Object(map) = value {
+        map.retain(|_, v| !v.is_null());
+        map.values_mut().for_each(strip_nulls);
+    }
+}
+
 /// Recursively merges two JSON objects
-fn merge_objects(existing: &mut Map<String, Value>, new: Map<String, Value>) -> Result<()> {
-    for (key, new_value) in new {
+fn merge_objects(
+    existing: &mut Map<String, Value>,
+    new: Map<String, Value>,
+    options: MergeOptions,
+) -> Result<()> {
+    for (key, mut new_value) in new {
+        if options.null_deletes && new_value.is_null() {
+            // Shift rather than swap so the remaining keys keep their order
+            existing.shift_remove(&key);
+            continue;
+        }
+
         match existing.get_mut(&key) {
             Some(existing_value) => {
                 // Recursively merge if both are objects
-                deep_merge_json(existing_value, new_value)?;
+                merge_values(existing_value, new_value, options)?;
             }
             None => {
                 // Insert new key-value pair
+                if options.null_deletes {
+                    strip_nulls(&mut new_value);
+                }
                 existing.insert(key, new_value);
             }
         }
@@ -73,6 +348,33 @@ mod tests {
         assert_eq!(existing, expected);
     }
 
+    #[test]
+    fn test_merge_json_leaves_inputs_unchanged() {
+        let existing = json!({"a": {"b": 1, "c": 2}, "list": [1, 2]});
+        let new = json!({"a": {"c": 3}, "list": [3], "d": true});
+        let (existing_before, new_before) = (existing.clone(), new.clone());
+
+        let merged = merge_json(&existing, &new).unwrap();
+
+        assert_eq!(existing, existing_before);
+        assert_eq!(new, new_before);
+        assert_eq!(
+            merged,
+            json!({"a": {"b": 1, "c": 3}, "list": [3], "d": true})
+        );
+    }
+
+    #[test]
+    fn test_merge_json_matches_in_place_merge() {
+        let existing = json!({"settings": {"retries": 3, "hosts": ["a"]}, "model": "sonnet"});
+        let new = json!({"settings": {"hosts": ["b"], "timeout": 5}, "model": "opus"});
+
+        let mut in_place = existing.clone();
+        deep_merge_json(&mut in_place, new.clone()).unwrap();
+
+        assert_eq!(merge_json(&existing, &new).unwrap(), in_place);
+    }
+
     #[test]
     fn test_deep_merge_complex() {
         let mut base = json!({
@@ -147,4 +449,245 @@ mod tests {
 
         assert_eq!(existing, expected);
     }
+
+    #[test]
+    fn test_merge_strategy_replace_arrays() {
+        let mut existing = json!({"permissions": {"allow": ["read", "write"]}});
+        let new = json!({"permissions": {"allow": ["write", "exec"]}});
+
+        deep_merge_json_with_strategy(&mut existing, new, MergeStrategy::ReplaceArrays).unwrap();
+
+        assert_eq!(
+            existing,
+            json!({"permissions": {"allow": ["write", "exec"]}})
+        );
+    }
+
+    #[test]
+    fn test_merge_strategy_concat_arrays() {
+        let mut existing = json!({"permissions": {"allow": ["read", "write"]}});
+        let new = json!({"permissions": {"allow": ["write", "exec"]}});
+
+        deep_merge_json_with_strategy(&mut existing, new, MergeStrategy::ConcatArrays).unwrap();
+
+        assert_eq!(
+            existing,
+            json!({"permissions": {"allow": ["read", "write", "write", "exec"]}})
+        );
+    }
+
+    #[test]
+    fn test_merge_strategy_concat_unique_arrays() {
+        let mut existing = json!({
+            "permissions": {
+                "allow": ["read", "write"],
+                "deny": []
+            }
+        });
+        let new = json!({
+            "permissions": {
+                "allow": ["write", "exec", "exec"],
+                "deny": [{"path": "/etc"}]
+            }
+        });
+
+        deep_merge_json_with_strategy(&mut existing, new, MergeStrategy::ConcatUniqueArrays)
+            .unwrap();
+
+        let expected = json!({
+            "permissions": {
+                "allow": ["read", "write", "exec"],
+                "deny": [{"path": "/etc"}]
+            }
+        });
+        assert_eq!(existing, expected);
+    }
+
+    #[test]
+    fn test_merge_strategy_type_mismatch_replaces() {
+        let mut existing = json!({"allow": ["read"], "deny": "none"});
+        let new = json!({"allow": "all", "deny": ["exec"]});
+
+        deep_merge_json_with_strategy(&mut existing, new, MergeStrategy::ConcatArrays).unwrap();
+
+        assert_eq!(existing, json!({"allow": "all", "deny": ["exec"]}));
+    }
+
+    const NULL_DELETES: MergeOptions = MergeOptions {
+        strategy: MergeStrategy::ReplaceArrays,
+        null_deletes: true,
+    };
+
+    #[test]
+    fn test_merge_strategy_strict_types_rejects_type_change() {
+        let mut existing = json!({"settings": {"retries": 3, "name": "a"}, "tags": ["x"]});
+        let original = existing.clone();
+        let new = json!({"settings": {"retries": "five", "name": "b"}, "tags": {"x": true}});
+
+        let result = deep_merge_json_with_strategy(&mut existing, new, MergeStrategy::StrictTypes);
+
+        match result {
+            Err(TokenProviderError::ValidationError(message)) => {
+                assert!(message.contains("`settings.retries` from a number to a string"));
+                assert!(message.contains("`tags` from an array to an object"));
+                assert!(!message.contains("settings.name"));
+            }
+            other => panic!("expected ValidationError, got {:?}", other),
+        }
+        assert_eq!(existing, original);
+    }
+
+    #[test]
+    fn test_merge_strategy_strict_types_replaces_same_types() {
+        let mut existing = json!({"settings": {"retries": 3}, "tags": ["x"], "name": "a"});
+        let new = json!({"settings": {"retries": 5, "timeout": 10}, "tags": ["y"], "name": "b"});
+
+        deep_merge_json_with_strategy(&mut existing, new, MergeStrategy::StrictTypes).unwrap();
+
+        assert_eq!(
+            existing,
+            json!({"settings": {"retries": 5, "timeout": 10}, "tags": ["y"], "name": "b"})
+        );
+    }
+
+    #[test]
+    fn test_null_deletes_top_level_key() {
+        let mut existing = json!({"keep": 1, "remove": 2});
+
+        deep_merge_json_with_options(&mut existing, json!({"remove": null}), NULL_DELETES).unwrap();
+
+        assert_eq!(existing, json!({"keep": 1}));
+    }
+
+    #[test]
+    fn test_null_deletes_nested_key() {
+        let mut existing = json!({
+            "env": {
+                "CLAUDE_CODE_ENABLE_TELEMETRY": "0",
+                "OLD_TOKEN": "secret"
+            }
+        });
+        let new = json!({
+            "env": {
+                "OLD_TOKEN": null,
+                "NEW_FLAG": "1"
+            },
+            "extra": {"a": null, "b": 2}
+        });
+
+        deep_merge_json_with_options(&mut existing, new, NULL_DELETES).unwrap();
+
+        let expected = json!({
+            "env": {
+                "CLAUDE_CODE_ENABLE_TELEMETRY": "0",
+                "NEW_FLAG": "1"
+            },
+            "extra": {"b": 2}
+        });
+        assert_eq!(existing, expected);
+    }
+
+    #[test]
+    fn test_null_deletes_missing_key_is_noop() {
+        let mut existing = json!({"keep": 1});
+
+        deep_merge_json_with_options(&mut existing, json!({"missing": null}), NULL_DELETES)
+            .unwrap();
+
+        assert_eq!(existing, json!({"keep": 1}));
+    }
+
+    #[test]
+    fn test_null_without_opt_in_is_stored() {
+        let mut existing = json!({"key": 1});
+
+        deep_merge_json(&mut existing, json!({"key": null})).unwrap();
+
+        assert_eq!(existing, json!({"key": null}));
+    }
+
+    #[test]
+    fn test_merge_reporting_conflict_paths() {
+        let mut existing = json!({
+            "config_version": 1,
+            "settings": {
+                "retries": 3,
+                "timeout_ms": 1000,
+                "hosts": ["a"]
+            },
+            "features": {
+                "feature_a": true
+            }
+        });
+        let new = json!({
+            "config_version": 2,
+            "settings": {
+                "hosts": ["b"],
+                "retries": 3,
+                "timeout_ms": 5000,
+                "new_setting": "value"
+            },
+            "features": {
+                "feature_b": true
+            }
+        });
+
+        let conflicts = deep_merge_json_reporting(&mut existing, new).unwrap();
+
+        let paths: Vec<&str> = conflicts.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["config_version", "settings.hosts", "settings.timeout_ms"]
+        );
+        assert_eq!(conflicts[2].old_value, json!(1000));
+        assert_eq!(conflicts[2].new_value, json!(5000));
+        assert_eq!(existing["settings"]["timeout_ms"], json!(5000));
+    }
+
+    #[test]
+    fn test_merge_reporting_concat_arrays_are_not_conflicts() {
+        let mut existing = json!({"allow": ["a"], "model": "old"});
+
+        let conflicts = deep_merge_json_reporting_with_strategy(
+            &mut existing,
+            json!({"allow": ["b"], "model": "new"}),
+            MergeStrategy::ConcatArrays,
+        )
+        .unwrap();
+
+        assert_eq!(existing, json!({"allow": ["a", "b"], "model": "new"}));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "model");
+    }
+
+    #[test]
+    fn test_merge_reporting_skips_delete_directives() {
+        let mut existing = json!({"env": {"OLD": "1", "KEEP": "2"}, "model": "old"});
+
+        let conflicts = deep_merge_json_reporting_with_options(
+            &mut existing,
+            json!({"env": {"OLD": null}, "model": "new"}),
+            MergeOptions {
+                null_deletes: true,
+                ..MergeOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(existing, json!({"env": {"KEEP": "2"}, "model": "new"}));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "model");
+    }
+
+    #[test]
+    fn test_merge_reporting_type_change_is_conflict() {
+        let mut existing = json!({"settings": {"retries": 3}});
+
+        let conflicts =
+            deep_merge_json_reporting(&mut existing, json!({"settings": "off"})).unwrap();
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "settings");
+        assert_eq!(conflicts[0].old_value, json!({"retries": 3}));
+    }
 }
@@ -0,0 +1,205 @@
+//! Config migrations keyed on `config_version`
+//!
+//! When an incoming config carries a newer `config_version` than the one on
+//! disk, registered migrations transform the merged value step by step from
+//! the old version to the new one (for example renaming a key).
+
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use crate::{Result, TokenProviderError};
+
+/// Key holding the config schema version
+pub const VERSION_KEY: &str = "config_version";
+
+/// A single migration step that mutates the merged config in place
+pub type MigrationFn = Box<dyn Fn(&mut Value) -> Result<()> + Send + Sync>;
+
+/// Reads `config_version` from a config, if present
+pub fn config_version(config: &Value) -> Option<u64> {
+    config.get(VERSION_KEY).and_then(Value::as_u64)
+}
+
+/// Migrations indexed by `(from_version, to_version)`
+#[derive(Default)]
+pub struct MigrationRegistry {
+    migrations: BTreeMap<(u64, u64), MigrationFn>,
+}
+
+impl MigrationRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a migration from one version to another
+    pub fn register(
+        &mut self,
+        from_version: u64,
+        to_version: u64,
+        migration: impl Fn(&mut Value) -> Result<()> + Send + Sync + 'static,
+    ) {
+        self.migrations
+            .insert((from_version, to_version), Box::new(migration));
+    }
+
+    /// Registers a migration, returning the registry for chaining
+    pub fn with(
+        mut self,
+        from_version: u64,
+        to_version: u64,
+        migration: impl Fn(&mut Value) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.register(from_version, to_version, migration);
+        self
+    }
+
+    /// Whether no migrations are registered
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty()
+    }
+
+    /// Finds the shortest chain of registered steps from `from` to `to`
+    fn path(&self, from: u64, to: u64) -> Option<Vec<(u64, u64)>> {
+        let mut previous: BTreeMap<u64, u64> = BTreeMap::new();
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(version) = queue.pop_front() {
+            if version == to {
+                let mut steps = Vec::new();
+                let mut current = to;
+                while current != from {
+                    let prior = previous[&current];
+                    steps.push((prior, current));
+                    current = prior;
+                }
+                steps.reverse();
+                return Some(steps);
+            }
+
+            for &(step_from, step_to) in self.migrations.keys() {
+                if step_from == version && step_to != from && !previous.contains_key(&step_to) {
+                    previous.insert(step_to, step_from);
+                    queue.push_back(step_to);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Migrates `config` from `from_version` to `to_version`
+    ///
+    /// Does nothing when the versions are equal.
+    ///
+    /// # Returns
+    /// * `Ok(())` - Every step along the path ran successfully
+    /// * `Err(TokenProviderError)` - If no migration path exists or a step fails
+    pub fn migrate(&self, config: &mut Value, from_version: u64, to_version: u64) -> Result<()> {
+        if from_version == to_version {
+            return Ok(());
+        }
+
+        let steps = self.path(from_version, to_version).ok_or_else(|| {
+            TokenProviderError::MigrationError(format!(
+                "no migration path from config_version {} to {}",
+                from_version, to_version
+            ))
+        })?;
+
+        for step in steps {
+            (self.migrations[&step])(config)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MigrationRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MigrationRegistry")
+            .field("steps", &self.migrations.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Registries are equal when they register the same steps
+///
+/// The migration functions themselves cannot be compared, so this lets
+/// options holding a registry keep deriving `PartialEq`.
+impl PartialEq for MigrationRegistry {
+    fn eq(&self, other: &Self) -> bool {
+        self.migrations.keys().eq(other.migrations.keys())
+    }
+}
+
+impl Eq for MigrationRegistry {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rename_timeout(config: &mut Value) -> Result<()> {
+        if let Some(settings) = config.get_mut("settings").and_then(Value::as_object_mut) {
+            if let Some(timeout) = settings.remove("timeout") {
+                settings.insert("timeout_ms".to_string(), timeout);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_migration_runs_between_versions() {
+        let registry = MigrationRegistry::new().with(1, 2, rename_timeout);
+        let mut config = json!({"config_version": 2, "settings": {"timeout": 5000}});
+
+        registry.migrate(&mut config, 1, 2).unwrap();
+
+        assert_eq!(
+            config,
+            json!({"config_version": 2, "settings": {"timeout_ms": 5000}})
+        );
+    }
+
+    #[test]
+    fn test_migration_chains_steps() {
+        let registry = MigrationRegistry::new()
+            .with(1, 2, rename_timeout)
+            .with(2, 3, |config| {
+                config["settings"]["retries"] = json!(3);
+                Ok(())
+            });
+        let mut config = json!({"settings": {"timeout": 5000}});
+
+        registry.migrate(&mut config, 1, 3).unwrap();
+
+        assert_eq!(
+            config,
+            json!({"settings": {"timeout_ms": 5000, "retries": 3}})
+        );
+    }
+
+    #[test]
+    fn test_same_version_is_noop() {
+        let registry = MigrationRegistry::new().with(1, 2, |_| panic!("should not run"));
+        let mut config = json!({"settings": {"timeout": 5000}});
+
+        registry.migrate(&mut config, 2, 2).unwrap();
+
+        assert_eq!(config, json!({"settings": {"timeout": 5000}}));
+    }
+
+    #[test]
+    fn test_missing_path_errors() {
+        let registry = MigrationRegistry::new().with(1, 2, rename_timeout);
+
+        match registry.migrate(&mut json!({}), 2, 4) {
+            Err(TokenProviderError::MigrationError(message)) => {
+                assert!(message.contains("from config_version 2 to 4"))
+            }
+            other => panic!("expected MigrationError, got {:?}", other),
+        }
+    }
+}
@@ -3,10 +3,20 @@
 //! This module handles JSON parsing, deep merging, and file operations
 //! for managing application configuration data.
 
+pub mod diff;
+pub mod expand;
 pub mod file_ops;
+pub mod format;
 pub mod merger;
+pub mod migrate;
+pub mod pointer;
 pub mod types;
 
+pub use diff::*;
+pub use expand::*;
 pub use file_ops::*;
+pub use format::*;
 pub use merger::*;
+pub use migrate::*;
+pub use pointer::*;
 pub use types::*;
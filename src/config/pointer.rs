@@ -0,0 +1,123 @@
+//! JSON Pointer (RFC 6901) targets for merging into a subtree
+//!
+//! A pointer such as `/settings/env` selects where the new config is merged.
+//! The empty pointer `""` is the document root. Within a segment `~1`
+//! stands for `/` and `~0` for `~`. Only object members can be addressed;
+//! array indices are not supported as merge targets.
+
+use serde_json::{Map, Value};
+
+use crate::{Result, TokenProviderError};
+
+/// Splits a JSON Pointer into unescaped object keys
+pub fn parse_pointer(pointer: &str) -> Result<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(TokenProviderError::ValidationError(format!(
+            "JSON pointer `{}` must be empty or start with `/`",
+            pointer
+        )));
+    };
+
+    rest.split('/')
+        .map(|segment| {
+            if segment.contains('~') && !is_valid_escape(segment) {
+                return Err(TokenProviderError::ValidationError(format!(
+                    "JSON pointer `{}` has an invalid `~` escape in `{}`",
+                    pointer, segment
+                )));
+            }
+            Ok(segment.replace("~1", "/").replace("~0", "~"))
+        })
+        .collect()
+}
+
+/// Whether every `~` in a segment starts a `~0` or `~1` escape
+fn is_valid_escape(segment: &str) -> bool {
+    let bytes = segment.as_bytes();
+    bytes
+        .iter()
+        .enumerate()
+        .filter(|(_, &byte)| byte == b'~')
+        .all(|(i, _)| matches!(bytes.get(i + 1), Some(b'0' | b'1')))
+}
+
+/// Wraps `value` in objects so that it sits at `segments` from the root
+///
+/// Merging the result at the root merges `value` at the pointer, creating
+/// any missing intermediate objects.
+pub fn nest_at(value: Value, segments: &[String]) -> Value {
+    segments.iter().rev().fold(value, |inner, key| {
+        let mut map = Map::new();
+        map.insert(key.clone(), inner);
+        Value::Object(map)
+    })
+}
+
+/// Checks that every existing parent along `segments` is an object
+///
+/// A missing member is fine (it will be created), but a parent holding any
+/// other type cannot contain the target and is an error.
+pub fn check_pointer_parents(config: &Value, segments: &[String]) -> Result<()> {
+    let mut current = config;
+    let mut path = String::new();
+
+    for key in segments {
+        let Value::Object(map) = current else {
+            return Err(TokenProviderError::ValidationError(format!(
+                "cannot merge below `{}`: it is not an object",
+                if path.is_empty() { "/" } else { &path }
+            )));
+        };
+        match map.get(key) {
+            Some(child) => current = child,
+            None => return Ok(()),
+        }
+        path.push('/');
+        path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_pointer() {
+        assert!(parse_pointer("").unwrap().is_empty());
+        assert_eq!(parse_pointer("/settings/env").unwrap(), ["settings", "env"]);
+        assert_eq!(parse_pointer("/a~1b/c~0d").unwrap(), ["a/b", "c~d"]);
+        assert!(parse_pointer("settings").is_err());
+        assert!(parse_pointer("/bad~2").is_err());
+    }
+
+    #[test]
+    fn test_nest_at() {
+        let nested = nest_at(
+            json!({"TOKEN": "abc"}),
+            &parse_pointer("/settings/env").unwrap(),
+        );
+
+        assert_eq!(nested, json!({"settings": {"env": {"TOKEN": "abc"}}}));
+    }
+
+    #[test]
+    fn test_check_pointer_parents_rejects_non_object() {
+        let config = json!({"settings": "flat"});
+
+        let result = check_pointer_parents(&config, &parse_pointer("/settings/env").unwrap());
+
+        match result {
+            Err(TokenProviderError::ValidationError(message)) => {
+                assert!(message.contains("`/settings`"), "{}", message)
+            }
+            other => panic!("expected ValidationError, got {:?}", other),
+        }
+    }
+}
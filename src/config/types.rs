@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 
+use crate::{Result, TokenProviderError};
+
 /// Application configuration structure
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AppConfig {
@@ -31,3 +34,57 @@ impl Default for AppConfig {
         }
     }
 }
+
+impl AppConfig {
+    /// Validates that a JSON value matches the `AppConfig` schema
+    ///
+    /// Fails with a descriptive error naming the missing or mistyped field.
+    pub fn validate(config: &Value) -> Result<AppConfig> {
+        AppConfig::deserialize(config).map_err(|e| {
+            TokenProviderError::ValidationError(format!("config does not match the schema: {}", e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_accepts_valid_config() {
+        let config = serde_json::to_value(AppConfig::default()).unwrap();
+
+        assert_eq!(AppConfig::validate(&config).unwrap(), AppConfig::default());
+    }
+
+    #[test]
+    fn test_validate_rejects_missing_field() {
+        let config = json!({
+            "config_version": 2,
+            "settings": {"retries": 5},
+            "features": {}
+        });
+
+        match AppConfig::validate(&config) {
+            Err(TokenProviderError::ValidationError(message)) => {
+                assert!(message.contains("timeout_ms"))
+            }
+            other => panic!("expected ValidationError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_wrong_type() {
+        let config = json!({
+            "config_version": "two",
+            "settings": {"retries": 5, "timeout_ms": 5000},
+            "features": {}
+        });
+
+        assert!(matches!(
+            AppConfig::validate(&config),
+            Err(TokenProviderError::ValidationError(_))
+        ));
+    }
+}
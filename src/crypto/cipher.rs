@@ -0,0 +1,136 @@
+//! Cipher trait for plugging the crate's AEAD into other pipelines
+//!
+//! [`Cipher`] hides the concrete algorithm behind `encrypt`/`decrypt` on
+//! byte slices, so callers can hold a `&dyn Cipher` and tests can swap in
+//! a fake. [`Aes256GcmCipher`] is the implementation backed by this crate.
+
+use super::nonce_guard::{NonceGuard, NonceReusePolicy};
+use super::{decrypt_data, encrypt_data, Algorithm, SecretKey, NONCE_SIZE};
+use crate::Result;
+
+/// An authenticated cipher keyed at construction
+pub trait Cipher {
+    /// Encrypts `data` under `nonce`, returning the ciphertext
+    fn encrypt(&self, nonce: &[u8; NONCE_SIZE], data: &[u8]) -> Result<Vec<u8>>;
+
+    /// Decrypts and authenticates `ciphertext` produced under `nonce`
+    fn decrypt(&self, nonce: &[u8; NONCE_SIZE], ciphertext: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// AES-256-GCM under a validated key
+///
+/// The key is held as a [`SecretKey`], so it is zeroized when the cipher
+/// is dropped and redacted in debug output.
+#[derive(Debug, Clone)]
+pub struct Aes256GcmCipher {
+    key: SecretKey,
+    nonce_guard: Option<(NonceGuard, NonceReusePolicy)>,
+}
+
+impl Aes256GcmCipher {
+    /// Creates a cipher from a validated key
+    pub fn new(key: SecretKey) -> Self {
+        Self {
+            key,
+            nonce_guard: None,
+        }
+    }
+
+    /// Checks every nonce passed to `encrypt` against `guard` before using it
+    pub fn with_nonce_guard(mut self, guard: NonceGuard, policy: NonceReusePolicy) -> Self {
+        self.nonce_guard = Some((guard, policy));
+        self
+    }
+}
+
+impl Cipher for Aes256GcmCipher {
+    fn encrypt(&self, nonce: &[u8; NONCE_SIZE], data: &[u8]) -> Result<Vec<u8>> {
+        if let Some((guard, policy)) = &self.nonce_guard {
+            guard.check_and_record_with(self.key.expose(), nonce, *policy)?;
+        }
+        encrypt_data(data, self.key.expose(), nonce, Algorithm::Aes256Gcm)
+    }
+
+    fn decrypt(&self, nonce: &[u8; NONCE_SIZE], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        decrypt_data(ciphertext, self.key.expose(), nonce, Algorithm::Aes256Gcm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KEY_SIZE;
+    use crate::TokenProviderError;
+
+    /// XORs data with the first nonce byte; only for exercising the trait
+    struct XorCipher;
+
+    impl Cipher for XorCipher {
+        fn encrypt(&self, nonce: &[u8; NONCE_SIZE], data: &[u8]) -> Result<Vec<u8>> {
+            Ok(data.iter().map(|byte| byte ^ nonce[0]).collect())
+        }
+
+        fn decrypt(&self, nonce: &[u8; NONCE_SIZE], ciphertext: &[u8]) -> Result<Vec<u8>> {
+            self.encrypt(nonce, ciphertext)
+        }
+    }
+
+    fn roundtrip(cipher: &dyn Cipher, data: &[u8]) -> Result<Vec<u8>> {
+        let nonce = [7u8; NONCE_SIZE];
+        let ciphertext = cipher.encrypt(&nonce, data)?;
+        cipher.decrypt(&nonce, &ciphertext)
+    }
+
+    #[test]
+    fn test_fake_cipher_through_trait_object() {
+        let ciphertext = XorCipher.encrypt(&[1u8; NONCE_SIZE], b"abc").unwrap();
+
+        assert_eq!(ciphertext, b"`cb");
+        assert_eq!(roundtrip(&XorCipher, b"abc").unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_aes_cipher_matches_free_functions() {
+        let key = [5u8; KEY_SIZE];
+        let nonce = [6u8; NONCE_SIZE];
+        let cipher = Aes256GcmCipher::new(SecretKey::new(key));
+
+        let ciphertext = cipher.encrypt(&nonce, b"config").unwrap();
+
+        assert_eq!(
+            ciphertext,
+            encrypt_data(b"config", &key, &nonce, Algorithm::Aes256Gcm).unwrap()
+        );
+        assert_eq!(roundtrip(&cipher, b"config").unwrap(), b"config");
+    }
+
+    #[test]
+    fn test_aes_cipher_with_guard_refuses_reused_nonce() {
+        let dir = tempfile::tempdir().unwrap();
+        let guard = NonceGuard::new(dir.path().join("nonce_history"));
+        let cipher = Aes256GcmCipher::new(SecretKey::new([5u8; KEY_SIZE]))
+            .with_nonce_guard(guard, NonceReusePolicy::Refuse);
+        let nonce = [6u8; NONCE_SIZE];
+
+        cipher.encrypt(&nonce, b"first").unwrap();
+
+        assert!(cipher.encrypt(&nonce, b"second").is_err());
+        assert!(cipher.encrypt(&[7u8; NONCE_SIZE], b"second").is_ok());
+    }
+
+    #[test]
+    fn test_aes_cipher_rejects_wrong_key() {
+        let nonce = [6u8; NONCE_SIZE];
+        let ciphertext = Aes256GcmCipher::new(SecretKey::new([5u8; KEY_SIZE]))
+            .encrypt(&nonce, b"config")
+            .unwrap();
+
+        let result =
+            Aes256GcmCipher::new(SecretKey::new([9u8; KEY_SIZE])).decrypt(&nonce, &ciphertext);
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+}
@@ -0,0 +1,185 @@
+//! Self-describing ciphertext container
+//!
+//! Bundles the nonce and algorithm with the ciphertext so they cannot be
+//! mismatched when passed around separately. The layout is:
+//!
+//! | Bytes  | Field                          |
+//! |--------|--------------------------------|
+//! | 0..4   | Magic `CTPC`                   |
+//! | 4      | Algorithm id (`Algorithm::id`) |
+//! | 5      | Key version (see `keyring`)    |
+//! | 6..18  | 12-byte nonce                  |
+//! | 18..   | Ciphertext (including tag)     |
+
+use super::{Algorithm, NONCE_SIZE};
+use crate::{Result, TokenProviderError};
+
+/// Magic bytes identifying a container
+pub const MAGIC: &[u8; 4] = b"CTPC";
+
+/// Key version recorded by [`pack`] when no keyring is involved
+pub const DEFAULT_KEY_VERSION: u8 = 0;
+
+/// Size of the fixed header preceding the ciphertext
+pub const HEADER_SIZE: usize = MAGIC.len() + 2 + NONCE_SIZE;
+
+/// The parts of an unpacked container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unpacked {
+    /// Cipher the payload was encrypted with
+    pub algorithm: Algorithm,
+    /// Version of the key the payload was encrypted with
+    pub key_version: u8,
+    /// Nonce the payload was encrypted under
+    pub nonce: [u8; NONCE_SIZE],
+    /// Encrypted payload, including the authentication tag
+    pub ciphertext: Vec<u8>,
+}
+
+/// Serializes a nonce, algorithm, and ciphertext into a container
+///
+/// The key version is recorded as [`DEFAULT_KEY_VERSION`].
+pub fn pack(nonce: &[u8; NONCE_SIZE], algorithm: Algorithm, ciphertext: &[u8]) -> Vec<u8> {
+    pack_with_key_version(nonce, algorithm, DEFAULT_KEY_VERSION, ciphertext)
+}
+
+/// Serializes a container that records which key version encrypted it
+pub fn pack_with_key_version(
+    nonce: &[u8; NONCE_SIZE],
+    algorithm: Algorithm,
+    key_version: u8,
+    ciphertext: &[u8],
+) -> Vec<u8> {
+    let mut output = Vec::with_capacity(HEADER_SIZE + ciphertext.len());
+    output.extend_from_slice(MAGIC);
+    output.push(algorithm.id());
+    output.push(key_version);
+    output.extend_from_slice(nonce);
+    output.extend_from_slice(ciphertext);
+    output
+}
+
+/// Parses a container produced by [`pack`]
+///
+/// # Returns
+/// * `Ok(Unpacked)` - The algorithm, key version, nonce, and ciphertext
+/// * `Err(TokenProviderError)` - If the buffer is truncated, the magic is
+///   wrong, or the algorithm id is unknown
+pub fn unpack(bytes: &[u8]) -> Result<Unpacked> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(TokenProviderError::crypto(format!(
+            "Container too short: expected at least {} header bytes, got {}",
+            HEADER_SIZE,
+            bytes.len()
+        )));
+    }
+
+    let (magic, rest) = bytes.split_at(MAGIC.len());
+    if magic != MAGIC {
+        return Err(TokenProviderError::crypto(format!(
+            "Not a container: expected magic {:?}, found {:?}",
+            MAGIC, magic
+        )));
+    }
+
+    let algorithm = Algorithm::from_id(rest[0]).ok_or_else(|| {
+        let supported: Vec<String> = Algorithm::ALL
+            .iter()
+            .map(|algorithm| format!("{} = {:?}", algorithm.id(), algorithm))
+            .collect();
+        TokenProviderError::crypto(format!(
+            "Unknown container algorithm id: {} (supported: {})",
+            rest[0],
+            supported.join(", ")
+        ))
+    })?;
+
+    let key_version = rest[1];
+    let (nonce_bytes, ciphertext) = rest[2..].split_at(NONCE_SIZE);
+    let mut nonce = [0u8; NONCE_SIZE];
+    nonce.copy_from_slice(nonce_bytes);
+
+    Ok(Unpacked {
+        algorithm,
+        key_version,
+        nonce,
+        ciphertext: ciphertext.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{decrypt_data, encrypt_data, KEY_SIZE};
+
+    #[test]
+    fn test_pack_unpack_roundtrip() {
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+
+        for algorithm in [
+            Algorithm::Aes256Gcm,
+            Algorithm::ChaCha20Poly1305,
+            Algorithm::Aes256GcmSiv,
+        ] {
+            let ciphertext = encrypt_data(b"Hello, World!", &key, &nonce, algorithm).unwrap();
+            let container = pack(&nonce, algorithm, &ciphertext);
+            assert_eq!(container.len(), HEADER_SIZE + ciphertext.len());
+
+            let unpacked = unpack(&container).unwrap();
+            assert_eq!(unpacked.algorithm, algorithm);
+            assert_eq!(unpacked.key_version, DEFAULT_KEY_VERSION);
+            assert_eq!(unpacked.nonce, nonce);
+
+            let plaintext =
+                decrypt_data(&unpacked.ciphertext, &key, &unpacked.nonce, algorithm).unwrap();
+            assert_eq!(plaintext, b"Hello, World!");
+        }
+    }
+
+    #[test]
+    fn test_key_version_roundtrip() {
+        let container = pack_with_key_version(&[0u8; NONCE_SIZE], Algorithm::Aes256Gcm, 7, b"data");
+
+        let unpacked = unpack(&container).unwrap();
+        assert_eq!(unpacked.key_version, 7);
+        assert_eq!(unpacked.ciphertext, b"data");
+    }
+
+    #[test]
+    fn test_unpack_bad_magic() {
+        let mut container = pack(&[0u8; NONCE_SIZE], Algorithm::Aes256Gcm, b"data");
+        container[0] = b'X';
+
+        match unpack(&container) {
+            Err(TokenProviderError::CryptoError { message, .. }) => {
+                assert!(message.contains("magic"))
+            }
+            other => panic!("expected CryptoError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unpack_truncated_header() {
+        let container = pack(&[0u8; NONCE_SIZE], Algorithm::Aes256Gcm, b"");
+
+        let result = unpack(&container[..HEADER_SIZE - 1]);
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unpack_unknown_algorithm() {
+        let mut container = pack(&[0u8; NONCE_SIZE], Algorithm::Aes256Gcm, b"data");
+        container[MAGIC.len()] = 0xff;
+
+        match unpack(&container) {
+            Err(TokenProviderError::CryptoError { message, .. }) => {
+                assert!(message.contains("255"))
+            }
+            other => panic!("expected CryptoError, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Passphrase-based key derivation
+//!
+//! Derives 32-byte encryption keys from human-memorable passphrases
+//! using Argon2id, so users don't have to paste raw Base64 keys. Keys can
+//! also be bound to a machine by mixing in a local secret file with HKDF.
+
+use argon2::{Algorithm as Argon2Algorithm, Argon2, Params, Version};
+use hkdf::Hkdf;
+use sha3::Sha3_256;
+use std::fs;
+use std::io;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+use super::{SecretKey, KEY_SIZE};
+use crate::{Result, TokenProviderError};
+
+/// Argon2 memory cost in KiB (19 MiB, the OWASP recommended minimum)
+pub const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+/// Argon2 number of iterations (time cost)
+pub const ARGON2_ITERATIONS: u32 = 2;
+/// Argon2 degree of parallelism (lanes)
+pub const ARGON2_PARALLELISM: u32 = 1;
+
+/// Default salt used when the caller does not configure one
+pub const DEFAULT_SALT: &[u8] = b"claude-token-provider";
+
+/// Argon2id cost parameters
+///
+/// The defaults follow the OWASP recommendation; slow devices and tests can
+/// lower them. Keys derived with different parameters are different keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    pub mem_kib: u32,
+    /// Number of iterations (time cost)
+    pub iterations: u32,
+    /// Degree of parallelism (lanes)
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            mem_kib: ARGON2_MEMORY_KIB,
+            iterations: ARGON2_ITERATIONS,
+            parallelism: ARGON2_PARALLELISM,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Checks the parameters against the ranges Argon2 accepts
+    pub fn validate(&self) -> Result<()> {
+        if !(Params::MIN_P_COST..=Params::MAX_P_COST).contains(&self.parallelism) {
+            return Err(TokenProviderError::crypto(format!(
+                "Argon2 parallelism must be between {} and {}, got {}",
+                Params::MIN_P_COST,
+                Params::MAX_P_COST,
+                self.parallelism
+            )));
+        }
+        if !(Params::MIN_T_COST..=Params::MAX_T_COST).contains(&self.iterations) {
+            return Err(TokenProviderError::crypto(format!(
+                "Argon2 iterations must be between {} and {}, got {}",
+                Params::MIN_T_COST,
+                Params::MAX_T_COST,
+                self.iterations
+            )));
+        }
+        // Argon2 needs at least 8 KiB of memory per lane
+        let min_mem_kib = Params::MIN_M_COST.max(8 * self.parallelism);
+        if !(min_mem_kib..=Params::MAX_M_COST).contains(&self.mem_kib) {
+            return Err(TokenProviderError::crypto(format!(
+                "Argon2 memory cost must be between {} and {} KiB for parallelism {}, got {}",
+                min_mem_kib,
+                Params::MAX_M_COST,
+                self.parallelism,
+                self.mem_kib
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Derives an encryption key from a passphrase using Argon2id
+///
+/// # Arguments
+/// * `passphrase` - The user-supplied passphrase
+/// * `salt` - Salt bytes (at least 8 bytes, as required by Argon2)
+/// * `params` - Argon2id cost parameters; see [`KdfParams::default`]
+///
+/// # Returns
+/// * `Ok(SecretKey)` - A key usable directly by `encrypt_data`/`decrypt_data`
+/// * `Err(TokenProviderError)` - If the parameters or salt are rejected
+pub fn derive_key_from_passphrase(
+    passphrase: &str,
+    salt: &[u8],
+    params: &KdfParams,
+) -> Result<SecretKey> {
+    params.validate()?;
+    let params = Params::new(
+        params.mem_kib,
+        params.iterations,
+        params.parallelism,
+        Some(KEY_SIZE),
+    )
+    .map_err(|e| TokenProviderError::crypto_with_source("invalid Argon2 parameters", e))?;
+    let argon2 = Argon2::new(Argon2Algorithm::Argon2id, Version::V0x13, params);
+
+    let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+    argon2
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| TokenProviderError::crypto_with_source("key derivation failed", e))?;
+
+    Ok(SecretKey::new(*key))
+}
+
+/// HKDF `info` label separating machine-bound keys from other uses of the secret
+pub const MACHINE_KEY_INFO: &[u8] = b"claude-token-provider machine-bound key";
+
+/// Derives a key from a passphrase and a secret file that stays on this machine
+///
+/// The machine secret is the HKDF-SHA3-256 input keying material and the
+/// passphrase is the salt, so the key cannot be reproduced from the
+/// passphrase alone once the ciphertext leaves the machine. The passphrase
+/// is not stretched; the secret file supplies the entropy.
+///
+/// # Returns
+/// * `Ok(SecretKey)` - A key usable directly by `encrypt_data`/`decrypt_data`
+/// * `Err(TokenProviderError)` - If the secret file is missing, unreadable, or empty
+pub fn derive_machine_bound_key(passphrase: &str, machine_secret_path: &Path) -> Result<SecretKey> {
+    let machine_secret = Zeroizing::new(fs::read(machine_secret_path).map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            TokenProviderError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Machine secret file not found at {}",
+                    machine_secret_path.display()
+                ),
+            ))
+        } else {
+            TokenProviderError::IoError(e)
+        }
+    })?);
+    if machine_secret.is_empty() {
+        return Err(TokenProviderError::crypto(format!(
+            "Machine secret file {} is empty",
+            machine_secret_path.display()
+        )));
+    }
+
+    let hkdf = Hkdf::<Sha3_256>::new(Some(passphrase.as_bytes()), &machine_secret);
+    let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+    hkdf.expand(MACHINE_KEY_INFO, key.as_mut())
+        .map_err(|_| TokenProviderError::crypto("machine-bound key derivation failed"))?;
+
+    Ok(SecretKey::new(*key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{decrypt_data, encrypt_data, Algorithm};
+
+    /// Cheap parameters so tests don't pay the full default cost
+    const FAST: KdfParams = KdfParams {
+        mem_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_derivation_is_deterministic() {
+        let first = derive_key_from_passphrase(
+            "correct horse battery staple",
+            DEFAULT_SALT,
+            &KdfParams::default(),
+        )
+        .unwrap();
+        let second = derive_key_from_passphrase(
+            "correct horse battery staple",
+            DEFAULT_SALT,
+            &KdfParams::default(),
+        )
+        .unwrap();
+
+        assert_eq!(first.expose(), second.expose());
+    }
+
+    #[test]
+    fn test_different_salt_yields_different_key() {
+        let first = derive_key_from_passphrase("passphrase", b"salt-one", &FAST).unwrap();
+        let second = derive_key_from_passphrase("passphrase", b"salt-two", &FAST).unwrap();
+
+        assert_ne!(first.expose(), second.expose());
+    }
+
+    #[test]
+    fn test_short_salt_rejected() {
+        let result = derive_key_from_passphrase("passphrase", b"short", &FAST);
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_derived_key_roundtrip() {
+        let key = derive_key_from_passphrase("passphrase", DEFAULT_SALT, &FAST).unwrap();
+        let nonce = [2u8; 12];
+        let plaintext = b"Hello, World!";
+
+        let ciphertext =
+            encrypt_data(plaintext, key.expose(), &nonce, Algorithm::Aes256Gcm).unwrap();
+        let decrypted =
+            decrypt_data(&ciphertext, key.expose(), &nonce, Algorithm::Aes256Gcm).unwrap();
+
+        assert_eq!(plaintext, decrypted.as_slice());
+    }
+
+    #[test]
+    fn test_custom_params_change_the_key() {
+        let fast = derive_key_from_passphrase("passphrase", DEFAULT_SALT, &FAST).unwrap();
+        let slower = KdfParams {
+            iterations: 2,
+            ..FAST
+        };
+        let other = derive_key_from_passphrase("passphrase", DEFAULT_SALT, &slower).unwrap();
+
+        assert_ne!(fast.expose(), other.expose());
+    }
+
+    #[test]
+    fn test_machine_bound_key_is_deterministic() {
+        let dir = tempfile::tempdir().unwrap();
+        let secret_path = dir.path().join("machine.secret");
+        fs::write(&secret_path, [7u8; 32]).unwrap();
+
+        let first = derive_machine_bound_key("passphrase", &secret_path).unwrap();
+        let second = derive_machine_bound_key("passphrase", &secret_path).unwrap();
+        let other = derive_machine_bound_key("other passphrase", &secret_path).unwrap();
+
+        assert_eq!(first.expose(), second.expose());
+        assert_ne!(first.expose(), other.expose());
+
+        fs::write(&secret_path, [8u8; 32]).unwrap();
+        let other_machine = derive_machine_bound_key("passphrase", &secret_path).unwrap();
+        assert_ne!(first.expose(), other_machine.expose());
+    }
+
+    #[test]
+    fn test_machine_bound_key_missing_secret_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let secret_path = dir.path().join("missing.secret");
+
+        match derive_machine_bound_key("passphrase", &secret_path) {
+            Err(TokenProviderError::IoError(e)) => {
+                assert_eq!(e.kind(), io::ErrorKind::NotFound);
+                assert!(e.to_string().contains("missing.secret"));
+            }
+            other => panic!("expected a not-found error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_params_rejected() {
+        let params = KdfParams {
+            mem_kib: 16,
+            iterations: 1,
+            parallelism: 4,
+        };
+
+        match derive_key_from_passphrase("passphrase", DEFAULT_SALT, &params) {
+            Err(TokenProviderError::CryptoError { message, .. }) => {
+                assert!(message.contains("memory cost"), "{}", message);
+                assert!(message.contains("32"), "{}", message);
+            }
+            other => panic!("expected CryptoError, got {:?}", other),
+        }
+
+        let no_iterations = KdfParams {
+            iterations: 0,
+            ..FAST
+        };
+        assert!(no_iterations.validate().is_err());
+    }
+}
@@ -0,0 +1,162 @@
+//! Versioned keys for key rotation
+//!
+//! A [`Keyring`] holds several keys indexed by a one-byte version. Payloads
+//! are packed into a container that records the version of the key that
+//! encrypted them, so ciphertext produced before a rotation still decrypts
+//! as long as its key stays in the keyring.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::container::{pack_with_key_version, unpack};
+use super::{decrypt_data, encrypt_data, Algorithm, SecretKey, KEY_SIZE, NONCE_SIZE};
+use crate::{Result, TokenProviderError};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+
+/// A set of encryption keys indexed by version
+#[derive(Default)]
+pub struct Keyring {
+    keys: BTreeMap<u8, SecretKey>,
+}
+
+impl Keyring {
+    /// Creates an empty keyring
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds (or replaces) the key for `version`
+    pub fn insert(&mut self, version: u8, key: SecretKey) {
+        self.keys.insert(version, key);
+    }
+
+    /// Adds the key for `version`, returning the keyring for chaining
+    pub fn with_key(mut self, version: u8, key: SecretKey) -> Self {
+        self.insert(version, key);
+        self
+    }
+
+    /// Returns the key for `version`, if present
+    pub fn key(&self, version: u8) -> Option<&[u8; KEY_SIZE]> {
+        self.keys.get(&version).map(SecretKey::expose)
+    }
+
+    /// The key versions held, in ascending order
+    pub fn versions(&self) -> impl Iterator<Item = u8> + '_ {
+        self.keys.keys().copied()
+    }
+
+    /// Encrypts `data` with the key for `version` into a container
+    ///
+    /// A fresh random nonce is generated and stored in the container along
+    /// with the algorithm and key version.
+    pub fn encrypt(&self, version: u8, data: &[u8], algorithm: Algorithm) -> Result<Vec<u8>> {
+        let key = self.require_key(version)?;
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let ciphertext = encrypt_data(data, key, &nonce, algorithm)?;
+        Ok(pack_with_key_version(
+            &nonce,
+            algorithm,
+            version,
+            &ciphertext,
+        ))
+    }
+
+    /// Decrypts a container, selecting the key by its recorded version
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` - The decrypted plaintext
+    /// * `Err(TokenProviderError)` - If the container is malformed, no key is
+    ///   held for its version, or authentication fails
+    pub fn decrypt(&self, container: &[u8]) -> Result<Vec<u8>> {
+        let unpacked = unpack(container)?;
+        let key = self.require_key(unpacked.key_version)?;
+
+        decrypt_data(
+            &unpacked.ciphertext,
+            key,
+            &unpacked.nonce,
+            unpacked.algorithm,
+        )
+    }
+
+    /// Returns the key for `version` or a descriptive error
+    fn require_key(&self, version: u8) -> Result<&[u8; KEY_SIZE]> {
+        self.key(version).ok_or_else(|| {
+            TokenProviderError::crypto(format!("No key in keyring for key version {}", version))
+        })
+    }
+}
+
+impl fmt::Debug for Keyring {
+    // Only the versions are shown; key bytes never appear in debug output
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keyring")
+            .field("versions", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyring() -> Keyring {
+        Keyring::new()
+            .with_key(1, SecretKey::new([1u8; KEY_SIZE]))
+            .with_key(2, SecretKey::new([2u8; KEY_SIZE]))
+    }
+
+    #[test]
+    fn test_decrypt_selects_key_by_version() {
+        let keyring = keyring();
+
+        let v1 = keyring
+            .encrypt(1, b"old secret", Algorithm::Aes256Gcm)
+            .unwrap();
+        let v2 = keyring
+            .encrypt(2, b"new secret", Algorithm::ChaCha20Poly1305)
+            .unwrap();
+
+        assert_eq!(keyring.decrypt(&v1).unwrap(), b"old secret");
+        assert_eq!(keyring.decrypt(&v2).unwrap(), b"new secret");
+    }
+
+    #[test]
+    fn test_v1_ciphertext_needs_v1_key() {
+        let v1 = keyring()
+            .encrypt(1, b"old secret", Algorithm::Aes256Gcm)
+            .unwrap();
+
+        // A keyring holding a different key under version 1 cannot decrypt it
+        let rotated = Keyring::new().with_key(1, SecretKey::new([9u8; KEY_SIZE]));
+        assert!(matches!(
+            rotated.decrypt(&v1),
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unknown_version_fails_cleanly() {
+        let keyring = keyring();
+        let v1 = keyring.encrypt(1, b"secret", Algorithm::Aes256Gcm).unwrap();
+
+        let only_v2 = Keyring::new().with_key(2, SecretKey::new([2u8; KEY_SIZE]));
+        match only_v2.decrypt(&v1) {
+            Err(TokenProviderError::CryptoError { message, .. }) => {
+                assert!(message.contains("key version 1"))
+            }
+            other => panic!("expected CryptoError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_debug_hides_key_material() {
+        let debug = format!("{:?}", keyring());
+
+        assert_eq!(debug, "Keyring { versions: [1, 2] }");
+    }
+}
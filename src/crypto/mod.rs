@@ -1,63 +1,225 @@
 //! Cryptographic operations for secure data handling
 //!
-//! This module provides AES-256-GCM encryption and decryption
-//! with proper key validation and error handling.
+//! This module provides AES-256-GCM, AES-256-GCM-SIV, and ChaCha20-Poly1305
+//! encryption and decryption with proper key validation and error handling.
+//!
+//! Decoded keys are returned as [`SecretKey`] and nonces wrapped in
+//! [`Zeroizing`]; both overwrite their bytes with zeros when dropped, and
+//! a `SecretKey` is redacted in debug output. Intermediate decode buffers
+//! are scrubbed the same way, so key material does not linger in memory
+//! after the workflow finishes with it.
 
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng, Payload},
     Aes256Gcm, Key, Nonce,
 };
+use aes_gcm_siv::Aes256GcmSiv;
 use base64::{engine::general_purpose, Engine as _};
+use chacha20poly1305::ChaCha20Poly1305;
+use sha3::{Digest, Sha3_256};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
 
 use crate::errors::{Result, TokenProviderError};
 
+pub mod cipher;
 pub mod constants;
+pub mod container;
+pub mod kdf;
+pub mod keyring;
+pub mod nonce_guard;
+pub mod secret;
+pub mod stream;
+
+pub use cipher::{Aes256GcmCipher, Cipher};
+pub use secret::SecretKey;
 
 /// AES-GCM key size in bytes (256 bits)
 pub const KEY_SIZE: usize = 32;
 /// AES-GCM nonce/IV size in bytes (96 bits)
 pub const NONCE_SIZE: usize = 12;
 
-/// Validates and decodes a base64-encoded key
-pub fn decode_and_validate_key(base64_key: &str) -> Result<[u8; KEY_SIZE]> {
-    let decoded = general_purpose::STANDARD
-        .decode(base64_key)
-        .map_err(TokenProviderError::InvalidBase64)?;
+/// Supported AEAD ciphers
+///
+/// All algorithms use a 32-byte key and a 12-byte nonce, so the same
+/// key/nonce validation applies to any choice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// AES-256-GCM, fastest on CPUs with hardware AES acceleration
+    #[default]
+    Aes256Gcm,
+    /// ChaCha20-Poly1305, preferred on platforms without AES acceleration
+    ChaCha20Poly1305,
+    /// AES-256-GCM-SIV (RFC 8452), resistant to nonce misuse
+    ///
+    /// Reusing a nonce with plain GCM or ChaCha20-Poly1305 exposes the XOR of
+    /// the plaintexts and lets an attacker forge messages. With GCM-SIV a
+    /// reused nonce only reveals whether two messages are identical;
+    /// confidentiality of distinct messages and authenticity are kept. It is
+    /// somewhat slower, since it makes two passes over the data.
+    Aes256GcmSiv,
+}
 
-    if decoded.len() != KEY_SIZE {
-        return Err(TokenProviderError::InvalidKeyLength {
-            actual: decoded.len(),
-        });
+impl Algorithm {
+    /// Every supported algorithm, in container id order
+    pub const ALL: [Algorithm; 3] = [
+        Algorithm::Aes256Gcm,
+        Algorithm::ChaCha20Poly1305,
+        Algorithm::Aes256GcmSiv,
+    ];
+
+    /// Stable one-byte identifier used in serialized containers
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::Aes256Gcm => 1,
+            Algorithm::ChaCha20Poly1305 => 2,
+            Algorithm::Aes256GcmSiv => 3,
+        }
     }
 
-    let mut key = [0u8; KEY_SIZE];
-    key.copy_from_slice(&decoded);
-    Ok(key)
+    /// Looks up an algorithm by its container identifier
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Algorithm::Aes256Gcm),
+            2 => Some(Algorithm::ChaCha20Poly1305),
+            3 => Some(Algorithm::Aes256GcmSiv),
+            _ => None,
+        }
+    }
 }
 
-/// Validates and decodes a base64-encoded nonce/IV
-pub fn decode_and_validate_nonce(base64_nonce: &str) -> Result<[u8; NONCE_SIZE]> {
-    let decoded = general_purpose::STANDARD
-        .decode(base64_nonce)
-        .map_err(TokenProviderError::InvalidBase64)?;
+/// Removes all ASCII whitespace, such as the line breaks in a wrapped key
+///
+/// Neither base64 nor hex uses whitespace, so it is never significant.
+pub fn strip_ascii_whitespace(input: &str) -> Zeroizing<String> {
+    Zeroizing::new(input.chars().filter(|c| !c.is_ascii_whitespace()).collect())
+}
+
+/// Decodes base64 in either the standard or the URL-safe alphabet
+///
+/// Whitespace anywhere in the input is ignored. Standard base64 (`+`/`/`,
+/// padded) is tried first; on failure the input is decoded as unpadded
+/// URL-safe base64 (`-`/`_`), which is what `build.rs` emits. If neither
+/// succeeds the standard decoder's error is returned.
+fn decode_base64(input: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let input = strip_ascii_whitespace(input);
+    match general_purpose::STANDARD.decode(input.as_str()) {
+        Ok(decoded) => Ok(Zeroizing::new(decoded)),
+        Err(standard_error) => general_purpose::URL_SAFE_NO_PAD
+            .decode(input.as_str())
+            .map(Zeroizing::new)
+            .map_err(|_| TokenProviderError::InvalidBase64(standard_error)),
+    }
+}
 
+/// Copies decoded bytes into a fixed-size nonce, checking the length
+fn into_nonce(decoded: &[u8]) -> Result<Zeroizing<[u8; NONCE_SIZE]>> {
     if decoded.len() != NONCE_SIZE {
         return Err(TokenProviderError::InvalidIvLength {
             actual: decoded.len(),
         });
     }
 
-    let mut nonce = [0u8; NONCE_SIZE];
-    nonce.copy_from_slice(&decoded);
+    let mut nonce = Zeroizing::new([0u8; NONCE_SIZE]);
+    nonce.copy_from_slice(decoded);
     Ok(nonce)
 }
 
-/// Encrypts data using AES-256-GCM
+/// Validates and decodes a base64-encoded key
+///
+/// Accepts standard or URL-safe base64. The returned key is redacted in
+/// debug output and zeroed from memory when dropped.
+pub fn decode_and_validate_key(base64_key: &str) -> Result<SecretKey> {
+    SecretKey::from_slice(&decode_base64(base64_key)?)
+}
+
+/// Validates and decodes a base64-encoded nonce/IV
+///
+/// Accepts standard or URL-safe base64. The returned nonce is zeroed from
+/// memory when dropped.
+pub fn decode_and_validate_nonce(base64_nonce: &str) -> Result<Zeroizing<[u8; NONCE_SIZE]>> {
+    into_nonce(&decode_base64(base64_nonce)?)
+}
+
+/// Validates and decodes a hex-encoded key (64 hex digits)
+///
+/// Whitespace is ignored. The returned key is redacted in debug output and
+/// zeroed from memory when dropped.
+pub fn decode_and_validate_key_hex(hex_key: &str) -> Result<SecretKey> {
+    let hex_key = strip_ascii_whitespace(hex_key);
+    SecretKey::from_slice(&Zeroizing::new(hex::decode(hex_key.as_str())?))
+}
+
+/// Validates and decodes a hex-encoded nonce/IV (24 hex digits)
+///
+/// Whitespace is ignored. The returned nonce is zeroed from memory when
+/// dropped.
+pub fn decode_and_validate_nonce_hex(hex_nonce: &str) -> Result<Zeroizing<[u8; NONCE_SIZE]>> {
+    let hex_nonce = strip_ascii_whitespace(hex_nonce);
+    into_nonce(&Zeroizing::new(hex::decode(hex_nonce.as_str())?))
+}
+
+/// Whether `input` looks like the hex encoding of exactly `byte_len` bytes
+///
+/// Whitespace is not counted. Hex output for a key or nonce is never the
+/// same length as its base64 encoding, so this check is unambiguous for
+/// credential input.
+pub fn is_hex_encoded(input: &str, byte_len: usize) -> bool {
+    let mut digits = 0;
+    for c in input.chars().filter(|c| !c.is_ascii_whitespace()) {
+        if !c.is_ascii_hexdigit() {
+            return false;
+        }
+        digits += 1;
+    }
+    digits == byte_len * 2
+}
+
+/// Bytes of the SHA3-256 digest shown as a key fingerprint
+const FINGERPRINT_BYTES: usize = 8;
+
+/// Returns a short hex fingerprint of a key for eyeball verification
+///
+/// The fingerprint is the first 8 bytes of the key's SHA3-256 digest, so it
+/// identifies the key without revealing it.
+pub fn key_fingerprint(key: &[u8; KEY_SIZE]) -> String {
+    hex::encode(&Sha3_256::digest(key)[..FINGERPRINT_BYTES])
+}
+
+/// Compares two byte slices in time independent of their contents
+///
+/// Use this instead of `==` for MACs, fingerprints, and other secrets.
+/// Slices of different lengths compare unequal; only the lengths, which
+/// are not secret, affect the timing.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Decodes a key given as either hex or base64, detecting the encoding
+pub fn decode_key_auto(input: &str) -> Result<SecretKey> {
+    if is_hex_encoded(input, KEY_SIZE) {
+        decode_and_validate_key_hex(input)
+    } else {
+        decode_and_validate_key(input)
+    }
+}
+
+/// Decodes a nonce given as either hex or base64, detecting the encoding
+pub fn decode_nonce_auto(input: &str) -> Result<Zeroizing<[u8; NONCE_SIZE]>> {
+    if is_hex_encoded(input, NONCE_SIZE) {
+        decode_and_validate_nonce_hex(input)
+    } else {
+        decode_and_validate_nonce(input)
+    }
+}
+
+/// Encrypts data using the selected AEAD algorithm
 ///
 /// # Arguments
 /// * `data` - The plaintext data to encrypt
 /// * `key` - 32-byte encryption key
 /// * `nonce` - 12-byte nonce/IV
+/// * `algorithm` - The cipher to encrypt with
 ///
 /// # Returns
 /// * `Ok(Vec<u8>)` - The encrypted ciphertext
@@ -66,22 +228,18 @@ pub fn encrypt_data(
     data: &[u8],
     key: &[u8; KEY_SIZE],
     nonce: &[u8; NONCE_SIZE],
+    algorithm: Algorithm,
 ) -> Result<Vec<u8>> {
-    let key = Key::<Aes256Gcm>::from_slice(key);
-    let cipher = Aes256Gcm::new(key);
-    let nonce = Nonce::from_slice(nonce);
-
-    cipher
-        .encrypt(nonce, data)
-        .map_err(|e| TokenProviderError::CryptoError(e.to_string()))
+    encrypt_data_with_aad(data, key, nonce, &[], algorithm)
 }
 
-/// Decrypts data using AES-256-GCM
+/// Decrypts data using the selected AEAD algorithm
 ///
 /// # Arguments
 /// * `ciphertext` - The encrypted data
 /// * `key` - 32-byte decryption key
 /// * `nonce` - 12-byte nonce/IV used for encryption
+/// * `algorithm` - The cipher the data was encrypted with
 ///
 /// # Returns
 /// * `Ok(Vec<u8>)` - The decrypted plaintext
@@ -90,14 +248,195 @@ pub fn decrypt_data(
     ciphertext: &[u8],
     key: &[u8; KEY_SIZE],
     nonce: &[u8; NONCE_SIZE],
+    algorithm: Algorithm,
+) -> Result<Vec<u8>> {
+    decrypt_data_with_aad(ciphertext, key, nonce, &[], algorithm)
+}
+
+/// Encrypts data and binds it to additional authenticated data (AAD)
+///
+/// The AAD is not encrypted or included in the output, but the same AAD
+/// must be supplied to decrypt. This ties a ciphertext to a context such
+/// as a hostname or config version so it cannot be replayed elsewhere.
+///
+/// # Arguments
+/// * `data` - The plaintext data to encrypt
+/// * `key` - 32-byte encryption key
+/// * `nonce` - 12-byte nonce/IV
+/// * `aad` - Context bytes to authenticate alongside the ciphertext
+/// * `algorithm` - The cipher to encrypt with
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - The encrypted ciphertext
+/// * `Err(TokenProviderError)` - If encryption fails
+pub fn encrypt_data_with_aad(
+    data: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    aad: &[u8],
+    algorithm: Algorithm,
 ) -> Result<Vec<u8>> {
-    let key = Key::<Aes256Gcm>::from_slice(key);
-    let cipher = Aes256Gcm::new(key);
     let nonce = Nonce::from_slice(nonce);
+    let payload = Payload { msg: data, aad };
 
-    cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|e| TokenProviderError::CryptoError(e.to_string()))
+    let result = match algorithm {
+        Algorithm::Aes256Gcm => {
+            Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)).encrypt(nonce, payload)
+        }
+        Algorithm::ChaCha20Poly1305 => {
+            ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key)).encrypt(nonce, payload)
+        }
+        Algorithm::Aes256GcmSiv => {
+            Aes256GcmSiv::new(aes_gcm_siv::Key::<Aes256GcmSiv>::from_slice(key))
+                .encrypt(nonce, payload)
+        }
+    };
+
+    result.map_err(|e| TokenProviderError::crypto_with_source("encryption failed", e))
+}
+
+/// Decrypts data that was bound to additional authenticated data (AAD)
+///
+/// # Arguments
+/// * `ciphertext` - The encrypted data
+/// * `key` - 32-byte decryption key
+/// * `nonce` - 12-byte nonce/IV used for encryption
+/// * `aad` - The same context bytes supplied at encryption time
+/// * `algorithm` - The cipher the data was encrypted with
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - The decrypted plaintext
+/// * `Err(TokenProviderError)` - If the AAD mismatches or authentication fails
+pub fn decrypt_data_with_aad(
+    ciphertext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    aad: &[u8],
+    algorithm: Algorithm,
+) -> Result<Vec<u8>> {
+    let nonce = Nonce::from_slice(nonce);
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+
+    let result = match algorithm {
+        Algorithm::Aes256Gcm => {
+            Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)).decrypt(nonce, payload)
+        }
+        Algorithm::ChaCha20Poly1305 => {
+            ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key)).decrypt(nonce, payload)
+        }
+        Algorithm::Aes256GcmSiv => {
+            Aes256GcmSiv::new(aes_gcm_siv::Key::<Aes256GcmSiv>::from_slice(key))
+                .decrypt(nonce, payload)
+        }
+    };
+
+    result.map_err(|e| TokenProviderError::crypto_with_source("decryption failed", e))
+}
+
+/// Decodes a base64 payload string into ciphertext bytes
+///
+/// Accepts standard or URL-safe base64, like the key and nonce decoders.
+/// Whitespace, such as line wrapping or a trailing newline, is ignored.
+pub fn decode_payload_base64(payload_b64: &str) -> Result<Vec<u8>> {
+    let mut decoded = decode_base64(payload_b64)?;
+    Ok(std::mem::take(&mut *decoded))
+}
+
+/// Decrypts an AES-256-GCM payload given as a base64 string
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - The decrypted plaintext
+/// * `Err(TokenProviderError)` - `InvalidBase64` if the payload does not
+///   decode, otherwise any error from [`decrypt_data`]
+pub fn decrypt_base64(
+    payload_b64: &str,
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+) -> Result<Vec<u8>> {
+    let ciphertext = decode_payload_base64(payload_b64)?;
+    decrypt_data(&ciphertext, key, nonce, Algorithm::Aes256Gcm)
+}
+
+/// Encrypts data under a freshly generated random nonce
+///
+/// A 12-byte nonce is drawn from the OS random number generator and
+/// prepended to the ciphertext, so the output layout is `nonce || ciphertext`.
+/// This avoids the catastrophic nonce reuse of a hardcoded IV.
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - The nonce followed by the encrypted ciphertext
+/// * `Err(TokenProviderError)` - If encryption fails
+pub fn encrypt_data_with_random_nonce(
+    data: &[u8],
+    key: &[u8; KEY_SIZE],
+    algorithm: Algorithm,
+) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+
+    let ciphertext = encrypt_data(data, key, &nonce, algorithm)?;
+
+    let mut output = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+    output.extend_from_slice(&nonce);
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+/// Generates a random key from the OS random number generator
+pub fn generate_key() -> SecretKey {
+    let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+    OsRng.fill_bytes(key.as_mut());
+    SecretKey::new(*key)
+}
+
+/// Decrypts a buffer produced by `encrypt_data_with_random_nonce`
+///
+/// The first 12 bytes of `buf` are taken as the nonce and the remainder
+/// as the ciphertext.
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - The decrypted plaintext
+/// * `Err(TokenProviderError)` - If the buffer is too short or authentication fails
+pub fn decrypt_data_with_prepended_nonce(
+    buf: &[u8],
+    key: &[u8; KEY_SIZE],
+    algorithm: Algorithm,
+) -> Result<Vec<u8>> {
+    if buf.len() < NONCE_SIZE {
+        return Err(TokenProviderError::crypto(format!(
+            "Encrypted buffer too short: expected at least {} bytes for the nonce, got {}",
+            NONCE_SIZE,
+            buf.len()
+        )));
+    }
+
+    let (nonce_bytes, ciphertext) = buf.split_at(NONCE_SIZE);
+    let mut nonce = [0u8; NONCE_SIZE];
+    nonce.copy_from_slice(nonce_bytes);
+
+    decrypt_data(ciphertext, key, &nonce, algorithm)
+}
+
+/// Decrypts a container, taking the cipher and nonce from its header
+///
+/// Payloads encrypted with different algorithms over time can all be
+/// decrypted with the same call; see [`container`] for the layout.
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - The decrypted plaintext
+/// * `Err(TokenProviderError)` - If the container is malformed, names an
+///   unknown algorithm id, or authentication fails
+pub fn decrypt_auto(container: &[u8], key: &[u8; KEY_SIZE]) -> Result<Vec<u8>> {
+    let unpacked = container::unpack(container)?;
+    decrypt_data(
+        &unpacked.ciphertext,
+        key,
+        &unpacked.nonce,
+        unpacked.algorithm,
+    )
 }
 
 #[cfg(test)]
@@ -110,12 +449,150 @@ mod tests {
         let nonce = [2u8; 12];
         let plaintext = b"Hello, World!";
 
-        let ciphertext = encrypt_data(plaintext, &key, &nonce).unwrap();
-        let decrypted = decrypt_data(&ciphertext, &key, &nonce).unwrap();
+        let ciphertext = encrypt_data(plaintext, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        let decrypted = decrypt_data(&ciphertext, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
 
         assert_eq!(plaintext, decrypted.as_slice());
     }
 
+    #[test]
+    fn test_chacha20_encrypt_decrypt_roundtrip() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let plaintext = b"Hello, World!";
+
+        let ciphertext =
+            encrypt_data(plaintext, &key, &nonce, Algorithm::ChaCha20Poly1305).unwrap();
+        let decrypted =
+            decrypt_data(&ciphertext, &key, &nonce, Algorithm::ChaCha20Poly1305).unwrap();
+
+        assert_eq!(plaintext, decrypted.as_slice());
+    }
+
+    #[test]
+    fn test_algorithms_are_not_interchangeable() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let plaintext = b"Hello, World!";
+
+        let ciphertext = encrypt_data(plaintext, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        let result = decrypt_data(&ciphertext, &key, &nonce, Algorithm::ChaCha20Poly1305);
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_tampered_ciphertext_preserves_source() {
+        use std::error::Error as _;
+
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+
+        let mut ciphertext = encrypt_data(b"secret", &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        ciphertext[0] ^= 0x01;
+        let error = decrypt_data(&ciphertext, &key, &nonce, Algorithm::Aes256Gcm).unwrap_err();
+
+        let source = error
+            .source()
+            .expect("decryption error should carry a source");
+        assert!(source.downcast_ref::<aes_gcm::Error>().is_some());
+    }
+
+    #[test]
+    fn test_key_decodes_from_standard_and_url_safe() {
+        let bytes = [0xfbu8; KEY_SIZE];
+        let standard = general_purpose::STANDARD.encode(bytes);
+        let url_safe = general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+        assert!(standard.contains('+') && url_safe.contains('-'));
+
+        assert_eq!(decode_and_validate_key(&standard).unwrap().expose(), &bytes);
+        assert_eq!(decode_and_validate_key(&url_safe).unwrap().expose(), &bytes);
+    }
+
+    #[test]
+    fn test_nonce_decodes_from_standard_and_url_safe() {
+        let bytes = [0xffu8; NONCE_SIZE];
+        let standard = general_purpose::STANDARD.encode(bytes);
+        let url_safe = general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+        assert_ne!(standard, url_safe);
+
+        assert_eq!(*decode_and_validate_nonce(&standard).unwrap(), bytes);
+        assert_eq!(*decode_and_validate_nonce(&url_safe).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_wrapped_credentials_ignore_whitespace() {
+        let bytes: [u8; KEY_SIZE] = std::array::from_fn(|i| i as u8 * 7);
+        let encoded = general_purpose::STANDARD.encode(bytes);
+        let wrapped = format!(
+            " {}\r\n{} \t{}\n",
+            &encoded[..20],
+            &encoded[20..30],
+            &encoded[30..]
+        );
+
+        assert_eq!(decode_and_validate_key(&wrapped).unwrap().expose(), &bytes);
+        assert_eq!(decode_key_auto(&wrapped).unwrap().expose(), &bytes);
+
+        let hex_key = hex::encode(bytes);
+        let wrapped_hex = format!("{}\n{}", &hex_key[..32], &hex_key[32..]);
+        assert_eq!(decode_key_auto(&wrapped_hex).unwrap().expose(), &bytes);
+
+        let nonce = general_purpose::STANDARD.encode([3u8; NONCE_SIZE]);
+        let wrapped_nonce = format!("{}\n {}", &nonce[..8], &nonce[8..]);
+        assert_eq!(
+            *decode_nonce_auto(&wrapped_nonce).unwrap(),
+            [3u8; NONCE_SIZE]
+        );
+    }
+
+    #[test]
+    fn test_decode_valid_hex() {
+        let key = decode_and_validate_key_hex(&"ab".repeat(KEY_SIZE)).unwrap();
+        let nonce = decode_and_validate_nonce_hex(&"0F".repeat(NONCE_SIZE)).unwrap();
+
+        assert_eq!(key.expose(), &[0xabu8; KEY_SIZE]);
+        assert_eq!(*nonce, [0x0fu8; NONCE_SIZE]);
+    }
+
+    #[test]
+    fn test_decode_odd_length_hex() {
+        let result = decode_and_validate_key_hex(&"a".repeat(63));
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::InvalidHex(hex::FromHexError::OddLength))
+        ));
+    }
+
+    #[test]
+    fn test_decode_non_hex_characters() {
+        let result = decode_and_validate_nonce_hex(&"zz".repeat(NONCE_SIZE));
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::InvalidHex(
+                hex::FromHexError::InvalidHexCharacter { c: 'z', .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_decode_auto_detects_encoding() {
+        let bytes = [0x5au8; KEY_SIZE];
+
+        let from_hex = decode_key_auto(&hex::encode(bytes)).unwrap();
+        let from_base64 = decode_key_auto(&general_purpose::STANDARD.encode(bytes)).unwrap();
+        assert_eq!(from_hex.expose(), &bytes);
+        assert_eq!(from_base64.expose(), &bytes);
+
+        let nonce = decode_nonce_auto(&hex::encode([7u8; NONCE_SIZE])).unwrap();
+        assert_eq!(*nonce, [7u8; NONCE_SIZE]);
+    }
+
     #[test]
     fn test_invalid_key_length() {
         let short_key = "dGVzdA=="; // "test" in base64 (4 bytes)
@@ -152,14 +629,269 @@ mod tests {
         let nonce = [2u8; 12];
         let plaintext = b"Hello, World!";
 
-        let mut ciphertext = encrypt_data(plaintext, &key, &nonce).unwrap();
+        let mut ciphertext = encrypt_data(plaintext, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+
+        // Tamper with the ciphertext
+        if let Some(byte) = ciphertext.get_mut(0) {
+            *byte = byte.wrapping_add(1);
+        }
+
+        let result = decrypt_data(&ciphertext, &key, &nonce, Algorithm::Aes256Gcm);
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_gcm_siv_encrypt_decrypt_roundtrip() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let plaintext = b"Hello, World!";
+
+        let ciphertext = encrypt_data(plaintext, &key, &nonce, Algorithm::Aes256GcmSiv).unwrap();
+        let decrypted = decrypt_data(&ciphertext, &key, &nonce, Algorithm::Aes256GcmSiv).unwrap();
+
+        assert_eq!(plaintext, decrypted.as_slice());
+        assert_eq!(
+            Algorithm::from_id(Algorithm::Aes256GcmSiv.id()),
+            Some(Algorithm::Aes256GcmSiv)
+        );
+    }
+
+    #[test]
+    fn test_gcm_siv_nonce_reuse_does_not_leak_plaintext_xor() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let first = b"token=aaaaaaaaaaaaaaaa";
+        let second = b"token=bbbbbbbbbbbbbbbb";
+        let xor = |a: &[u8], b: &[u8]| a.iter().zip(b).map(|(x, y)| x ^ y).collect::<Vec<_>>();
+
+        // With plain GCM, a reused nonce exposes the XOR of the plaintexts
+        let gcm_first = encrypt_data(first, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        let gcm_second = encrypt_data(second, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        assert_eq!(
+            xor(&gcm_first, &gcm_second)[..first.len()],
+            xor(first, second)
+        );
+
+        let siv_first = encrypt_data(first, &key, &nonce, Algorithm::Aes256GcmSiv).unwrap();
+        let siv_second = encrypt_data(second, &key, &nonce, Algorithm::Aes256GcmSiv).unwrap();
+        assert_ne!(
+            xor(&siv_first, &siv_second)[..first.len()],
+            xor(first, second)
+        );
+
+        // Both messages still decrypt and authenticate
+        for (ciphertext, plaintext) in [(&siv_first, &first[..]), (&siv_second, &second[..])] {
+            let decrypted =
+                decrypt_data(ciphertext, &key, &nonce, Algorithm::Aes256GcmSiv).unwrap();
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn test_gcm_siv_authentication_failure() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let mut ciphertext =
+            encrypt_data(b"Hello, World!", &key, &nonce, Algorithm::Aes256GcmSiv).unwrap();
+        ciphertext[0] ^= 0x01;
+
+        let result = decrypt_data(&ciphertext, &key, &nonce, Algorithm::Aes256GcmSiv);
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_chacha20_authentication_failure() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let plaintext = b"Hello, World!";
+
+        let mut ciphertext =
+            encrypt_data(plaintext, &key, &nonce, Algorithm::ChaCha20Poly1305).unwrap();
 
         // Tamper with the ciphertext
         if let Some(byte) = ciphertext.get_mut(0) {
             *byte = byte.wrapping_add(1);
         }
 
-        let result = decrypt_data(&ciphertext, &key, &nonce);
-        assert!(matches!(result, Err(TokenProviderError::CryptoError(_))));
+        let result = decrypt_data(&ciphertext, &key, &nonce, Algorithm::ChaCha20Poly1305);
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_random_nonce_roundtrip() {
+        let key = [1u8; 32];
+        let plaintext = b"Hello, World!";
+
+        let buf = encrypt_data_with_random_nonce(plaintext, &key, Algorithm::Aes256Gcm).unwrap();
+        let decrypted =
+            decrypt_data_with_prepended_nonce(&buf, &key, Algorithm::Aes256Gcm).unwrap();
+
+        assert_eq!(plaintext, decrypted.as_slice());
+    }
+
+    #[test]
+    fn test_random_nonce_outputs_differ() {
+        let key = [1u8; 32];
+        let plaintext = b"Hello, World!";
+
+        let first = encrypt_data_with_random_nonce(plaintext, &key, Algorithm::Aes256Gcm).unwrap();
+        let second = encrypt_data_with_random_nonce(plaintext, &key, Algorithm::Aes256Gcm).unwrap();
+
+        assert_ne!(first, second);
+        assert_ne!(first[..NONCE_SIZE], second[..NONCE_SIZE]);
+    }
+
+    #[test]
+    fn test_prepended_nonce_buffer_too_short() {
+        let key = [1u8; 32];
+        let short_buf = [0u8; NONCE_SIZE - 1];
+
+        let result = decrypt_data_with_prepended_nonce(&short_buf, &key, Algorithm::Aes256Gcm);
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_auto_selects_each_algorithm() {
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+
+        for algorithm in Algorithm::ALL {
+            let ciphertext = encrypt_data(b"Hello, World!", &key, &nonce, algorithm).unwrap();
+            let packed = container::pack(&nonce, algorithm, &ciphertext);
+
+            assert_eq!(decrypt_auto(&packed, &key).unwrap(), b"Hello, World!");
+        }
+    }
+
+    #[test]
+    fn test_decrypt_auto_rejects_unknown_algorithm_id() {
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+        let ciphertext = encrypt_data(b"data", &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        let mut packed = container::pack(&nonce, Algorithm::Aes256Gcm, &ciphertext);
+        packed[container::MAGIC.len()] = 9;
+
+        match decrypt_auto(&packed, &key) {
+            Err(TokenProviderError::CryptoError { message, .. }) => {
+                assert!(message.contains("algorithm id: 9"), "{}", message);
+                assert!(message.contains("3 = Aes256GcmSiv"), "{}", message);
+            }
+            other => panic!("expected CryptoError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_aad_roundtrip() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let plaintext = b"Hello, World!";
+        let aad = b"host=example;config_version=2";
+
+        let ciphertext =
+            encrypt_data_with_aad(plaintext, &key, &nonce, aad, Algorithm::Aes256Gcm).unwrap();
+        let decrypted =
+            decrypt_data_with_aad(&ciphertext, &key, &nonce, aad, Algorithm::Aes256Gcm).unwrap();
+
+        assert_eq!(plaintext, decrypted.as_slice());
+    }
+
+    #[test]
+    fn test_aad_mismatch_fails() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let plaintext = b"Hello, World!";
+
+        let ciphertext =
+            encrypt_data_with_aad(plaintext, &key, &nonce, b"host-a", Algorithm::Aes256Gcm)
+                .unwrap();
+        let result =
+            decrypt_data_with_aad(&ciphertext, &key, &nonce, b"host-b", Algorithm::Aes256Gcm);
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_empty_aad_matches_plain_functions() {
+        let key = [1u8; 32];
+        let nonce = [2u8; 12];
+        let plaintext = b"Hello, World!";
+
+        let with_aad =
+            encrypt_data_with_aad(plaintext, &key, &nonce, &[], Algorithm::Aes256Gcm).unwrap();
+        let without_aad = encrypt_data(plaintext, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        assert_eq!(with_aad, without_aad);
+
+        let decrypted = decrypt_data(&with_aad, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        assert_eq!(plaintext, decrypted.as_slice());
+    }
+
+    #[test]
+    fn test_key_fingerprint_is_stable_and_distinct() {
+        let first = key_fingerprint(&[1u8; KEY_SIZE]);
+
+        assert_eq!(first, key_fingerprint(&[1u8; KEY_SIZE]));
+        assert_eq!(first.len(), 16);
+        assert_ne!(first, key_fingerprint(&[2u8; KEY_SIZE]));
+    }
+
+    #[test]
+    fn test_decrypt_base64_roundtrip() {
+        let key = [3u8; KEY_SIZE];
+        let nonce = [4u8; NONCE_SIZE];
+        let ciphertext = encrypt_data(b"payload", &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        let encoded = format!("{}\n", general_purpose::STANDARD.encode(&ciphertext));
+
+        assert_eq!(decrypt_base64(&encoded, &key, &nonce).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_decrypt_base64_rejects_invalid_base64() {
+        let result = decrypt_base64("not*base64!", &[3u8; KEY_SIZE], &[4u8; NONCE_SIZE]);
+
+        assert!(matches!(result, Err(TokenProviderError::InvalidBase64(_))));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"fingerprint", b"fingerprint"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"fingerprint", b"fingerprinT"));
+        assert!(!constant_time_eq(b"fingerprint", b"finger"));
+        assert!(!constant_time_eq(b"", b"x"));
+    }
+
+    #[test]
+    fn test_zeroizing_types_through_decrypt_path() {
+        let key: SecretKey =
+            decode_and_validate_key("AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=").unwrap();
+        let nonce: Zeroizing<[u8; NONCE_SIZE]> =
+            decode_and_validate_nonce("AgICAgICAgICAgIC").unwrap();
+        let plaintext = b"Hello, World!";
+
+        let ciphertext =
+            encrypt_data(plaintext, key.expose(), &nonce, Algorithm::Aes256Gcm).unwrap();
+        let decrypted = Zeroizing::new(
+            decrypt_data(&ciphertext, key.expose(), &nonce, Algorithm::Aes256Gcm).unwrap(),
+        );
+
+        assert_eq!(key.expose(), &[1u8; KEY_SIZE]);
+        assert_eq!(*nonce, [2u8; NONCE_SIZE]);
+        assert_eq!(plaintext, decrypted.as_slice());
     }
 }
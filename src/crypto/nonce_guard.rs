@@ -0,0 +1,323 @@
+//! Detection of nonce reuse across runs
+//!
+//! Encrypting two messages under the same key and nonce breaks AES-GCM and
+//! ChaCha20-Poly1305 confidentiality. A [`NonceGuard`] remembers recently
+//! used (key fingerprint, nonce) pairs in a small state file and refuses a
+//! pair it has seen before, or only warns about it under
+//! [`NonceReusePolicy::Warn`].
+//!
+//! Keys are never stored: each is recorded as a SHA3-256 hash salted with a
+//! random value kept in the state file, so the file cannot be used to test
+//! guesses against other installations.
+//!
+//! [`weak_nonce_reason`] separately flags nonces that look typed by hand,
+//! such as the all-zero example value, which are likely to be reused.
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use log::warn;
+use sha3::{Digest, Sha3_256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{constant_time_eq, KEY_SIZE, NONCE_SIZE};
+use crate::config::file_ops::{ensure_config_dir, get_config_path, transactional_write};
+use crate::{Result, TokenProviderError};
+
+/// State file name, stored next to `settings.json`
+pub const NONCE_GUARD_FILE: &str = "nonce_history";
+/// Most recent pairs kept; older entries are dropped first
+pub const MAX_NONCE_ENTRIES: usize = 1024;
+/// Salt size in bytes
+const SALT_SIZE: usize = 16;
+/// Bytes of the salted key hash kept as the fingerprint
+const FINGERPRINT_SIZE: usize = 16;
+
+/// What happens when a (key, nonce) pair has been used before
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum NonceReusePolicy {
+    /// Log a warning and continue
+    Warn,
+    /// Fail before anything is encrypted
+    #[default]
+    Refuse,
+}
+
+/// Records (key fingerprint, nonce) pairs in a state file
+#[derive(Debug, Clone)]
+pub struct NonceGuard {
+    path: PathBuf,
+}
+
+impl NonceGuard {
+    /// Uses the given state file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Uses the state file in the default config directory (`~/.claude`)
+    pub fn with_default_path() -> Result<Self> {
+        let config_path = get_config_path()?;
+        Ok(Self::new(config_path.with_file_name(NONCE_GUARD_FILE)))
+    }
+
+    /// The state file backing this guard
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Fails if `nonce` was already used with `key`, otherwise records the pair
+    ///
+    /// # Returns
+    /// * `Ok(())` - The pair is new and has been recorded
+    /// * `Err(TokenProviderError)` - The pair was seen before, or the state
+    ///   file could not be read or written
+    pub fn check_and_record(&self, key: &[u8; KEY_SIZE], nonce: &[u8; NONCE_SIZE]) -> Result<()> {
+        self.check_and_record_with(key, nonce, NonceReusePolicy::Refuse)
+    }
+
+    /// Like [`NonceGuard::check_and_record`], handling a reused pair per `policy`
+    ///
+    /// Under [`NonceReusePolicy::Warn`] a reused pair is logged and `Ok` is
+    /// returned; it is already recorded, so the history is unchanged.
+    pub fn check_and_record_with(
+        &self,
+        key: &[u8; KEY_SIZE],
+        nonce: &[u8; NONCE_SIZE],
+        policy: NonceReusePolicy,
+    ) -> Result<()> {
+        let (salt, mut entries) = self.load()?;
+        let entry = format!("{} {}", fingerprint(&salt, key), hex::encode(nonce));
+
+        let reused = entries
+            .iter()
+            .any(|seen| constant_time_eq(seen.as_bytes(), entry.as_bytes()));
+        if reused {
+            const MESSAGE: &str = "nonce reuse detected: this nonce was already used with this key";
+            return match policy {
+                NonceReusePolicy::Refuse => {
+                    Err(TokenProviderError::ValidationError(MESSAGE.to_string()))
+                }
+                NonceReusePolicy::Warn => {
+                    warn!("{}; the ciphertexts can be compared", MESSAGE);
+                    Ok(())
+                }
+            };
+        }
+
+        entries.push(entry);
+        if entries.len() > MAX_NONCE_ENTRIES {
+            entries.drain(..entries.len() - MAX_NONCE_ENTRIES);
+        }
+        self.store(&salt, &entries)
+    }
+
+    /// Reads the salt and entries, creating a fresh salt for a missing file
+    fn load(&self) -> Result<([u8; SALT_SIZE], Vec<String>)> {
+        if !self.path.exists() {
+            let mut salt = [0u8; SALT_SIZE];
+            OsRng.fill_bytes(&mut salt);
+            return Ok((salt, Vec::new()));
+        }
+
+        let content = fs::read_to_string(&self.path)?;
+        let mut lines = content.lines();
+        let salt = lines
+            .next()
+            .and_then(|line| hex::decode(line).ok())
+            .and_then(|bytes| <[u8; SALT_SIZE]>::try_from(bytes).ok())
+            .ok_or_else(|| {
+                TokenProviderError::crypto(format!(
+                    "nonce history {} is corrupted",
+                    self.path.display()
+                ))
+            })?;
+
+        Ok((salt, lines.map(str::to_string).collect()))
+    }
+
+    /// Writes the salt on the first line followed by one entry per line
+    ///
+    /// The file is replaced atomically, so an interrupted run cannot lose
+    /// the history.
+    fn store(&self, salt: &[u8; SALT_SIZE], entries: &[String]) -> Result<()> {
+        ensure_config_dir(&self.path)?;
+
+        let mut content = hex::encode(salt);
+        for entry in entries {
+            content.push('\n');
+            content.push_str(entry);
+        }
+        content.push('\n');
+
+        transactional_write(&self.path, content.as_bytes(), |_| Ok(()))
+    }
+}
+
+/// Salted, truncated SHA3-256 hash identifying a key
+fn fingerprint(salt: &[u8], key: &[u8; KEY_SIZE]) -> String {
+    let digest = Sha3_256::new()
+        .chain_update(salt)
+        .chain_update(key)
+        .finalize();
+    hex::encode(&digest[..FINGERPRINT_SIZE])
+}
+
+/// Checks and records a (key, nonce) pair in the default state file
+pub fn check_and_record(key: &[u8; KEY_SIZE], nonce: &[u8; NONCE_SIZE]) -> Result<()> {
+    NonceGuard::with_default_path()?.check_and_record(key, nonce)
+}
+
+/// Describes why a nonce looks hand-picked rather than random, if it does
+///
+/// Flags an all-zero nonce, one made of a short repeated pattern such as
+/// `abab…`, and one built from at most two distinct byte values, such as a
+/// small counter. A random 12-byte nonce matches with negligible
+/// probability, so a hit almost always means a placeholder was used.
+pub fn weak_nonce_reason(nonce: &[u8; NONCE_SIZE]) -> Option<String> {
+    if nonce.iter().all(|&byte| byte == 0) {
+        return Some("it is all zeros".to_string());
+    }
+
+    if let Some(period) = (1..=NONCE_SIZE / 2)
+        .find(|&period| (period..NONCE_SIZE).all(|i| nonce[i] == nonce[i - period]))
+    {
+        return Some(format!("it repeats a {}-byte pattern", period));
+    }
+
+    let mut distinct: Vec<u8> = nonce.to_vec();
+    distinct.sort_unstable();
+    distinct.dedup();
+    if distinct.len() <= 2 {
+        return Some(format!(
+            "it uses only {} distinct byte values",
+            distinct.len()
+        ));
+    }
+
+    None
+}
+
+/// Warns about a weak nonce, or rejects it when `strict` is set
+///
+/// A weak nonce still decrypts correctly, so it is only refused on request;
+/// see [`weak_nonce_reason`].
+pub fn check_nonce_strength(nonce: &[u8; NONCE_SIZE], strict: bool) -> Result<()> {
+    let Some(reason) = weak_nonce_reason(nonce) else {
+        return Ok(());
+    };
+
+    if strict {
+        return Err(TokenProviderError::ValidationError(format!(
+            "Nonce looks hand-picked ({}); use a random nonce",
+            reason
+        )));
+    }
+    warn!(
+        "Nonce looks hand-picked ({}); reusing it across payloads weakens encryption",
+        reason
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; KEY_SIZE] = [1u8; KEY_SIZE];
+    const NONCE: [u8; NONCE_SIZE] = [2u8; NONCE_SIZE];
+
+    #[test]
+    fn test_all_zero_nonce_is_weak() {
+        let zeros = [0u8; NONCE_SIZE];
+
+        assert_eq!(
+            weak_nonce_reason(&zeros).as_deref(),
+            Some("it is all zeros")
+        );
+        assert!(check_nonce_strength(&zeros, false).is_ok());
+        assert!(matches!(
+            check_nonce_strength(&zeros, true),
+            Err(TokenProviderError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_repetitive_nonces_are_weak() {
+        assert!(weak_nonce_reason(&NONCE).is_some());
+        assert_eq!(
+            weak_nonce_reason(b"abcabcabcabc").as_deref(),
+            Some("it repeats a 3-byte pattern")
+        );
+        let mut counter = [0u8; NONCE_SIZE];
+        counter[NONCE_SIZE - 1] = 1;
+        assert!(weak_nonce_reason(&counter).is_some());
+    }
+
+    #[test]
+    fn test_random_looking_nonce_is_not_weak() {
+        let nonce: [u8; NONCE_SIZE] = hex::decode("9f3a1c7e5b20d48e6a0f1b2c")
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        assert_eq!(weak_nonce_reason(&nonce), None);
+        assert!(check_nonce_strength(&nonce, true).is_ok());
+    }
+
+    #[test]
+    fn test_warn_policy_allows_reuse() {
+        let dir = tempfile::tempdir().unwrap();
+        let guard = NonceGuard::new(dir.path().join(NONCE_GUARD_FILE));
+
+        guard
+            .check_and_record_with(&KEY, &NONCE, NonceReusePolicy::Warn)
+            .unwrap();
+        let history = fs::read_to_string(guard.path()).unwrap();
+
+        guard
+            .check_and_record_with(&KEY, &NONCE, NonceReusePolicy::Warn)
+            .unwrap();
+        assert_eq!(fs::read_to_string(guard.path()).unwrap(), history);
+        assert!(guard
+            .check_and_record_with(&KEY, &NONCE, NonceReusePolicy::Refuse)
+            .is_err());
+    }
+
+    #[test]
+    fn test_first_use_recorded_and_reuse_flagged() {
+        let dir = tempfile::tempdir().unwrap();
+        let guard = NonceGuard::new(dir.path().join(NONCE_GUARD_FILE));
+
+        guard.check_and_record(&KEY, &NONCE).unwrap();
+        assert!(guard.path().exists());
+
+        match guard.check_and_record(&KEY, &NONCE) {
+            Err(TokenProviderError::ValidationError(message)) => {
+                assert!(message.contains("nonce reuse"))
+            }
+            other => panic!("expected ValidationError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_same_nonce_with_other_key_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        let guard = NonceGuard::new(dir.path().join(NONCE_GUARD_FILE));
+
+        guard.check_and_record(&KEY, &NONCE).unwrap();
+        guard.check_and_record(&[9u8; KEY_SIZE], &NONCE).unwrap();
+        guard.check_and_record(&KEY, &[3u8; NONCE_SIZE]).unwrap();
+    }
+
+    #[test]
+    fn test_state_file_never_contains_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let guard = NonceGuard::new(dir.path().join(NONCE_GUARD_FILE));
+
+        guard.check_and_record(&KEY, &NONCE).unwrap();
+
+        let content = fs::read_to_string(guard.path()).unwrap();
+        assert!(!content.contains(&hex::encode(KEY)));
+        assert_eq!(content.lines().count(), 2);
+    }
+}
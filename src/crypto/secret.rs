@@ -0,0 +1,92 @@
+//! Redacted wrapper for secret key bytes
+//!
+//! [`SecretKey`] never prints its bytes through `Debug` or `Display`, so a
+//! stray `{:?}` in a log line cannot leak the key. The raw bytes are only
+//! reachable through [`SecretKey::expose`], which makes every use explicit.
+//!
+//! Nonces are not wrapped: an AEAD nonce must be unique but is not secret,
+//! and it is stored in the clear alongside the ciphertext.
+
+use std::fmt;
+use zeroize::Zeroize;
+
+use super::KEY_SIZE;
+use crate::{Result, TokenProviderError};
+
+/// A 32-byte encryption key that is redacted when formatted
+///
+/// The bytes are overwritten with zeros when the key is dropped.
+#[derive(Clone)]
+pub struct SecretKey([u8; KEY_SIZE]);
+
+impl SecretKey {
+    /// Wraps raw key bytes
+    pub fn new(bytes: [u8; KEY_SIZE]) -> Self {
+        Self(bytes)
+    }
+
+    /// Copies a key out of a decoded buffer, checking its length
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let bytes: [u8; KEY_SIZE] =
+            bytes
+                .try_into()
+                .map_err(|_| TokenProviderError::InvalidKeyLength {
+                    actual: bytes.len(),
+                })?;
+        Ok(Self(bytes))
+    }
+
+    /// Returns the raw key bytes
+    ///
+    /// Only call this where the bytes are genuinely needed, such as handing
+    /// the key to a cipher.
+    pub fn expose(&self) -> &[u8; KEY_SIZE] {
+        &self.0
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey(****)")
+    }
+}
+
+impl fmt::Display for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretKey(****)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_and_display_redact_key_bytes() {
+        let key = SecretKey::new([0xab; KEY_SIZE]);
+
+        for output in [format!("{:?}", key), format!("{}", key)] {
+            assert_eq!(output, "SecretKey(****)");
+            assert!(!output.contains("171"));
+            assert!(!output.to_lowercase().contains("ab"));
+        }
+    }
+
+    #[test]
+    fn test_from_slice_checks_length() {
+        assert_eq!(
+            SecretKey::from_slice(&[7u8; KEY_SIZE]).unwrap().expose(),
+            &[7u8; KEY_SIZE]
+        );
+        assert!(matches!(
+            SecretKey::from_slice(&[7u8; 16]),
+            Err(TokenProviderError::InvalidKeyLength { actual: 16 })
+        ));
+    }
+}
@@ -0,0 +1,322 @@
+//! Chunked streaming encryption for large payloads
+//!
+//! The one-shot functions in the parent module hold the whole plaintext and
+//! ciphertext in memory. These functions instead process fixed-size chunks
+//! using the STREAM construction (`aead::stream`, big-endian 32-bit counter),
+//! so memory use stays bounded regardless of payload size.
+//!
+//! Each plaintext chunk of `CHUNK_SIZE` bytes becomes a ciphertext chunk of
+//! `CHUNK_SIZE + TAG_SIZE` bytes. The final chunk is always shorter than a
+//! full chunk (possibly empty) and is sealed with a "last block" flag, so
+//! truncating or reordering chunks fails authentication.
+
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::KeyInit;
+use aes_gcm::Aes256Gcm;
+use aes_gcm_siv::Aes256GcmSiv;
+use chacha20poly1305::ChaCha20Poly1305;
+use std::io::{self, Read, Write};
+use zeroize::Zeroizing;
+
+use super::{Algorithm, KEY_SIZE};
+use crate::{Result, TokenProviderError};
+
+/// Plaintext bytes per chunk
+pub const CHUNK_SIZE: usize = 64 * 1024;
+/// Authentication tag appended to every ciphertext chunk
+pub const TAG_SIZE: usize = 16;
+/// Stream nonce size: the 12-byte AEAD nonce minus 5 bytes of counter and flag
+pub const STREAM_NONCE_SIZE: usize = 7;
+
+/// Per-algorithm STREAM encryptor
+enum StreamEncryptor {
+    Aes256Gcm(Box<EncryptorBE32<Aes256Gcm>>),
+    ChaCha20Poly1305(EncryptorBE32<ChaCha20Poly1305>),
+    Aes256GcmSiv(Box<EncryptorBE32<Aes256GcmSiv>>),
+}
+
+impl StreamEncryptor {
+    fn new(key: &[u8; KEY_SIZE], nonce: &[u8; STREAM_NONCE_SIZE], algorithm: Algorithm) -> Self {
+        let nonce = nonce.into();
+        match algorithm {
+            Algorithm::Aes256Gcm => StreamEncryptor::Aes256Gcm(Box::new(EncryptorBE32::from_aead(
+                Aes256Gcm::new(key.into()),
+                nonce,
+            ))),
+            Algorithm::ChaCha20Poly1305 => StreamEncryptor::ChaCha20Poly1305(
+                EncryptorBE32::from_aead(ChaCha20Poly1305::new(key.into()), nonce),
+            ),
+            Algorithm::Aes256GcmSiv => StreamEncryptor::Aes256GcmSiv(Box::new(
+                EncryptorBE32::from_aead(Aes256GcmSiv::new(key.into()), nonce),
+            )),
+        }
+    }
+
+    fn encrypt_next(&mut self, chunk: &[u8]) -> std::result::Result<Vec<u8>, aes_gcm::Error> {
+        match self {
+            StreamEncryptor::Aes256Gcm(encryptor) => encryptor.encrypt_next(chunk),
+            StreamEncryptor::ChaCha20Poly1305(encryptor) => encryptor.encrypt_next(chunk),
+            StreamEncryptor::Aes256GcmSiv(encryptor) => encryptor.encrypt_next(chunk),
+        }
+    }
+
+    fn encrypt_last(self, chunk: &[u8]) -> std::result::Result<Vec<u8>, aes_gcm::Error> {
+        match self {
+            StreamEncryptor::Aes256Gcm(encryptor) => (*encryptor).encrypt_last(chunk),
+            StreamEncryptor::ChaCha20Poly1305(encryptor) => encryptor.encrypt_last(chunk),
+            StreamEncryptor::Aes256GcmSiv(encryptor) => (*encryptor).encrypt_last(chunk),
+        }
+    }
+}
+
+/// Per-algorithm STREAM decryptor
+enum StreamDecryptor {
+    Aes256Gcm(Box<DecryptorBE32<Aes256Gcm>>),
+    ChaCha20Poly1305(DecryptorBE32<ChaCha20Poly1305>),
+    Aes256GcmSiv(Box<DecryptorBE32<Aes256GcmSiv>>),
+}
+
+impl StreamDecryptor {
+    fn new(key: &[u8; KEY_SIZE], nonce: &[u8; STREAM_NONCE_SIZE], algorithm: Algorithm) -> Self {
+        let nonce = nonce.into();
+        match algorithm {
+            Algorithm::Aes256Gcm => StreamDecryptor::Aes256Gcm(Box::new(DecryptorBE32::from_aead(
+                Aes256Gcm::new(key.into()),
+                nonce,
+            ))),
+            Algorithm::ChaCha20Poly1305 => StreamDecryptor::ChaCha20Poly1305(
+                DecryptorBE32::from_aead(ChaCha20Poly1305::new(key.into()), nonce),
+            ),
+            Algorithm::Aes256GcmSiv => StreamDecryptor::Aes256GcmSiv(Box::new(
+                DecryptorBE32::from_aead(Aes256GcmSiv::new(key.into()), nonce),
+            )),
+        }
+    }
+
+    fn decrypt_next(&mut self, chunk: &[u8]) -> std::result::Result<Vec<u8>, aes_gcm::Error> {
+        match self {
+            StreamDecryptor::Aes256Gcm(decryptor) => decryptor.decrypt_next(chunk),
+            StreamDecryptor::ChaCha20Poly1305(decryptor) => decryptor.decrypt_next(chunk),
+            StreamDecryptor::Aes256GcmSiv(decryptor) => decryptor.decrypt_next(chunk),
+        }
+    }
+
+    fn decrypt_last(self, chunk: &[u8]) -> std::result::Result<Vec<u8>, aes_gcm::Error> {
+        match self {
+            StreamDecryptor::Aes256Gcm(decryptor) => (*decryptor).decrypt_last(chunk),
+            StreamDecryptor::ChaCha20Poly1305(decryptor) => decryptor.decrypt_last(chunk),
+            StreamDecryptor::Aes256GcmSiv(decryptor) => (*decryptor).decrypt_last(chunk),
+        }
+    }
+}
+
+/// Reads until `buf` is full or the reader is exhausted
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Encrypts everything from `reader` into `writer` in fixed-size chunks
+///
+/// # Returns
+/// * `Ok(u64)` - Number of ciphertext bytes written
+/// * `Err(TokenProviderError)` - If reading, writing, or encryption fails
+pub fn encrypt_reader(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; STREAM_NONCE_SIZE],
+    algorithm: Algorithm,
+) -> Result<u64> {
+    let mut encryptor = StreamEncryptor::new(key, nonce, algorithm);
+    let mut buffer = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+    let mut written = 0u64;
+    let encrypt_error = |e| TokenProviderError::crypto_with_source("encryption failed", e);
+
+    // A short (possibly empty) read marks the final chunk
+    let last_len = loop {
+        let filled = read_full(&mut reader, &mut buffer)?;
+        if filled < CHUNK_SIZE {
+            break filled;
+        }
+
+        let ciphertext = encryptor.encrypt_next(&buffer[..]).map_err(encrypt_error)?;
+        writer.write_all(&ciphertext)?;
+        written += ciphertext.len() as u64;
+    };
+
+    let ciphertext = encryptor
+        .encrypt_last(&buffer[..last_len])
+        .map_err(encrypt_error)?;
+    writer.write_all(&ciphertext)?;
+    written += ciphertext.len() as u64;
+
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Decrypts a stream produced by [`encrypt_reader`] from `reader` into `writer`
+///
+/// Plaintext is written chunk by chunk as each one authenticates, so on
+/// error `writer` may already hold a verified prefix of the output; callers
+/// that need all-or-nothing behavior should write to a temporary location.
+///
+/// # Returns
+/// * `Ok(u64)` - Number of plaintext bytes written
+/// * `Err(TokenProviderError)` - If any chunk fails authentication, the stream
+///   is truncated, or reading/writing fails
+pub fn decrypt_reader(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; STREAM_NONCE_SIZE],
+    algorithm: Algorithm,
+) -> Result<u64> {
+    let mut decryptor = StreamDecryptor::new(key, nonce, algorithm);
+    let mut buffer = vec![0u8; CHUNK_SIZE + TAG_SIZE];
+    let mut written = 0u64;
+    let mut index = 0usize;
+    let auth_error = |index: usize, e: aes_gcm::Error| {
+        TokenProviderError::crypto_with_source(
+            format!("stream chunk {} failed authentication", index),
+            e,
+        )
+    };
+
+    // Full-size chunks are never the last one
+    let last_len = loop {
+        let filled = read_full(&mut reader, &mut buffer)?;
+        if filled < buffer.len() {
+            break filled;
+        }
+
+        let plaintext = Zeroizing::new(
+            decryptor
+                .decrypt_next(&buffer[..])
+                .map_err(|e| auth_error(index, e))?,
+        );
+        writer.write_all(&plaintext)?;
+        written += plaintext.len() as u64;
+        index += 1;
+    };
+
+    let plaintext = Zeroizing::new(
+        decryptor
+            .decrypt_last(&buffer[..last_len])
+            .map_err(|e| auth_error(index, e))?,
+    );
+    writer.write_all(&plaintext)?;
+    written += plaintext.len() as u64;
+
+    writer.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; KEY_SIZE] = [1u8; KEY_SIZE];
+    const NONCE: [u8; STREAM_NONCE_SIZE] = [2u8; STREAM_NONCE_SIZE];
+
+    fn sample_plaintext(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn encrypt(plaintext: &[u8], algorithm: Algorithm) -> Vec<u8> {
+        let mut ciphertext = Vec::new();
+        encrypt_reader(plaintext, &mut ciphertext, &KEY, &NONCE, algorithm).unwrap();
+        ciphertext
+    }
+
+    #[test]
+    fn test_multi_chunk_roundtrip() {
+        let plaintext = sample_plaintext(CHUNK_SIZE * 3 + 1234);
+
+        for algorithm in [
+            Algorithm::Aes256Gcm,
+            Algorithm::ChaCha20Poly1305,
+            Algorithm::Aes256GcmSiv,
+        ] {
+            let ciphertext = encrypt(&plaintext, algorithm);
+            assert_eq!(ciphertext.len(), plaintext.len() + 4 * TAG_SIZE);
+
+            let mut decrypted = Vec::new();
+            let written =
+                decrypt_reader(&ciphertext[..], &mut decrypted, &KEY, &NONCE, algorithm).unwrap();
+
+            assert_eq!(written, plaintext.len() as u64);
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn test_exact_chunk_multiple_roundtrip() {
+        let plaintext = sample_plaintext(CHUNK_SIZE * 2);
+        let ciphertext = encrypt(&plaintext, Algorithm::Aes256Gcm);
+
+        let mut decrypted = Vec::new();
+        decrypt_reader(
+            &ciphertext[..],
+            &mut decrypted,
+            &KEY,
+            &NONCE,
+            Algorithm::Aes256Gcm,
+        )
+        .unwrap();
+
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_flipped_byte_fails_authentication() {
+        let plaintext = sample_plaintext(CHUNK_SIZE * 2 + 10);
+        let mut ciphertext = encrypt(&plaintext, Algorithm::Aes256Gcm);
+        // Corrupt a byte in the second chunk
+        ciphertext[CHUNK_SIZE + TAG_SIZE + 100] ^= 0x01;
+
+        let result = decrypt_reader(
+            &ciphertext[..],
+            &mut Vec::new(),
+            &KEY,
+            &NONCE,
+            Algorithm::Aes256Gcm,
+        );
+
+        match result {
+            Err(TokenProviderError::CryptoError { message, .. }) => {
+                assert!(message.contains("chunk 1"))
+            }
+            other => panic!("expected CryptoError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_truncated_stream_fails_authentication() {
+        let plaintext = sample_plaintext(CHUNK_SIZE * 2 + 10);
+        let ciphertext = encrypt(&plaintext, Algorithm::Aes256Gcm);
+
+        // Drop the final chunk, leaving only full-size chunks
+        let truncated = &ciphertext[..2 * (CHUNK_SIZE + TAG_SIZE)];
+        let result = decrypt_reader(
+            truncated,
+            &mut Vec::new(),
+            &KEY,
+            &NONCE,
+            Algorithm::Aes256Gcm,
+        );
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::CryptoError { .. })
+        ));
+    }
+}
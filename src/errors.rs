@@ -1,41 +1,222 @@
 use thiserror::Error;
 
+/// Boxed underlying error carried as the `source()` of a wrapping variant
+pub type BoxedSource = Box<dyn std::error::Error + Send + Sync + 'static>;
+
 /// Custom error type for the Token Provider application
 #[derive(Error, Debug)]
 pub enum TokenProviderError {
     #[error("Invalid base64 input: {0}")]
     InvalidBase64(#[from] base64::DecodeError),
 
+    #[error("Invalid hex input: {0}")]
+    InvalidHex(#[from] hex::FromHexError),
+
     #[error("Invalid key length: expected 32 bytes, got {actual}")]
     InvalidKeyLength { actual: usize },
 
     #[error("Invalid IV length: expected 12 bytes, got {actual}")]
     InvalidIvLength { actual: usize },
 
-    #[error("Cryptographic operation failed: {0}")]
-    CryptoError(String),
+    #[error("Cryptographic operation failed: {message}")]
+    CryptoError {
+        message: String,
+        #[source]
+        source: Option<BoxedSource>,
+    },
+
+    #[error("No encrypted configuration payload found")]
+    MissingPayload,
+
+    #[error("Decrypted data is not valid UTF-8: {0}")]
+    InvalidUtf8(#[from] std::string::FromUtf8Error),
 
     #[error("JSON parsing error: {0}")]
     JsonError(#[from] serde_json::Error),
 
-    #[error("I/O operation failed: {0}")]
+    #[error("YAML parsing error: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+
+    #[error("TOML conversion error: {0}")]
+    TomlError(String),
+
+    #[error("Validation failed: {0}")]
+    ValidationError(String),
+
+    #[error("Configuration migration failed: {0}")]
+    MigrationError(String),
+
+    #[error("Environment variable `{name}` referenced at `{path}` is not set")]
+    MissingEnvVar { name: String, path: String },
+
+    #[error("No input received within {0:?}")]
+    PromptTimeout(std::time::Duration),
+
+    #[error("Interrupted by user")]
+    Interrupted,
+
+    #[error("Configuration already exists at {}", .0.display())]
+    ConfigExists(std::path::PathBuf),
+
+    #[error("Home directory could not be determined")]
+    HomeDirUnavailable,
+
+    // The I/O error is left out of the message, since `display_chain`
+    // prints it as the source
+    #[error("I/O operation failed")]
     IoError(#[from] std::io::Error),
 
-    #[error("Self-deletion failed: {0}")]
-    SelfDeletionError(String),
+    #[error("I/O operation failed on {}", .path.display())]
+    FileIo {
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Self-deletion failed: {message}")]
+    SelfDeletionError {
+        message: String,
+        #[source]
+        source: Option<std::io::Error>,
+    },
 }
 
 /// Type alias for Result with our custom error type
 pub type Result<T> = std::result::Result<T, TokenProviderError>;
 
 impl TokenProviderError {
+    /// Creates a `CryptoError` with no underlying cause
+    pub fn crypto(message: impl Into<String>) -> Self {
+        TokenProviderError::CryptoError {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// Creates a `CryptoError` that wraps the error that caused it
+    pub fn crypto_with_source(
+        message: impl Into<String>,
+        source: impl std::error::Error + Send + Sync + 'static,
+    ) -> Self {
+        TokenProviderError::CryptoError {
+            message: message.into(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    /// Creates a `SelfDeletionError` with no underlying cause
+    pub fn self_deletion(message: impl Into<String>) -> Self {
+        TokenProviderError::SelfDeletionError {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// Creates a `SelfDeletionError` that wraps the I/O error that caused it
+    pub fn self_deletion_with_source(message: impl Into<String>, source: std::io::Error) -> Self {
+        TokenProviderError::SelfDeletionError {
+            message: message.into(),
+            source: Some(source),
+        }
+    }
+
+    /// Creates a `FileIo` error naming the file the I/O error happened on
+    pub fn file_io(path: impl Into<std::path::PathBuf>, source: std::io::Error) -> Self {
+        TokenProviderError::FileIo {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// Formats this error followed by each underlying cause, one per line
+    pub fn display_chain(&self) -> String {
+        let mut output = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            output.push_str(&format!("\n  caused by: {}", cause));
+            source = cause.source();
+        }
+        output
+    }
+
     /// Returns whether the error is recoverable
     pub fn is_recoverable(&self) -> bool {
-        match self {
+        matches!(
+            self,
             TokenProviderError::InvalidBase64(_)
-            | TokenProviderError::InvalidKeyLength { .. }
-            | TokenProviderError::InvalidIvLength { .. } => true,
-            _ => false,
+                | TokenProviderError::InvalidHex(_)
+                | TokenProviderError::InvalidKeyLength { .. }
+                | TokenProviderError::InvalidIvLength { .. }
+        )
+    }
+
+    /// Returns the process exit code for this error's category
+    ///
+    /// These codes are a stable contract for scripts that branch on why the
+    /// tool failed. Code 12 is [`crate::workflow::PLAN_NO_CHANGES_EXIT_CODE`],
+    /// a successful outcome rather than an error, listed so the table covers
+    /// every code the binary exits with:
+    ///
+    /// | Code | Category                                            |
+    /// |------|-----------------------------------------------------|
+    /// | 2    | Invalid base64 or hex input                         |
+    /// | 3    | Invalid key or IV length                            |
+    /// | 4    | Cryptographic failure or missing payload            |
+    /// | 5    | Config parsing/serialization (UTF-8/JSON/YAML/TOML) |
+    /// | 6    | I/O failure, prompt timeout, or no home dir         |
+    /// | 7    | Self-deletion failure                               |
+    /// | 8    | Validation failure (schema, input, or nonce policy) |
+    /// | 9    | Config migration failure                            |
+    /// | 10   | Referenced environment variable not set             |
+    /// | 11   | Existing config kept due to `--no-clobber`          |
+    /// | 12   | `plan` found no changes (not an error)              |
+    /// | 130  | Interrupted with Ctrl-C                             |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TokenProviderError::InvalidBase64(_) | TokenProviderError::InvalidHex(_) => 2,
+            TokenProviderError::InvalidKeyLength { .. }
+            | TokenProviderError::InvalidIvLength { .. } => 3,
+            TokenProviderError::CryptoError { .. } | TokenProviderError::MissingPayload => 4,
+            TokenProviderError::InvalidUtf8(_)
+            | TokenProviderError::JsonError(_)
+            | TokenProviderError::YamlError(_)
+            | TokenProviderError::TomlError(_) => 5,
+            TokenProviderError::HomeDirUnavailable
+            | TokenProviderError::PromptTimeout(_)
+            | TokenProviderError::IoError(_)
+            | TokenProviderError::FileIo { .. } => 6,
+            TokenProviderError::SelfDeletionError { .. } => 7,
+            TokenProviderError::ValidationError(_) => 8,
+            TokenProviderError::MigrationError(_) => 9,
+            TokenProviderError::MissingEnvVar { .. } => 10,
+            TokenProviderError::ConfigExists(_) => 11,
+            TokenProviderError::Interrupted => crate::interrupt::INTERRUPTED_EXIT_CODE,
+        }
+    }
+
+    /// Returns the variant name, a stable identifier for machine-readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TokenProviderError::InvalidBase64(_) => "InvalidBase64",
+            TokenProviderError::InvalidHex(_) => "InvalidHex",
+            TokenProviderError::InvalidKeyLength { .. } => "InvalidKeyLength",
+            TokenProviderError::InvalidIvLength { .. } => "InvalidIvLength",
+            TokenProviderError::CryptoError { .. } => "CryptoError",
+            TokenProviderError::MissingPayload => "MissingPayload",
+            TokenProviderError::InvalidUtf8(_) => "InvalidUtf8",
+            TokenProviderError::JsonError(_) => "JsonError",
+            TokenProviderError::YamlError(_) => "YamlError",
+            TokenProviderError::TomlError(_) => "TomlError",
+            TokenProviderError::ValidationError(_) => "ValidationError",
+            TokenProviderError::MigrationError(_) => "MigrationError",
+            TokenProviderError::MissingEnvVar { .. } => "MissingEnvVar",
+            TokenProviderError::ConfigExists(_) => "ConfigExists",
+            TokenProviderError::PromptTimeout(_) => "PromptTimeout",
+            TokenProviderError::Interrupted => "Interrupted",
+            TokenProviderError::HomeDirUnavailable => "HomeDirUnavailable",
+            TokenProviderError::IoError(_) => "IoError",
+            TokenProviderError::FileIo { .. } => "FileIo",
+            TokenProviderError::SelfDeletionError { .. } => "SelfDeletionError",
         }
     }
 
@@ -45,16 +226,186 @@ impl TokenProviderError {
             TokenProviderError::InvalidBase64(_) => {
                 "Please check your base64 input format".to_string()
             }
+            TokenProviderError::InvalidHex(_) => "Please check your hex input format".to_string(),
             TokenProviderError::InvalidKeyLength { .. } => {
                 "Secret key must be exactly 32 bytes when decoded".to_string()
             }
             TokenProviderError::InvalidIvLength { .. } => {
                 "IV/Nonce must be exactly 12 bytes when decoded".to_string()
             }
-            TokenProviderError::CryptoError(_) => {
+            // Only a failed AEAD check points at the key, IV, or data
+            TokenProviderError::CryptoError {
+                source: Some(source),
+                ..
+            } if source.is::<aes_gcm::aead::Error>() => {
                 "Key or IV mismatch, or data corruption detected".to_string()
             }
+            TokenProviderError::MissingPayload => {
+                "No encrypted configuration is available; pass --payload or rebuild with one"
+                    .to_string()
+            }
+            TokenProviderError::InvalidUtf8(_) => {
+                "Payload decrypted but is not UTF-8 text; it may not be a JSON config".to_string()
+            }
+            TokenProviderError::ConfigExists(path) => format!(
+                "{} already exists; drop --no-clobber to merge into it",
+                path.display()
+            ),
+            TokenProviderError::HomeDirUnavailable => {
+                "Could not find your home directory; set HOME or pass --config-path".to_string()
+            }
+            TokenProviderError::IoError(error) => format!("I/O operation failed: {}", error),
+            TokenProviderError::FileIo { path, source } => {
+                format!("I/O operation failed on {}: {}", path.display(), source)
+            }
+            TokenProviderError::PromptTimeout(timeout) => format!(
+                "No input received within {}s; pass the value as a flag or raise --prompt-timeout",
+                timeout.as_secs()
+            ),
             _ => self.to_string(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_stable() {
+        let json_error = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let yaml_error = serde_yaml::from_str::<serde_yaml::Value>("a: [").unwrap_err();
+        let utf8_error = String::from_utf8(vec![0xff]).unwrap_err();
+
+        let cases = [
+            (
+                TokenProviderError::InvalidBase64(base64::DecodeError::InvalidPadding),
+                2,
+            ),
+            (
+                TokenProviderError::InvalidHex(hex::FromHexError::OddLength),
+                2,
+            ),
+            (TokenProviderError::InvalidKeyLength { actual: 4 }, 3),
+            (TokenProviderError::InvalidIvLength { actual: 4 }, 3),
+            (TokenProviderError::crypto("tampered"), 4),
+            (TokenProviderError::MissingPayload, 4),
+            (TokenProviderError::InvalidUtf8(utf8_error), 5),
+            (TokenProviderError::JsonError(json_error), 5),
+            (TokenProviderError::YamlError(yaml_error), 5),
+            (TokenProviderError::TomlError("null".to_string()), 5),
+            (
+                TokenProviderError::IoError(std::io::Error::other("disk full")),
+                6,
+            ),
+            (
+                TokenProviderError::file_io("settings.json", std::io::Error::other("denied")),
+                6,
+            ),
+            (TokenProviderError::HomeDirUnavailable, 6),
+            (
+                TokenProviderError::PromptTimeout(std::time::Duration::from_secs(30)),
+                6,
+            ),
+            (TokenProviderError::self_deletion("locked"), 7),
+            (
+                TokenProviderError::ValidationError("missing".to_string()),
+                8,
+            ),
+            (TokenProviderError::MigrationError("no path".to_string()), 9),
+            (
+                TokenProviderError::MissingEnvVar {
+                    name: "HOST".to_string(),
+                    path: "api_base".to_string(),
+                },
+                10,
+            ),
+            (TokenProviderError::ConfigExists("settings.json".into()), 11),
+            (TokenProviderError::Interrupted, 130),
+        ];
+
+        for (error, expected) in cases {
+            assert_eq!(error.exit_code(), expected, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_not_recoverable() {
+        let error: TokenProviderError = String::from_utf8(vec![0xff]).unwrap_err().into();
+
+        assert!(!error.is_recoverable());
+        assert!(error.user_message().contains("not UTF-8"));
+    }
+
+    #[test]
+    fn test_crypto_user_message_names_the_problem() {
+        let failed_tag =
+            TokenProviderError::crypto_with_source("decryption failed", aes_gcm::aead::Error);
+        assert_eq!(
+            failed_tag.user_message(),
+            "Key or IV mismatch, or data corruption detected"
+        );
+
+        let bad_magic = TokenProviderError::crypto("Not a container: expected magic");
+        assert_eq!(
+            bad_magic.user_message(),
+            "Cryptographic operation failed: Not a container: expected magic"
+        );
+    }
+
+    #[test]
+    fn test_home_dir_unavailable_suggests_fix() {
+        let message = TokenProviderError::HomeDirUnavailable.user_message();
+
+        assert!(message.contains("HOME"));
+        assert!(message.contains("--config-path"));
+    }
+
+    #[test]
+    fn test_display_chain_includes_sources() {
+        let error = TokenProviderError::self_deletion_with_source(
+            "failed to delete executable",
+            std::io::Error::other("file in use"),
+        );
+
+        assert_eq!(
+            error.display_chain(),
+            "Self-deletion failed: failed to delete executable\n  caused by: file in use"
+        );
+    }
+
+    #[test]
+    fn test_display_chain_shows_io_error_once() {
+        let error = TokenProviderError::file_io(
+            "settings.json",
+            std::io::Error::other("permission denied"),
+        );
+        assert!(std::error::Error::source(&error).is_some());
+        assert_eq!(
+            error.display_chain(),
+            "I/O operation failed on settings.json\n  caused by: permission denied"
+        );
+        assert_eq!(
+            error.user_message(),
+            "I/O operation failed on settings.json: permission denied"
+        );
+
+        let error = TokenProviderError::from(std::io::Error::other("disk full"));
+        assert!(std::error::Error::source(&error).is_some());
+        assert_eq!(
+            error.display_chain(),
+            "I/O operation failed\n  caused by: disk full"
+        );
+        assert_eq!(error.user_message(), "I/O operation failed: disk full");
+    }
+
+    #[test]
+    fn test_display_chain_without_source() {
+        let error = TokenProviderError::crypto("empty payload");
+
+        assert_eq!(
+            error.display_chain(),
+            "Cryptographic operation failed: empty payload"
+        );
+    }
+}
@@ -1,15 +1,215 @@
-use rpassword::prompt_password;
-use std::io::{self, Write};
+use base64::{engine::general_purpose, Engine as _};
+use std::borrow::Cow;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use zeroize::{Zeroize, Zeroizing};
 
-use crate::crypto::{decode_and_validate_key, decode_and_validate_nonce, KEY_SIZE, NONCE_SIZE};
-use crate::{Result, TokenProviderError};
+use crate::crypto::constants::ENCRYPTED_CONFIG;
+use crate::crypto::kdf::{derive_key_from_passphrase, KdfParams};
+use crate::crypto::{
+    decode_key_auto, decode_nonce_auto, is_hex_encoded, key_fingerprint, strip_ascii_whitespace,
+    SecretKey, KEY_SIZE, NONCE_SIZE,
+};
+use crate::{interrupt, Result, TokenProviderError};
+
+/// Environment variable holding the Base64 secret key for non-interactive runs
+pub const KEY_ENV_VAR: &str = "CLAUDE_TOKEN_KEY";
+/// Environment variable holding the Base64 IV/Nonce for non-interactive runs
+pub const NONCE_ENV_VAR: &str = "CLAUDE_TOKEN_NONCE";
+
+/// Environment variable pointing at an encrypted payload file
+pub const PAYLOAD_ENV_VAR: &str = "CLAUDE_TOKEN_PAYLOAD";
+
+/// Maximum size accepted for a key file, to avoid reading huge blobs
+pub const MAX_KEY_FILE_SIZE: u64 = 1024;
+
+/// Whether an environment flag value is truthy (`1`, `true`, or `yes`)
+pub fn is_truthy(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "1" | "true" | "yes"
+    )
+}
+
+#[cfg(unix)]
+mod tty;
+
+/// Prompts for hidden input, giving up after `timeout` or on Ctrl-C
+///
+/// On Unix the terminal is polled before the hidden read starts, so a
+/// timeout or interrupt leaves nothing blocked and echo is restored.
+/// Elsewhere the prompt goes through [`read_input`], and an interrupt is
+/// noticed once the read returns.
+fn prompt_hidden(prompt: &'static str, timeout: Option<Duration>) -> Result<Zeroizing<String>> {
+    #[cfg(unix)]
+    let input = tty::prompt_password(prompt, timeout)?;
+    #[cfg(not(unix))]
+    let input = read_input(move || rpassword::prompt_password(prompt), timeout)?;
+
+    interrupt::check()?;
+    Ok(input)
+}
+
+/// Reads a line of stdin, returning early with `Interrupted` on Ctrl-C
+///
+/// Used for the yes/no confirmations. On Unix a terminal stdin is polled
+/// first so the wait can be cut short.
+pub fn read_stdin_line() -> Result<String> {
+    #[cfg(unix)]
+    {
+        use std::io::IsTerminal;
+        use std::os::fd::AsRawFd;
+
+        if io::stdin().is_terminal() {
+            tty::wait_for_input(io::stdin().as_raw_fd(), None)?;
+        }
+    }
+
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    interrupt::check()?;
+    Ok(line)
+}
+
+/// Reads one piece of interactive input, giving up after `timeout`
+///
+/// With a timeout the read runs on a helper thread so the wait is bounded;
+/// if it expires the thread is left blocked on stdin and ends with the
+/// process. Without a timeout `read` runs inline.
+#[cfg(any(not(unix), test))]
+fn read_input<F>(read: F, timeout: Option<Duration>) -> Result<Zeroizing<String>>
+where
+    F: FnOnce() -> io::Result<String> + Send + 'static,
+{
+    use std::sync::mpsc::{self, RecvTimeoutError};
+
+    let Some(timeout) = timeout else {
+        return Ok(Zeroizing::new(read()?));
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(read());
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => Ok(Zeroizing::new(result?)),
+        Err(RecvTimeoutError::Timeout) => Err(TokenProviderError::PromptTimeout(timeout)),
+        Err(RecvTimeoutError::Disconnected) => Err(TokenProviderError::IoError(io::Error::other(
+            "input reader stopped unexpectedly",
+        ))),
+    }
+}
+
+/// Default number of prompt attempts before giving up
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Controls how interactive credential prompts wait and retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptOptions {
+    /// Fail if a prompt gets no input within this long; `None` waits indefinitely
+    pub timeout: Option<Duration>,
+    /// Number of invalid entries accepted before giving up
+    pub max_attempts: u32,
+    /// Show the key's fingerprint and re-prompt until the user confirms it
+    pub confirm_fingerprint: bool,
+}
+
+impl Default for PromptOptions {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            confirm_fingerprint: false,
+        }
+    }
+}
+
+/// Reads credential input until it decodes or `max_attempts` is used up
+///
+/// `read` is called with the 1-based attempt number. Format and recoverable
+/// decode errors are reported and retried; anything else, including a read
+/// failure, is returned immediately. Once the attempts run out the last
+/// error is returned.
+fn prompt_until_valid<T>(
+    max_attempts: u32,
+    mut read: impl FnMut(u32) -> Result<Zeroizing<String>>,
+    byte_len: usize,
+    decode: impl Fn(&str) -> Result<T>,
+    expected: &str,
+) -> Result<T> {
+    let mut last_error = None;
+
+    for attempt in 1..=max_attempts.max(1) {
+        let input = read(attempt)?;
+        let input = input.trim();
+
+        // Pre-validate format
+        if !is_hex_encoded(input, byte_len) {
+            if let Err(e) = validate_base64_format(input) {
+                eprintln!("Error: Invalid Base64 format");
+                eprintln!("Please ensure your input contains only valid Base64 characters (A-Z, a-z, 0-9, +, /, -, _, =)\n");
+                last_error = Some(e);
+                continue;
+            }
+        }
+
+        match decode(input) {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                eprintln!("Error: {}", e.user_message());
+                if !e.is_recoverable() {
+                    return Err(e);
+                }
+                eprintln!("{}", expected);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    eprintln!("Error: giving up after {} attempts", max_attempts.max(1));
+    Err(last_error.expect("at least one attempt was made"))
+}
+
+/// Reads and decodes a credential from an environment variable
+///
+/// Returns `Ok(None)` when the variable is unset. A variable that is set
+/// but malformed is an error rather than a signal to fall back to an
+/// interactive prompt, since non-interactive environments have no TTY.
+fn credential_from_env<T>(var: &str, decode: impl Fn(&str) -> Result<T>) -> Result<Option<T>> {
+    match env::var(var) {
+        Ok(value) => {
+            let value = Zeroizing::new(value);
+            decode(value.trim()).map(Some)
+        }
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(TokenProviderError::IoError(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {}", var, e),
+        ))),
+    }
+}
 
 /// Validates base64 input format before attempting decode
+///
+/// Both the standard (`+`/`/`) and URL-safe (`-`/`_`) alphabets are accepted.
+/// Padding may only appear as the final one or two characters, and padded or
+/// standard-alphabet input must be a multiple of 4 characters long. Unpadded
+/// URL-safe input may have any length a base64 encoder can produce.
+/// Whitespace anywhere in the input is ignored, as it is when decoding.
 pub fn validate_base64_format(input: &str) -> Result<()> {
+    let input = strip_ascii_whitespace(input);
+    let input = input.as_str();
+
     // Check for valid base64 characters
     let valid_chars = input
         .chars()
-        .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=');
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '-' | '_' | '='));
 
     if !valid_chars {
         return Err(TokenProviderError::InvalidBase64(
@@ -17,78 +217,382 @@ pub fn validate_base64_format(input: &str) -> Result<()> {
         ));
     }
 
-    // Check padding
+    // Check padding: at most two `=`, and only at the very end
     let padding_count = input.chars().rev().take_while(|&c| c == '=').count();
-    if padding_count > 2 {
+    let body = &input[..input.len() - padding_count];
+    if padding_count > 2 || body.contains('=') {
         return Err(TokenProviderError::InvalidBase64(
             base64::DecodeError::InvalidPadding,
         ));
     }
 
+    // Check length
+    let uses_standard_alphabet = body.contains(['+', '/']);
+    let valid_length = if padding_count > 0 || uses_standard_alphabet {
+        input.len().is_multiple_of(4)
+    } else {
+        input.len() % 4 != 1
+    };
+    if !valid_length {
+        return Err(TokenProviderError::InvalidBase64(
+            base64::DecodeError::InvalidLength(input.len()),
+        ));
+    }
+
     Ok(())
 }
 
 /// Enhanced key input with format validation
-pub fn get_secret_key() -> Result<[u8; KEY_SIZE]> {
-    loop {
-        let key_input = prompt_password("Enter AES-256-GCM Secret Key (Base64): ")
-            .map_err(|e| TokenProviderError::IoError(io::Error::new(io::ErrorKind::Other, e)))?;
+///
+/// Accepts base64 or a 64-digit hex string; the encoding is detected
+/// automatically. Uses `CLAUDE_TOKEN_KEY` without prompting when it is set.
+pub fn get_secret_key() -> Result<SecretKey> {
+    get_secret_key_with_options(&PromptOptions::default())
+}
 
-        // Pre-validate format
-        if let Err(_) = validate_base64_format(&key_input) {
-            eprintln!("Error: Invalid Base64 format");
-            eprintln!("Please ensure your input contains only valid Base64 characters (A-Z, a-z, 0-9, +, /, =)\n");
-            continue;
+/// Like [`get_secret_key`], with the prompt's timeout, attempt limit, and fingerprint check
+pub fn get_secret_key_with_options(options: &PromptOptions) -> Result<SecretKey> {
+    if let Some(key) = credential_from_env(KEY_ENV_VAR, decode_key_auto)? {
+        return Ok(key);
+    }
+
+    let prompt_key = || {
+        prompt_until_valid(
+            options.max_attempts,
+            |_| {
+                prompt_hidden(
+                    "Enter AES-256-GCM Secret Key (Base64 or hex): ",
+                    options.timeout,
+                )
+            },
+            KEY_SIZE,
+            decode_key_auto,
+            "Expected: 44 Base64 characters or 64 hex digits (32 bytes when decoded)\n",
+        )
+    };
+
+    if options.confirm_fingerprint {
+        confirm_key_fingerprint(options.max_attempts, prompt_key, ask_fingerprint_matches)
+    } else {
+        prompt_key()
+    }
+}
+
+/// Reads keys until the user confirms one by its fingerprint
+///
+/// `confirm` is shown the [`key_fingerprint`] of each key from `read_key`
+/// and returns whether it is the one the user expects. A rejected key is
+/// dropped and read again, up to `max_attempts` times.
+fn confirm_key_fingerprint(
+    max_attempts: u32,
+    mut read_key: impl FnMut() -> Result<SecretKey>,
+    mut confirm: impl FnMut(&str) -> Result<bool>,
+) -> Result<SecretKey> {
+    for _ in 0..max_attempts.max(1) {
+        let key = read_key()?;
+        if confirm(&key_fingerprint(key.expose()))? {
+            return Ok(key);
         }
+        eprintln!("Fingerprint rejected; please enter the key again\n");
+    }
 
-        match decode_and_validate_key(&key_input) {
-            Ok(key) => return Ok(key),
-            Err(e) => {
-                eprintln!("Error: {}", e.user_message());
-                if !e.is_recoverable() {
-                    return Err(e);
-                }
-                eprintln!("Expected: 44 Base64 characters (32 bytes when decoded)\n");
+    Err(TokenProviderError::ValidationError(format!(
+        "Key fingerprint was rejected {} time(s)",
+        max_attempts.max(1)
+    )))
+}
+
+/// Shows a key fingerprint on stderr and asks whether it is the expected one
+fn ask_fingerprint_matches(fingerprint: &str) -> Result<bool> {
+    eprint!("Key fingerprint: {}\nDoes this match? [y/N]: ", fingerprint);
+    io::stderr().flush()?;
+
+    let answer = read_stdin_line()?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// Loads the encrypted configuration payload
+///
+/// Reads the raw ciphertext from `path` when one is given, so a new payload
+/// does not require a recompile. Without a path the compiled-in
+/// `ENCRYPTED_CONFIG` constant is used.
+pub fn load_encrypted_payload(path: Option<&Path>) -> Result<Cow<'static, [u8]>> {
+    match path {
+        Some(path) => Ok(Cow::Owned(
+            fs::read(path).map_err(|e| TokenProviderError::file_io(path, e))?,
+        )),
+        None => Ok(Cow::Borrowed(ENCRYPTED_CONFIG)),
+    }
+}
+
+/// Reads the key and nonce from the first two non-empty lines of `reader`
+///
+/// This backs the `--stdin` mode, e.g. `printf '%s\n%s\n' "$KEY" "$NONCE" |
+/// claude-token-provider --stdin`. Each line may be base64 or hex, and
+/// surrounding whitespace is ignored. Fewer than two non-empty lines is an
+/// error rather than a fallback to the interactive prompts.
+pub fn read_credentials_from(
+    reader: impl BufRead,
+) -> Result<(SecretKey, Zeroizing<[u8; NONCE_SIZE]>)> {
+    let mut lines = Vec::with_capacity(2);
+    for line in reader.lines() {
+        let line = Zeroizing::new(line?);
+        if !line.trim().is_empty() {
+            lines.push(line);
+            if lines.len() == 2 {
+                break;
             }
         }
     }
+
+    let [key, nonce] = lines.as_slice() else {
+        return Err(TokenProviderError::IoError(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "expected a key line and a nonce line on stdin, got {} line(s)",
+                lines.len()
+            ),
+        )));
+    };
+
+    Ok((
+        decode_key_auto(key.trim())?,
+        decode_nonce_auto(nonce.trim())?,
+    ))
 }
 
-/// Prompts user for the AES-GCM IV/Nonce
-pub fn get_nonce() -> Result<[u8; NONCE_SIZE]> {
-    print!("Enter AES-256-GCM IV/Nonce (Base64): ");
-    io::stdout().flush().map_err(TokenProviderError::IoError)?;
+/// Separator between the key and nonce in a combined credential string
+pub const COMBINED_DELIMITER: char = ':';
 
-    loop {
-        let mut nonce_input = String::new();
-        io::stdin()
-            .read_line(&mut nonce_input)
-            .map_err(TokenProviderError::IoError)?;
+/// Splits a `key:nonce` string into a validated key and nonce
+///
+/// Each half may be base64 or hex, as with `--key` and `--nonce`, and
+/// surrounding whitespace is ignored. A string without exactly one `:` is
+/// rejected before either half is decoded.
+pub fn parse_combined_credentials(
+    combined: &str,
+) -> Result<(SecretKey, Zeroizing<[u8; NONCE_SIZE]>)> {
+    let parts: Vec<&str> = combined.trim().split(COMBINED_DELIMITER).collect();
+    let [key, nonce] = parts.as_slice() else {
+        return Err(TokenProviderError::ValidationError(format!(
+            "combined credentials must be `key{}nonce`, found {} part(s)",
+            COMBINED_DELIMITER,
+            parts.len()
+        )));
+    };
 
-        let nonce_input = nonce_input.trim();
+    Ok((
+        decode_key_auto(key.trim())?,
+        decode_nonce_auto(nonce.trim())?,
+    ))
+}
 
-        // Pre-validate format
-        if let Err(_) = validate_base64_format(nonce_input) {
-            eprintln!("Error: Invalid Base64 format");
-            eprintln!("Please ensure your input contains only valid Base64 characters (A-Z, a-z, 0-9, +, /, =)");
-            print!("Please try again: ");
-            io::stdout().flush().map_err(TokenProviderError::IoError)?;
-            continue;
-        }
+/// A key and nonce parsed from the combined `key:nonce` form
+///
+/// Parse one with `"<key>:<nonce>".parse::<Credentials>()`. Both arrays are
+/// zeroed when dropped, and `Debug` redacts the key.
+#[derive(Clone)]
+pub struct Credentials {
+    pub key: [u8; KEY_SIZE],
+    pub nonce: [u8; NONCE_SIZE],
+}
 
-        match decode_and_validate_nonce(nonce_input) {
-            Ok(nonce) => return Ok(nonce),
-            Err(e) => {
-                eprintln!("Error: {}", e.user_message());
-                if !e.is_recoverable() {
-                    return Err(e);
-                }
-                eprintln!("Expected: 16 Base64 characters (12 bytes when decoded)");
-                print!("Please try again: ");
-                io::stdout().flush().map_err(TokenProviderError::IoError)?;
-            }
+impl FromStr for Credentials {
+    type Err = TokenProviderError;
+
+    /// Parses `key:nonce` with the same rules as [`parse_combined_credentials`]
+    fn from_str(combined: &str) -> Result<Self> {
+        let (key, nonce) = parse_combined_credentials(combined)?;
+        Ok(Self {
+            key: *key.expose(),
+            nonce: *nonce,
+        })
+    }
+}
+
+impl Drop for Credentials {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        self.nonce.zeroize();
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("key", &"****")
+            .field("nonce", &hex::encode(self.nonce))
+            .finish()
+    }
+}
+
+/// Reads the Base64 or hex secret key from a file
+///
+/// Trailing whitespace and newlines are trimmed before decoding, so keys
+/// written with `echo` work as expected.
+pub fn get_secret_key_from_file(path: &Path) -> Result<SecretKey> {
+    let size = fs::metadata(path)
+        .map_err(|e| TokenProviderError::file_io(path, e))?
+        .len();
+    if size > MAX_KEY_FILE_SIZE {
+        return Err(TokenProviderError::IoError(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Key file {} is too large: {} bytes (maximum {} bytes)",
+                path.display(),
+                size,
+                MAX_KEY_FILE_SIZE
+            ),
+        )));
+    }
+
+    let contents =
+        Zeroizing::new(fs::read_to_string(path).map_err(|e| TokenProviderError::file_io(path, e))?);
+    decode_key_auto(contents.trim_end())
+}
+
+/// Keyring service name the CLI stores and looks up keys under by default
+pub const KEYRING_SERVICE: &str = "claude-token-provider";
+
+/// A store of named secrets, such as the OS keyring
+pub trait SecretStore {
+    /// Returns the secret stored for `service` and `account`
+    fn get(&self, service: &str, account: &str) -> Result<Zeroizing<String>>;
+
+    /// Stores `secret` for `service` and `account`, replacing any previous one
+    fn set(&self, service: &str, account: &str, secret: &str) -> Result<()>;
+}
+
+/// The platform credential store via the `keyring` crate
+///
+/// This is the Keychain on macOS, Credential Manager on Windows, and the
+/// kernel keyring on Linux, where entries last until the user logs out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsKeyring;
+
+impl SecretStore for OsKeyring {
+    fn get(&self, service: &str, account: &str) -> Result<Zeroizing<String>> {
+        keyring::Entry::new(service, account)
+            .and_then(|entry| entry.get_password())
+            .map(Zeroizing::new)
+            .map_err(keyring_error)
+    }
+
+    fn set(&self, service: &str, account: &str, secret: &str) -> Result<()> {
+        keyring::Entry::new(service, account)
+            .and_then(|entry| entry.set_password(secret))
+            .map_err(keyring_error)
+    }
+}
+
+/// Converts a keyring failure, keeping a missing entry distinguishable
+fn keyring_error(error: keyring::Error) -> TokenProviderError {
+    let kind = match error {
+        keyring::Error::NoEntry => io::ErrorKind::NotFound,
+        _ => io::ErrorKind::Other,
+    };
+    TokenProviderError::IoError(io::Error::new(kind, format!("OS keyring: {}", error)))
+}
+
+/// Reads the base64 or hex secret key stored in the OS keyring
+pub fn get_secret_key_from_keyring(service: &str, account: &str) -> Result<SecretKey> {
+    get_secret_key_from_store(&OsKeyring, service, account)
+}
+
+/// Like [`get_secret_key_from_keyring`], reading from any [`SecretStore`]
+pub fn get_secret_key_from_store(
+    store: &dyn SecretStore,
+    service: &str,
+    account: &str,
+) -> Result<SecretKey> {
+    let stored = store.get(service, account)?;
+    decode_key_auto(stored.trim())
+}
+
+/// Stores a key in `store` as standard base64, for [`get_secret_key_from_store`]
+pub fn store_secret_key(
+    store: &dyn SecretStore,
+    service: &str,
+    account: &str,
+    key: &SecretKey,
+) -> Result<()> {
+    let encoded = Zeroizing::new(general_purpose::STANDARD.encode(key.expose()));
+    store.set(service, account, &encoded)
+}
+
+/// Prompts for a passphrase and derives the secret key from it
+///
+/// This is an alternative to `get_secret_key` for users who prefer a
+/// memorable passphrase over a raw Base64 key.
+pub fn get_key_from_passphrase(salt: &[u8]) -> Result<SecretKey> {
+    get_key_from_passphrase_with_options(salt, &PromptOptions::default())
+}
+
+/// Like [`get_key_from_passphrase`], with the prompt's timeout and attempt limit
+pub fn get_key_from_passphrase_with_options(
+    salt: &[u8],
+    options: &PromptOptions,
+) -> Result<SecretKey> {
+    let passphrase = prompt_until_non_empty(options.max_attempts, |_| {
+        prompt_hidden("Enter passphrase: ", options.timeout)
+    })?;
+    derive_key_from_passphrase(&passphrase, salt, &KdfParams::default())
+}
+
+/// Reads a passphrase until one is not empty or `max_attempts` is used up
+///
+/// `read` is called with the 1-based attempt number; a read failure is
+/// returned immediately.
+fn prompt_until_non_empty(
+    max_attempts: u32,
+    mut read: impl FnMut(u32) -> Result<Zeroizing<String>>,
+) -> Result<Zeroizing<String>> {
+    for attempt in 1..=max_attempts.max(1) {
+        let passphrase = read(attempt)?;
+        if !passphrase.is_empty() {
+            return Ok(passphrase);
         }
+        eprintln!("Error: Passphrase must not be empty\n");
     }
+
+    eprintln!("Error: giving up after {} attempts", max_attempts.max(1));
+    Err(TokenProviderError::ValidationError(
+        "passphrase must not be empty".to_string(),
+    ))
+}
+
+/// Prompts user for the AES-GCM IV/Nonce
+///
+/// Input is hidden like the key prompt, since a nonce can reveal which
+/// payload is in use. Accepts base64 or a 24-digit hex string; the encoding is detected
+/// automatically. Uses `CLAUDE_TOKEN_NONCE` without prompting when it is set.
+pub fn get_nonce() -> Result<Zeroizing<[u8; NONCE_SIZE]>> {
+    get_nonce_with_options(&PromptOptions::default())
+}
+
+/// Like [`get_nonce`], with the prompt's timeout and attempt limit
+pub fn get_nonce_with_options(options: &PromptOptions) -> Result<Zeroizing<[u8; NONCE_SIZE]>> {
+    if let Some(nonce) = credential_from_env(NONCE_ENV_VAR, decode_nonce_auto)? {
+        return Ok(nonce);
+    }
+
+    prompt_until_valid(
+        options.max_attempts,
+        |attempt| {
+            let prompt = if attempt == 1 {
+                "Enter AES-256-GCM IV/Nonce (Base64 or hex): "
+            } else {
+                "Please try again: "
+            };
+            prompt_hidden(prompt, options.timeout)
+        },
+        NONCE_SIZE,
+        decode_nonce_auto,
+        "Expected: 16 Base64 characters or 24 hex digits (12 bytes when decoded)",
+    )
 }
 
 pub const APP_TOKEN: &str = env!("APP_TOKEN");
@@ -110,15 +614,494 @@ pub fn display_banner() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::decode_and_validate_key;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_prompt_gives_up_after_max_attempts() {
+        let mut attempts = 0;
+
+        let result = prompt_until_valid(
+            4,
+            |_| {
+                attempts += 1;
+                Ok(Zeroizing::new("not base64!".to_string()))
+            },
+            NONCE_SIZE,
+            decode_nonce_auto,
+            "",
+        );
+
+        assert_eq!(attempts, 4);
+        assert!(matches!(result, Err(TokenProviderError::InvalidBase64(_))));
+    }
+
+    #[test]
+    fn test_passphrase_prompt_gives_up_after_max_attempts() {
+        let mut attempts = 0;
+
+        let result = prompt_until_non_empty(2, |_| {
+            attempts += 1;
+            Ok(Zeroizing::new(String::new()))
+        });
+
+        assert_eq!(attempts, 2);
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_passphrase_prompt_accepts_retry() {
+        let mut inputs = ["", "correct horse"].into_iter();
+
+        let passphrase = prompt_until_non_empty(DEFAULT_MAX_ATTEMPTS, |_| {
+            Ok(Zeroizing::new(inputs.next().unwrap().to_string()))
+        })
+        .unwrap();
+
+        assert_eq!(passphrase.as_str(), "correct horse");
+    }
+
+    #[test]
+    fn test_prompt_returns_last_recoverable_error() {
+        let mut inputs = ["not base64!", "AAAA"].into_iter();
+
+        let result = prompt_until_valid(
+            2,
+            |_| Ok(Zeroizing::new(inputs.next().unwrap().to_string())),
+            NONCE_SIZE,
+            decode_nonce_auto,
+            "",
+        );
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::InvalidIvLength { actual: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_prompt_accepts_valid_retry() {
+        let mut inputs = ["AAAA", "AgICAgICAgICAgIC\n"].into_iter();
+
+        let nonce = prompt_until_valid(
+            DEFAULT_MAX_ATTEMPTS,
+            |_| Ok(Zeroizing::new(inputs.next().unwrap().to_string())),
+            NONCE_SIZE,
+            decode_nonce_auto,
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(*nonce, [2u8; NONCE_SIZE]);
+    }
+
+    #[test]
+    fn test_nonce_prompt_decodes_hex_and_base64() {
+        let mut inputs = ["02".repeat(NONCE_SIZE), "AgICAgICAgICAgIC".to_string()].into_iter();
+
+        for _ in 0..2 {
+            let nonce = prompt_until_valid(
+                1,
+                |_| Ok(Zeroizing::new(inputs.next().unwrap())),
+                NONCE_SIZE,
+                decode_nonce_auto,
+                "",
+            )
+            .unwrap();
+
+            assert_eq!(*nonce, [2u8; NONCE_SIZE]);
+        }
+    }
+
+    #[test]
+    fn test_read_credentials_from_two_lines() {
+        let input =
+            "\n  AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=\n\n020202020202020202020202\n";
+
+        let (key, nonce) = read_credentials_from(input.as_bytes()).unwrap();
+
+        assert_eq!(key.expose(), &[1u8; KEY_SIZE]);
+        assert_eq!(*nonce, [2u8; NONCE_SIZE]);
+    }
+
+    #[test]
+    fn test_read_credentials_requires_two_lines() {
+        let input = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=\n\n";
+
+        match read_credentials_from(input.as_bytes()) {
+            Err(TokenProviderError::IoError(e)) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+                assert!(e.to_string().contains("got 1 line(s)"));
+            }
+            other => panic!("expected an I/O error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_confirm_key_fingerprint_reprompts_after_rejection() {
+        let mut keys = vec![
+            SecretKey::new([2u8; KEY_SIZE]),
+            SecretKey::new([1u8; KEY_SIZE]),
+        ];
+        let mut shown = Vec::new();
+
+        let key = confirm_key_fingerprint(
+            3,
+            || Ok(keys.pop().unwrap()),
+            |fingerprint| {
+                shown.push(fingerprint.to_string());
+                Ok(shown.len() == 2)
+            },
+        )
+        .unwrap();
+
+        assert_eq!(key.expose(), &[2u8; KEY_SIZE]);
+        assert_eq!(
+            shown,
+            [
+                key_fingerprint(&[1u8; KEY_SIZE]),
+                key_fingerprint(&[2u8; KEY_SIZE])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_confirm_key_fingerprint_gives_up() {
+        let mut reads = 0;
+
+        let result = confirm_key_fingerprint(
+            2,
+            || {
+                reads += 1;
+                Ok(SecretKey::new([1u8; KEY_SIZE]))
+            },
+            |_| Ok(false),
+        );
+
+        assert_eq!(reads, 2);
+        match result {
+            Err(TokenProviderError::ValidationError(message)) => {
+                assert!(message.contains("rejected 2"))
+            }
+            other => panic!("expected a rejection error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    /// In-memory stand-in for the OS keyring
+    #[derive(Default)]
+    struct MemoryStore {
+        secrets: std::cell::RefCell<std::collections::HashMap<(String, String), String>>,
+    }
+
+    impl SecretStore for MemoryStore {
+        fn get(&self, service: &str, account: &str) -> Result<Zeroizing<String>> {
+            self.secrets
+                .borrow()
+                .get(&(service.to_string(), account.to_string()))
+                .map(|secret| Zeroizing::new(secret.clone()))
+                .ok_or_else(|| keyring_error(keyring::Error::NoEntry))
+        }
+
+        fn set(&self, service: &str, account: &str, secret: &str) -> Result<()> {
+            self.secrets.borrow_mut().insert(
+                (service.to_string(), account.to_string()),
+                secret.to_string(),
+            );
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_keyring_store_then_fetch() {
+        let store = MemoryStore::default();
+        let key = SecretKey::new([9u8; KEY_SIZE]);
+
+        store_secret_key(&store, KEYRING_SERVICE, "work", &key).unwrap();
+        let fetched = get_secret_key_from_store(&store, KEYRING_SERVICE, "work").unwrap();
+
+        assert_eq!(fetched.expose(), key.expose());
+        assert_eq!(
+            store.get(KEYRING_SERVICE, "work").unwrap().as_str(),
+            general_purpose::STANDARD.encode([9u8; KEY_SIZE])
+        );
+    }
+
+    #[test]
+    fn test_keyring_missing_or_invalid_entry() {
+        let store = MemoryStore::default();
+
+        match get_secret_key_from_store(&store, KEYRING_SERVICE, "absent") {
+            Err(TokenProviderError::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            other => panic!("expected a missing entry, got {:?}", other.map(|_| ())),
+        }
+
+        store.set(KEYRING_SERVICE, "short", "dGVzdA==").unwrap();
+        assert!(matches!(
+            get_secret_key_from_store(&store, KEYRING_SERVICE, "short"),
+            Err(TokenProviderError::InvalidKeyLength { actual: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_parse_combined_credentials() {
+        let combined = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=:AgICAgICAgICAgIC\n";
+
+        let (key, nonce) = parse_combined_credentials(combined).unwrap();
+
+        assert_eq!(key.expose(), &[1u8; KEY_SIZE]);
+        assert_eq!(*nonce, [2u8; NONCE_SIZE]);
+    }
+
+    #[test]
+    fn test_parse_combined_credentials_rejects_wrong_part_count() {
+        let key = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+        for (combined, parts) in [
+            (key.to_string(), 1),
+            (format!("{}:AgICAgICAgICAgIC:extra", key), 3),
+        ] {
+            match parse_combined_credentials(&combined) {
+                Err(TokenProviderError::ValidationError(message)) => {
+                    assert!(message.contains(&format!("found {} part(s)", parts)));
+                }
+                other => panic!("expected an input error, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_credentials_from_str() {
+        let credentials: Credentials =
+            "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=:AgICAgICAgICAgIC"
+                .parse()
+                .unwrap();
+
+        assert_eq!(credentials.key, [1u8; KEY_SIZE]);
+        assert_eq!(credentials.nonce, [2u8; NONCE_SIZE]);
+        assert!(!format!("{:?}", credentials).contains("AQEB"));
+    }
+
+    #[test]
+    fn test_credentials_from_str_rejects_malformed_input() {
+        let key = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+        let nonce = "AgICAgICAgICAgIC";
+
+        assert!(matches!(
+            "".parse::<Credentials>(),
+            Err(TokenProviderError::ValidationError(_))
+        ));
+        assert!(matches!(
+            format!("{}:{}:{}", key, nonce, nonce).parse::<Credentials>(),
+            Err(TokenProviderError::ValidationError(_))
+        ));
+        assert!(matches!(
+            format!("AQEBAQ==:{}", nonce).parse::<Credentials>(),
+            Err(TokenProviderError::InvalidKeyLength { actual: 4 })
+        ));
+        assert!(matches!(
+            format!("{}:AgICAg==", key).parse::<Credentials>(),
+            Err(TokenProviderError::InvalidIvLength { actual: 4 })
+        ));
+        assert!(matches!(
+            format!("{}:not base64!", key).parse::<Credentials>(),
+            Err(TokenProviderError::InvalidBase64(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_input_times_out_without_input() {
+        // Stands in for a stdin that stays open but never delivers a line
+        let never_ready = || {
+            std::thread::sleep(Duration::from_secs(5));
+            Ok(String::new())
+        };
+
+        match read_input(never_ready, Some(Duration::from_millis(20))) {
+            Err(TokenProviderError::PromptTimeout(timeout)) => {
+                assert_eq!(timeout, Duration::from_millis(20))
+            }
+            other => panic!("expected a timeout error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_read_input_returns_input_before_timeout() {
+        let input = read_input(
+            || Ok("AgICAgICAgICAgIC\n".to_string()),
+            Some(Duration::from_secs(5)),
+        )
+        .unwrap();
+
+        assert_eq!(input.as_str(), "AgICAgICAgICAgIC\n");
+    }
 
     #[test]
     fn test_validate_base64_format() {
         // Valid base64
         assert!(validate_base64_format("SGVsbG8gV29ybGQ=").is_ok());
         assert!(validate_base64_format("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=").is_ok());
+        assert!(validate_base64_format("-_v7-_v7").is_ok()); // URL-safe alphabet
 
         // Invalid characters
         assert!(validate_base64_format("Hello@World!").is_err());
         assert!(validate_base64_format("SGVsbG8gV29ybGQ===").is_err()); // Too much padding
     }
+
+    #[test]
+    fn test_validate_base64_ignores_whitespace() {
+        assert!(validate_base64_format("AAAAAAAAAAAAAAAAAAAAAA\nAAAAAAAAAAAAAAAAAAAAA=").is_ok());
+        assert!(validate_base64_format(" SGVs\tbG8g\r\nV29y bGQ= ").is_ok());
+        assert!(validate_base64_format("SGVs bG8@").is_err());
+    }
+
+    #[test]
+    fn test_validate_base64_rejects_interior_padding() {
+        assert!(matches!(
+            validate_base64_format("SGV=sbG8="),
+            Err(TokenProviderError::InvalidBase64(
+                base64::DecodeError::InvalidPadding
+            ))
+        ));
+        assert!(validate_base64_format("=SGVsbG8").is_err());
+    }
+
+    #[test]
+    fn test_validate_base64_rejects_wrong_length() {
+        // Padded input must be a multiple of 4 characters
+        assert!(validate_base64_format("SGVsbG8=").is_ok());
+        assert!(matches!(
+            validate_base64_format("SGVsbG8gV="),
+            Err(TokenProviderError::InvalidBase64(
+                base64::DecodeError::InvalidLength(10)
+            ))
+        ));
+        // Standard-alphabet input must be padded to a multiple of 4
+        assert!(validate_base64_format("+/v7+").is_err());
+        // No encoder produces a single trailing character
+        assert!(validate_base64_format("SGVsb").is_err());
+        // Unpadded URL-safe input may end on a partial group
+        assert!(validate_base64_format("SGVsbG8").is_ok());
+    }
+
+    #[test]
+    fn test_get_secret_key_from_env() {
+        env::set_var(KEY_ENV_VAR, "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=");
+        let key = get_secret_key();
+        env::remove_var(KEY_ENV_VAR);
+
+        assert_eq!(key.unwrap().expose(), &[1u8; KEY_SIZE]);
+    }
+
+    #[test]
+    fn test_get_nonce_from_env() {
+        env::set_var(NONCE_ENV_VAR, "AgICAgICAgICAgIC");
+        let nonce = get_nonce();
+        env::remove_var(NONCE_ENV_VAR);
+
+        assert_eq!(*nonce.unwrap(), [2u8; NONCE_SIZE]);
+    }
+
+    #[test]
+    fn test_malformed_env_credential_errors() {
+        let var = "CLAUDE_TOKEN_TEST_MALFORMED_KEY";
+        env::set_var(var, "dGVzdA==");
+        let result = credential_from_env(var, decode_and_validate_key);
+        env::remove_var(var);
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::InvalidKeyLength { actual: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_unset_env_credential_is_none() {
+        let result =
+            credential_from_env("CLAUDE_TOKEN_TEST_UNSET_KEY", decode_and_validate_key).unwrap();
+
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_load_encrypted_payload_from_file() {
+        use crate::crypto::{decrypt_data, encrypt_data, Algorithm};
+
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+        let ciphertext =
+            encrypt_data(br#"{"version": 2}"#, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        let payload_file = NamedTempFile::new().unwrap();
+        fs::write(payload_file.path(), &ciphertext).unwrap();
+
+        let payload = load_encrypted_payload(Some(payload_file.path())).unwrap();
+        let plaintext = decrypt_data(&payload, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+
+        assert_eq!(plaintext, br#"{"version": 2}"#);
+    }
+
+    #[test]
+    fn test_load_encrypted_payload_defaults_to_constant() {
+        let payload = load_encrypted_payload(None).unwrap();
+
+        assert_eq!(payload.as_ref(), ENCRYPTED_CONFIG);
+    }
+
+    #[test]
+    fn test_get_secret_key_from_file() {
+        let mut key_file = NamedTempFile::new().unwrap();
+        writeln!(key_file, "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=").unwrap();
+
+        let key = get_secret_key_from_file(key_file.path()).unwrap();
+        assert_eq!(key.expose(), &[1u8; KEY_SIZE]);
+    }
+
+    #[test]
+    fn test_get_secret_key_from_hex_file() {
+        let mut key_file = NamedTempFile::new().unwrap();
+        writeln!(key_file, "{}", "01".repeat(KEY_SIZE)).unwrap();
+
+        let key = get_secret_key_from_file(key_file.path()).unwrap();
+        assert_eq!(key.expose(), &[1u8; KEY_SIZE]);
+    }
+
+    #[test]
+    fn test_get_secret_key_from_file_wrong_length() {
+        let mut key_file = NamedTempFile::new().unwrap();
+        writeln!(key_file, "dGVzdA==").unwrap();
+
+        let result = get_secret_key_from_file(key_file.path());
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::InvalidKeyLength { actual: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_get_secret_key_from_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = dir.path().join("missing.key");
+
+        let result = get_secret_key_from_file(&path);
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::FileIo { path: failed, source })
+                if failed == path && source.kind() == io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_get_secret_key_from_oversized_file() {
+        let mut key_file = NamedTempFile::new().unwrap();
+        key_file.write_all(&[b'A'; 2048]).unwrap();
+
+        let result = get_secret_key_from_file(key_file.path());
+        assert!(
+            matches!(result, Err(TokenProviderError::IoError(e)) if e.kind() == io::ErrorKind::InvalidData)
+        );
+    }
 }
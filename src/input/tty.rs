@@ -0,0 +1,177 @@
+//! Interruptible terminal input, for Unix
+//!
+//! Input is polled for before anything is read, so a prompt that times out
+//! or is interrupted with Ctrl-C leaves no blocked reader behind, and the
+//! terminal's echo setting is restored before the error is returned.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+use crate::{interrupt, Result, TokenProviderError};
+
+/// How long one poll waits before the interrupt flag is checked again
+const POLL_SLICE: Duration = Duration::from_millis(100);
+
+/// Longest line accepted without reallocating, which would leave copies behind
+const LINE_CAPACITY: usize = 1024;
+
+/// Turns terminal echo off and restores the original settings when dropped
+struct EchoOff {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl EchoOff {
+    fn new(fd: RawFd) -> io::Result<Self> {
+        // SAFETY: `termios` is plain data, so all-zero is a valid value for
+        // `tcgetattr` to overwrite, and `fd` is open for the guard's lifetime.
+        let mut settings = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(fd, &mut settings) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let original = settings;
+
+        // Keep echoing the newline so the next output starts on its own line
+        settings.c_lflag &= !libc::ECHO;
+        settings.c_lflag |= libc::ECHONL;
+        // SAFETY: `settings` came from `tcgetattr` on the same descriptor.
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &settings) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd, original })
+    }
+}
+
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        // SAFETY: `original` was read from this descriptor by `tcgetattr`.
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Waits until `fd` has input
+///
+/// Fails with `PromptTimeout` once `timeout` passes, or with `Interrupted`
+/// as soon as `interrupted` returns true; it is checked between polls.
+fn wait_readable(
+    fd: RawFd,
+    timeout: Option<Duration>,
+    interrupted: impl Fn() -> bool,
+) -> Result<()> {
+    let started = Instant::now();
+    loop {
+        if interrupted() {
+            return Err(TokenProviderError::Interrupted);
+        }
+        let slice = match timeout {
+            Some(timeout) => {
+                let remaining = timeout.saturating_sub(started.elapsed());
+                if remaining.is_zero() {
+                    return Err(TokenProviderError::PromptTimeout(timeout));
+                }
+                remaining.min(POLL_SLICE)
+            }
+            None => POLL_SLICE,
+        };
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // SAFETY: `poll_fd` is a single valid entry that outlives the call.
+        match unsafe { libc::poll(&mut poll_fd, 1, slice.as_millis() as libc::c_int) } {
+            0 => {}
+            -1 => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error.into());
+                }
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Waits until `fd` has input, giving up after `timeout` or on Ctrl-C
+pub fn wait_for_input(fd: RawFd, timeout: Option<Duration>) -> Result<()> {
+    wait_readable(fd, timeout, interrupt::was_interrupted)
+}
+
+/// Reads one line from `tty`, without the trailing newline
+fn read_line(mut tty: &File) -> io::Result<Zeroizing<String>> {
+    let mut line = Zeroizing::new(Vec::with_capacity(LINE_CAPACITY));
+    let mut chunk = Zeroizing::new([0u8; 256]);
+    loop {
+        let read = match tty.read(&mut chunk[..]) {
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        line.extend_from_slice(&chunk[..read]);
+        if read == 0 || line.ends_with(b"\n") {
+            break;
+        }
+    }
+
+    while matches!(line.last(), Some(b'\n' | b'\r')) {
+        line.pop();
+    }
+    let line = String::from_utf8(std::mem::take(&mut *line)).map_err(|e| {
+        let mut bytes = e.into_bytes();
+        zeroize::Zeroize::zeroize(&mut bytes);
+        io::Error::new(io::ErrorKind::InvalidData, "input is not valid UTF-8")
+    })?;
+    Ok(Zeroizing::new(line))
+}
+
+/// Prompts on the controlling terminal and reads a line with echo off
+///
+/// Fails with `PromptTimeout` if no line is entered within `timeout`, or
+/// with `Interrupted` on Ctrl-C.
+pub fn prompt_password(prompt: &str, timeout: Option<Duration>) -> Result<Zeroizing<String>> {
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    (&tty).write_all(prompt.as_bytes())?;
+    (&tty).flush()?;
+
+    let echo_off = EchoOff::new(tty.as_raw_fd())?;
+    if let Err(e) = wait_for_input(tty.as_raw_fd(), timeout) {
+        drop(echo_off);
+        let _ = (&tty).write_all(b"\n");
+        return Err(e);
+    }
+    Ok(read_line(&tty)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_readable_times_out_then_sees_input() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        let timeout = Duration::from_millis(20);
+
+        match wait_readable(reader.as_raw_fd(), Some(timeout), || false) {
+            Err(TokenProviderError::PromptTimeout(waited)) => assert_eq!(waited, timeout),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+
+        writer.write_all(b"AgICAgICAgICAgIC\n").unwrap();
+        wait_readable(reader.as_raw_fd(), Some(Duration::from_secs(5)), || false).unwrap();
+    }
+
+    #[test]
+    fn test_wait_readable_stops_when_interrupted() {
+        let (reader, _writer) = io::pipe().unwrap();
+
+        let result = wait_readable(reader.as_raw_fd(), None, || true);
+
+        assert!(matches!(result, Err(TokenProviderError::Interrupted)));
+    }
+}
@@ -0,0 +1,51 @@
+//! Ctrl-C handling
+//!
+//! The handler only records the interrupt. Prompts and config writes check
+//! the flag and return [`TokenProviderError::Interrupted`], so the run
+//! unwinds normally, skips self-deletion, and exits with
+//! [`INTERRUPTED_EXIT_CODE`]. A write that has not been renamed into place
+//! yet is abandoned, leaving the old config untouched.
+
+use log::warn;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{Result, TokenProviderError};
+
+/// Exit code for a run interrupted with Ctrl-C (128 + SIGINT)
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Records that the user interrupted the run
+pub fn mark_interrupted() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Returns whether the run has been interrupted
+pub fn was_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Fails with [`TokenProviderError::Interrupted`] once the run has been interrupted
+pub fn check() -> Result<()> {
+    if was_interrupted() {
+        return Err(TokenProviderError::Interrupted);
+    }
+    Ok(())
+}
+
+/// Installs a Ctrl-C handler that marks the run interrupted
+///
+/// The handler prints "interrupted by user" and returns; the code that is
+/// running notices the flag and stops. A failure to install the handler is
+/// only logged.
+pub fn install_handler() {
+    let result = ctrlc::set_handler(|| {
+        mark_interrupted();
+        eprintln!("interrupted by user");
+    });
+
+    if let Err(e) = result {
+        warn!("Could not install Ctrl-C handler: {}", e);
+    }
+}
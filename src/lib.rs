@@ -3,10 +3,14 @@
 //! This library provides functionality for managing encrypted configuration
 //! data with automatic self-deletion capabilities.
 
+pub mod cli;
 pub mod config;
 pub mod crypto;
 pub mod errors;
 pub mod input;
+pub mod interrupt;
+pub mod output;
 pub mod self_deletion;
+pub mod workflow;
 
 pub use errors::{Result, TokenProviderError};
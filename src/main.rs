@@ -4,91 +4,587 @@
 //! advanced Rust concepts including cryptography, error handling, and
 //! systems programming.
 
+use clap::Parser;
 use claude_token_provider::{
-    config::file_ops::apply_config_update,
-    crypto::{constants::ENCRYPTED_CONFIG, decrypt_data},
-    input::{display_banner, get_nonce, get_secret_key},
-    self_deletion::perform_self_deletion,
+    cli::{Cli, Command},
+    config::file_ops::{
+        check_config, get_config_path_with_override, restore_backup_from, ApplyAction,
+        ApplyOptions, ApplyOutcome,
+    },
+    crypto::{key_fingerprint, nonce_guard::NonceGuard},
+    input::{display_banner, store_secret_key, OsKeyring, APP_TOKEN},
+    interrupt,
+    output::{command_json, error_json, plan_json, success_json},
+    self_deletion::{
+        confirm_self_deletion, decide_self_deletion_unless_interrupted, perform_self_deletion,
+        secure_self_delete, DeletionDecision, DeletionOutcome, DEFAULT_OVERWRITE_PASSES,
+    },
+    workflow::{
+        apply_encrypted_bundle_with_progress, apply_encrypted_config_with_progress,
+        apply_plaintext_file, decrypt_config, encrypt_config, encrypt_config_with_nonce,
+        plan_encrypted_config, render_plan, self_test, time_phases, verify_credentials,
+        ConsoleProgress, PhaseTimings, ProgressListener, StepKind, PLAN_NO_CHANGES_EXIT_CODE,
+    },
     Result, TokenProviderError,
 };
+use log::{error, info, warn};
+use serde_json::json;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use zeroize::Zeroizing;
 
-fn main() -> Result<()> {
-    // Display application banner and warnings
-    display_banner();
+fn main() {
+    let cli = Cli::parse();
+
+    cli.logger().init();
+    interrupt::install_handler();
+
+    let result = run(&cli);
+    if let Err(e) = &result {
+        if cli.is_json() {
+            println!("{}", error_json(e));
+        }
+    }
+
+    // A Ctrl-C wins over whatever the interrupted step reported
+    if interrupt::was_interrupted() {
+        std::process::exit(interrupt::INTERRUPTED_EXIT_CODE);
+    }
+    // Exit with a code that identifies the failure category, or the outcome
+    match result {
+        Ok(0) => {}
+        Ok(code) => std::process::exit(code),
+        Err(e) => std::process::exit(e.exit_code()),
+    }
+}
+
+/// Runs the tool, returning the exit code for a successful run
+fn run(cli: &Cli) -> Result<i32> {
+    // Answered before any input, crypto, or deletion code runs
+    if cli.print_app_token {
+        println!("{}", APP_TOKEN);
+        return Ok(0);
+    }
+    if cli.show_config_path {
+        let path = get_config_path_with_override(cli.config_path.clone())?;
+        println!("{}", path.display());
+        return Ok(0);
+    }
+
+    let result = match &cli.command {
+        Some(Command::Verify) => run_verify(cli),
+        Some(Command::Decrypt) => run_decrypt(cli),
+        Some(Command::Encrypt { input }) => run_encrypt(cli, input.as_deref()),
+        Some(Command::Rollback) => run_rollback(cli),
+        Some(Command::Plan) => return run_plan(cli),
+        Some(Command::Check { schema }) => run_check(cli, *schema),
+        Some(Command::Selftest) => run_self_test(cli),
+        Some(Command::StoreKey { account }) => run_store_key(cli, account),
+        None => run_apply(cli),
+    };
+    result.map(|()| 0)
+}
+
+/// Applies the configuration, including any requested self-deletion
+fn run_apply(cli: &Cli) -> Result<()> {
+    // Display application banner and warnings; stdout is reserved in JSON mode
+    if !cli.is_quiet() && !cli.is_json() {
+        display_banner();
+    }
 
     // Execute main application logic
-    match run_application() {
-        Ok(()) => {
-            println!("\n✓ Configuration successfully applied!");
+    let outcome = match run_application(cli) {
+        Ok(outcome) => {
+            match outcome.action() {
+                ApplyAction::Unchanged => {
+                    info!("✓ Configuration already up to date; nothing written")
+                }
+                ApplyAction::Planned => info!("✓ Dry run complete; nothing written"),
+                ApplyAction::Applied => info!("✓ Configuration successfully applied!"),
+            }
+            outcome
         }
         Err(e) => {
-            eprintln!("\n❌ Application failed: {}", e.user_message());
-            eprintln!("Technical details: {}", e);
+            error!("❌ Application failed: {}", e.user_message());
+            error!("Technical details: {}", e.display_chain());
 
-            // Still attempt self-deletion even on failure
-            if let Err(deletion_error) = perform_self_deletion() {
-                eprintln!("Additional error during cleanup: {}", deletion_error);
-                return Err(e); // Return original error
+            // Still attempt requested self-deletion even on failure
+            if let Ok(DeletionDecision::Proceed) = decide_deletion(cli) {
+                if let Err(deletion_error) = delete_executable(cli) {
+                    error!("Additional error during cleanup: {}", deletion_error);
+                    return Err(e); // Return original error
+                }
             }
 
             return Err(e);
         }
+    };
+
+    finish_self_deletion(cli)?;
+
+    // Printed last, so a failed cleanup is reported by main's error object
+    // instead of following this one
+    if cli.is_json() {
+        println!("{}", success_json(&outcome, cli.dry_run));
+    }
+    Ok(())
+}
+
+/// Runs the requested self-deletion after a successful apply
+fn finish_self_deletion(cli: &Cli) -> Result<()> {
+    match decide_deletion(cli)? {
+        DeletionDecision::NotRequested => {
+            info!("Self-deletion skipped (not requested)");
+            return Ok(());
+        }
+        DeletionDecision::Declined => {
+            info!("Self-deletion cancelled; executable kept");
+            return Ok(());
+        }
+        DeletionDecision::Interrupted => {
+            info!("Self-deletion skipped (interrupted)");
+            return Ok(());
+        }
+        DeletionDecision::Proceed => {}
     }
 
     // Attempt self-deletion
-    match perform_self_deletion() {
-        Ok(()) => {
-            println!("🗑️  Executable successfully removed");
+    match delete_executable(cli) {
+        Ok(DeletionOutcome::Deleted) => {
+            info!("🗑️  Executable successfully removed");
+            Ok(())
+        }
+        Ok(DeletionOutcome::DeferredUntilReboot) => {
+            info!("🗑️  Executable will be removed on the next reboot");
             Ok(())
         }
+        Ok(DeletionOutcome::Disabled) => Ok(()),
         Err(deletion_error) => {
-            eprintln!("⚠️  Configuration applied but cleanup failed");
+            warn!("⚠️  Configuration applied but cleanup failed");
             Err(deletion_error)
         }
     }
 }
 
+/// Decides whether to self-delete, never after a Ctrl-C
+fn decide_deletion(cli: &Cli) -> Result<DeletionDecision> {
+    decide_self_deletion_unless_interrupted(
+        interrupt::was_interrupted(),
+        cli.should_self_delete(),
+        cli.yes,
+        confirm_self_deletion,
+    )
+}
+
+/// Checks the credentials against the payload without touching any config
+fn run_verify(cli: &Cli) -> Result<()> {
+    let result = cli.credentials().and_then(|(key, nonce)| {
+        info!("Key fingerprint: {}", key_fingerprint(key.expose()));
+        let payload = cli.encrypted_payload()?;
+        verify_credentials(&payload, key.expose(), &nonce)
+    });
+
+    match result {
+        Ok(()) => {
+            if cli.is_json() {
+                println!("{}", command_json("verify", json!({"valid": true})));
+            } else {
+                println!("credentials valid");
+            }
+            Ok(())
+        }
+        Err(e) => {
+            error!("❌ Verification failed: {}", e.user_message());
+            error!("Technical details: {}", e.display_chain());
+            Err(e)
+        }
+    }
+}
+
+/// Prints the decrypted configuration without applying it or self-deleting
+fn run_decrypt(cli: &Cli) -> Result<()> {
+    let result = cli.credentials().and_then(|(key, nonce)| {
+        info!("Key fingerprint: {}", key_fingerprint(key.expose()));
+        let payload = cli.encrypted_payload()?;
+        decrypt_config(&payload, key.expose(), &nonce)
+    });
+
+    match result {
+        Ok(config) => {
+            if cli.is_json() {
+                println!("{}", command_json("decrypt", json!({"config": config})));
+            } else {
+                println!("{}", serde_json::to_string_pretty(&config)?);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            error!("❌ Decryption failed: {}", e.user_message());
+            error!("Technical details: {}", e.display_chain());
+            Err(e)
+        }
+    }
+}
+
+/// Encrypts a plaintext JSON file (or stdin) and prints the sealed payload
+fn run_encrypt(cli: &Cli, input: Option<&Path>) -> Result<()> {
+    let read_plaintext = || -> Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(match input {
+            Some(path) => fs::read(path).map_err(|e| TokenProviderError::file_io(path, e))?,
+            None => {
+                let mut buffer = Vec::new();
+                io::stdin().read_to_end(&mut buffer)?;
+                buffer
+            }
+        }))
+    };
+    // A caller-chosen nonce must never repeat under the same key
+    let result = if cli.nonce.is_some() || cli.combined.is_some() {
+        cli.credentials().and_then(|(key, nonce)| {
+            let guard = NonceGuard::with_default_path()?;
+            encrypt_config_with_nonce(
+                &read_plaintext()?,
+                key.expose(),
+                &nonce,
+                &guard,
+                cli.nonce_reuse,
+            )
+        })
+    } else {
+        cli.secret_key()
+            .and_then(|key| encrypt_config(&read_plaintext()?, key.expose()))
+    };
+
+    match result {
+        Ok(encoded) => {
+            if cli.is_json() {
+                println!("{}", command_json("encrypt", json!({"payload": encoded})));
+            } else {
+                println!("{}", encoded);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            error!("❌ Encryption failed: {}", e.user_message());
+            error!("Technical details: {}", e.display_chain());
+            Err(e)
+        }
+    }
+}
+
+/// Prints what an apply would change; returns whether anything would
+fn run_plan(cli: &Cli) -> Result<i32> {
+    let result = cli.credentials().and_then(|(key, nonce)| {
+        info!("Key fingerprint: {}", key_fingerprint(key.expose()));
+        let payload = cli.encrypted_payload()?;
+        plan_encrypted_config(&payload, key.expose(), &nonce, &cli.apply_options())
+    });
+
+    match result {
+        Ok(plan) => {
+            if cli.is_json() {
+                println!("{}", plan_json(&plan));
+            } else {
+                println!("{}", render_plan(&plan));
+            }
+            Ok(if plan.has_changes() {
+                0
+            } else {
+                PLAN_NO_CHANGES_EXIT_CODE
+            })
+        }
+        Err(e) => {
+            error!("❌ Plan failed: {}", e.user_message());
+            error!("Technical details: {}", e.display_chain());
+            Err(e)
+        }
+    }
+}
+
+/// Restores the target config from its backup
+fn run_rollback(cli: &Cli) -> Result<()> {
+    let result = get_config_path_with_override(cli.config_path.clone()).and_then(|path| {
+        restore_backup_from(&path, cli.backup_dir.as_deref()).map(|backup| (path, backup))
+    });
+
+    match result {
+        Ok((path, backup)) => {
+            info!("✓ Restored {} from {}", path.display(), backup.display());
+            if cli.is_json() {
+                let report = json!({"path": path, "backup_path": backup});
+                println!("{}", command_json("rollback", report));
+            }
+            Ok(())
+        }
+        Err(e) => {
+            error!("❌ Rollback failed: {}", e.user_message());
+            error!("Technical details: {}", e.display_chain());
+            Err(e)
+        }
+    }
+}
+
+/// Checks the target config file without decrypting or writing anything
+fn run_check(cli: &Cli, validate_schema: bool) -> Result<()> {
+    let result = get_config_path_with_override(cli.config_path.clone())
+        .and_then(|path| check_config(&path, validate_schema).map(|_| path));
+
+    match result {
+        Ok(path) => {
+            if cli.is_json() {
+                let report = json!({"path": path, "valid": true, "schema": validate_schema});
+                println!("{}", command_json("check", report));
+            } else {
+                println!("{} is valid", path.display());
+            }
+            Ok(())
+        }
+        Err(e) => {
+            error!("❌ Check failed: {}", e.user_message());
+            error!("Technical details: {}", e.display_chain());
+            Err(e)
+        }
+    }
+}
+
+/// Saves the key in the OS keyring so later runs can use `--keyring`
+fn run_store_key(cli: &Cli, account: &str) -> Result<()> {
+    let result = cli.secret_key().and_then(|key| {
+        info!("Key fingerprint: {}", key_fingerprint(key.expose()));
+        store_secret_key(&OsKeyring, &cli.keyring_service, account, &key)
+    });
+
+    match result {
+        Ok(()) => {
+            info!(
+                "✓ Key stored in the OS keyring as {}/{}",
+                cli.keyring_service, account
+            );
+            if cli.is_json() {
+                let report = json!({"service": cli.keyring_service, "account": account});
+                println!("{}", command_json("store-key", report));
+            }
+            Ok(())
+        }
+        Err(e) => {
+            error!("❌ Storing the key failed: {}", e.user_message());
+            error!("Technical details: {}", e.display_chain());
+            Err(e)
+        }
+    }
+}
+
+/// Runs the built-in crypto self-test and reports pass or fail
+fn run_self_test(cli: &Cli) -> Result<()> {
+    match self_test() {
+        Ok(()) => {
+            if cli.is_json() {
+                println!("{}", command_json("selftest", json!({"passed": true})));
+            } else {
+                println!("selftest passed");
+            }
+            Ok(())
+        }
+        Err(e) => {
+            if !cli.is_json() {
+                println!("selftest failed");
+            }
+            error!("❌ Self-test failed: {}", e.user_message());
+            error!("Technical details: {}", e.display_chain());
+            Err(e)
+        }
+    }
+}
+
+/// Deletes the executable, scrubbing its contents first if requested
+fn delete_executable(cli: &Cli) -> Result<DeletionOutcome> {
+    if cli.secure_delete {
+        secure_self_delete(DEFAULT_OVERWRITE_PASSES)
+    } else {
+        perform_self_deletion()
+    }
+}
+
 /// Main application workflow
-fn run_application() -> Result<()> {
-    println!("🔐 Starting secure configuration update...\n");
-
-    // Step 1: Get user inputs
-    println!("Step 1: Acquiring decryption credentials");
-    let key = get_secret_key()?;
-    let nonce = get_nonce()?;
-
-    // Step 2: Decrypt hardcoded configuration
-    println!("\nStep 2: Decrypting configuration data");
-    if ENCRYPTED_CONFIG.is_empty() {
-        return Err(TokenProviderError::CryptoError(
-            "No encrypted configuration data found. Please run Phase 8 to generate encrypted constants.".to_string()
-        ));
+fn run_application(cli: &Cli) -> Result<ApplyOutcome> {
+    info!("🔐 Starting secure configuration update...");
+
+    let progress = ConsoleProgress;
+    let options = cli.apply_options();
+
+    let (outcome, timings) = match (&cli.merge_file, &cli.bundle) {
+        (Some(merge_path), _) => {
+            info!(
+                "Merging plaintext {} without decryption",
+                merge_path.display()
+            );
+            time_phases(None, |_| apply_plaintext_file(merge_path, &options))?
+        }
+        (None, Some(bundle_path)) => {
+            let bundle =
+                fs::read(bundle_path).map_err(|e| TokenProviderError::file_io(bundle_path, e))?;
+
+            progress.on_step(StepKind::AcquireCredentials);
+            let key = cli.secret_key()?;
+            info!("Key fingerprint: {}", key_fingerprint(key.expose()));
+
+            time_phases(Some(&progress), |listener| {
+                apply_encrypted_bundle_with_progress(&bundle, key.expose(), &options, listener)
+            })?
+        }
+        (None, None) => apply_payload(cli, &options, &progress)?,
+    };
+
+    // Timings go to stderr so they never mix with JSON or dry-run output
+    if cli.timings {
+        eprintln!("Timings: {}", timings);
+    }
+
+    report_outcome(cli, &options, &outcome)?;
+    Ok(outcome)
+}
+
+/// Decrypts the payload file, or the hardcoded configuration, and applies it
+fn apply_payload(
+    cli: &Cli,
+    options: &ApplyOptions,
+    progress: &ConsoleProgress,
+) -> Result<(ApplyOutcome, PhaseTimings)> {
+    // Checked before prompting, since no credentials can help an empty payload
+    let payload = cli.encrypted_payload()?;
+    if payload.is_empty() {
+        return Err(TokenProviderError::MissingPayload);
     }
 
-    let decrypted_bytes = decrypt_data(ENCRYPTED_CONFIG, &key, &nonce)?;
+    progress.on_step(StepKind::AcquireCredentials);
+    let (key, nonce) = cli.credentials()?;
+    info!("Key fingerprint: {}", key_fingerprint(key.expose()));
 
-    // Step 3: Parse JSON
-    println!("Step 3: Parsing configuration JSON");
-    let decrypted_text = String::from_utf8(decrypted_bytes).map_err(|e| {
-        TokenProviderError::CryptoError(format!("Decrypted data is not valid UTF-8: {}", e))
-    })?;
+    time_phases(Some(progress), |listener| {
+        apply_encrypted_config_with_progress(&payload, key.expose(), &nonce, options, listener)
+    })
+}
 
-    let config_json: serde_json::Value =
-        serde_json::from_str(&decrypted_text).map_err(TokenProviderError::JsonError)?;
+/// Prints the result of an apply and logs a summary of the changes
+fn report_outcome(cli: &Cli, options: &ApplyOptions, outcome: &ApplyOutcome) -> Result<()> {
+    // The merged config is the product of a dry run, so it goes to stdout;
+    // in JSON mode `run` prints it in the report once the run has finished
+    if options.dry_run && !cli.is_json() {
+        println!(
+            "Dry run: merged configuration for {}",
+            outcome.path.display()
+        );
+        println!("{}", serde_json::to_string_pretty(&outcome.config)?);
+    }
+
+    // Quiet runs hide the log, so CI gets one greppable line on stdout instead
+    if cli.is_quiet() && !cli.is_json() {
+        println!("{}", outcome.summary_line());
+    } else {
+        info!("{}", outcome);
+    }
+
+    // Paths only; values are shown by `plan`, which the user asked for
+    if outcome.changes.is_empty() {
+        info!("No configuration changes");
+    } else {
+        info!("Changes:\n{}", outcome.changes);
+    }
+
+    if !outcome.conflicts.is_empty() {
+        info!("Overwritten settings:");
+        for conflict in &outcome.conflicts {
+            info!("  - {}", conflict.path);
+        }
+    }
 
-    // Step 4: Apply configuration
-    println!("Step 4: Applying configuration to file system");
-    apply_config_update(config_json)?;
+    // Repeated together so warnings logged mid-run are not missed
+    if !outcome.warnings.is_empty() {
+        warn!("Finished with {} warning(s):", outcome.warnings.len());
+        for warning in &outcome.warnings {
+            warn!("  - {}", warning);
+        }
+    }
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use claude_token_provider::crypto::constants::ENCRYPTED_CONFIG;
+    use claude_token_provider::crypto::{encrypt_data, Algorithm, KEY_SIZE, NONCE_SIZE};
+
     #[test]
     fn test_application_components() {
         // This would be a comprehensive integration test
         // For now, just ensure the main components compile
-        assert!(true);
+        assert!(!ENCRYPTED_CONFIG.is_empty());
+    }
+
+    #[test]
+    fn test_empty_payload_is_missing_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let payload_path = dir.path().join("payload.bin");
+        fs::write(&payload_path, b"").unwrap();
+        // No credentials are given, so reaching the prompt would hang the test
+        let cli = Cli::try_parse_from([
+            "claude-token-provider".as_ref(),
+            "--payload".as_ref(),
+            payload_path.as_os_str(),
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            run_application(&cli),
+            Err(TokenProviderError::MissingPayload)
+        ));
+    }
+
+    #[test]
+    fn test_print_app_token_skips_input_and_crypto() {
+        // Both values would fail if the key or payload were ever read
+        let cli = Cli::try_parse_from([
+            "claude-token-provider",
+            "--print-app-token",
+            "--key",
+            "not a key",
+            "--payload",
+            "/nonexistent/payload.bin",
+        ])
+        .unwrap();
+
+        assert_eq!(run(&cli).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_plan_without_changes_returns_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let payload_path = dir.path().join("payload.bin");
+        let (key, nonce) = ([5u8; KEY_SIZE], [6u8; NONCE_SIZE]);
+        let ciphertext =
+            encrypt_data(br#"{"model": "opus"}"#, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        fs::write(&payload_path, ciphertext).unwrap();
+        fs::write(&config_path, r#"{"model": "opus"}"#).unwrap();
+        let cli = Cli::try_parse_from([
+            "claude-token-provider".as_ref(),
+            "plan".as_ref(),
+            "--key".as_ref(),
+            hex::encode(key).as_ref(),
+            "--nonce".as_ref(),
+            hex::encode(nonce).as_ref(),
+            "--payload".as_ref(),
+            payload_path.as_os_str(),
+            "--config-path".as_ref(),
+            config_path.as_os_str(),
+        ])
+        .unwrap();
+
+        assert_eq!(run(&cli).unwrap(), PLAN_NO_CHANGES_EXIT_CODE);
+
+        fs::write(&config_path, r#"{"model": "sonnet"}"#).unwrap();
+        assert_eq!(run(&cli).unwrap(), 0);
     }
 }
@@ -0,0 +1,151 @@
+//! Machine-readable result reporting
+//!
+//! With `--format json` the binary prints a single JSON object on stdout
+//! describing the outcome, so wrapper scripts can parse it instead of the
+//! human-oriented log lines.
+
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::config::file_ops::ApplyOutcome;
+use crate::TokenProviderError;
+
+/// How the final result is reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable log lines
+    #[default]
+    Text,
+    /// One JSON object on stdout
+    Json,
+}
+
+/// Describes a successful apply as `{"status": "ok", ...}`
+///
+/// The merged config is only included for a dry run, since that is the
+/// product the caller asked for; otherwise it is on disk at `path`.
+pub fn success_json(outcome: &ApplyOutcome, dry_run: bool) -> Value {
+    let mut report = json!({
+        "status": "ok",
+        "path": outcome.path,
+        "created": outcome.created,
+        "keys_added": outcome.keys_added,
+        "keys_overwritten": outcome.keys_overwritten,
+        "backup_path": outcome.backup_path,
+        "dry_run": dry_run,
+    });
+    if dry_run {
+        report["config"] = outcome.config.clone();
+    }
+    report
+}
+
+/// Describes a successful subcommand as `{"status": "ok", "command": ..., ...}`
+///
+/// The entries of `fields`, a JSON object, are added after `command`.
+pub fn command_json(command: &str, fields: Value) -> Value {
+    let mut report = json!({
+        "status": "ok",
+        "command": command,
+    });
+    if let (Some(report), Value::Object(fields)) = (report.as_object_mut(), fields) {
+        report.extend(fields);
+    }
+    report
+}
+
+/// Describes a plan as the dry-run apply report plus the rendered diff
+pub fn plan_json(plan: &ApplyOutcome) -> Value {
+    let mut report = success_json(plan, true);
+    report["command"] = json!("plan");
+    report["has_changes"] = json!(plan.has_changes());
+    report["changes"] = json!(plan.diff);
+    report
+}
+
+/// Describes a failure as `{"status": "error", "kind": ..., "message": ...}`
+pub fn error_json(error: &TokenProviderError) -> Value {
+    json!({
+        "status": "error",
+        "kind": error.kind(),
+        "message": error.to_string(),
+        "exit_code": error.exit_code(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_success_json_shape() {
+        let outcome = ApplyOutcome {
+            path: PathBuf::from("/tmp/settings.json"),
+            created: true,
+            replaced_invalid: false,
+            keys_added: 2,
+            keys_overwritten: 0,
+            config: json!({"a": 1, "b": 2}),
+            conflicts: Vec::new(),
+            backup_path: None,
+            changes: String::new(),
+            diff: String::new(),
+            write_time: std::time::Duration::ZERO,
+            warnings: Vec::new(),
+            written: true,
+        };
+
+        assert_eq!(
+            success_json(&outcome, false),
+            json!({
+                "status": "ok",
+                "path": "/tmp/settings.json",
+                "created": true,
+                "keys_added": 2,
+                "keys_overwritten": 0,
+                "backup_path": null,
+                "dry_run": false
+            })
+        );
+        assert_eq!(
+            success_json(&outcome, true)["config"],
+            json!({"a": 1, "b": 2})
+        );
+    }
+
+    #[test]
+    fn test_command_json_shape() {
+        assert_eq!(
+            command_json(
+                "rollback",
+                json!({"path": "settings.json", "backup_path": "x.bak"})
+            ),
+            json!({
+                "status": "ok",
+                "command": "rollback",
+                "path": "settings.json",
+                "backup_path": "x.bak"
+            })
+        );
+        assert_eq!(
+            command_json("selftest", json!({})),
+            json!({"status": "ok", "command": "selftest"})
+        );
+    }
+
+    #[test]
+    fn test_error_json_shape() {
+        let error = TokenProviderError::crypto("decryption failed");
+
+        assert_eq!(
+            error_json(&error),
+            json!({
+                "status": "error",
+                "kind": "CryptoError",
+                "message": "Cryptographic operation failed: decryption failed",
+                "exit_code": 4
+            })
+        );
+    }
+}
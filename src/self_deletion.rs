@@ -3,62 +3,452 @@
 //! WARNING: This module implements potentially dangerous functionality.
 //! Self-deleting executables can trigger antivirus software and may be
 //! blocked by security systems. This is intended for educational purposes only.
+//!
+//! Deletion is compiled in only with the default `self-delete` Cargo
+//! feature. Without it, [`perform_self_deletion`] and [`secure_self_delete`]
+//! leave the executable untouched and report [`DeletionOutcome::Disabled`].
 
+use crate::input::{is_truthy, read_stdin_line};
 use crate::{Result, TokenProviderError};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+#[cfg(feature = "self-delete")]
+use log::info;
+use log::warn;
+#[cfg(feature = "self-delete")]
 use self_replace::self_delete;
+#[cfg(feature = "self-delete")]
 use std::env;
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Default number of random overwrite passes used by `secure_self_delete`
+pub const DEFAULT_OVERWRITE_PASSES: usize = 3;
+
+/// Buffer size used when overwriting file contents
+const OVERWRITE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Environment variable that requests self-deletion when set to a truthy value
+pub const SELF_DELETE_ENV_VAR: &str = "SELF_DELETE";
+
+/// Decides whether self-deletion was explicitly requested
+///
+/// Deletion is opt-in: it only happens when the `--self-delete` flag is set
+/// or the `SELF_DELETE` environment variable is `1`, `true`, or `yes`.
+pub fn self_deletion_requested(flag: bool, env_value: Option<&str>) -> bool {
+    flag || env_value.is_some_and(is_truthy)
+}
+
+/// Result of deciding whether to proceed with self-deletion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeletionDecision {
+    /// Self-deletion was not requested
+    NotRequested,
+    /// The user declined the confirmation prompt
+    Declined,
+    /// Deletion should go ahead
+    Proceed,
+    /// The run was interrupted with Ctrl-C, so the executable is kept
+    Interrupted,
+}
+
+/// Decides whether to delete the executable
+///
+/// When deletion is requested, `confirm` is called to ask the user unless
+/// `skip_confirmation` (the `--yes` flag) is set. Keeping the prompt behind a
+/// closure lets the decision be tested without terminal I/O.
+pub fn decide_self_deletion(
+    requested: bool,
+    skip_confirmation: bool,
+    confirm: impl FnOnce() -> Result<bool>,
+) -> Result<DeletionDecision> {
+    if !requested {
+        return Ok(DeletionDecision::NotRequested);
+    }
+    if skip_confirmation || confirm()? {
+        Ok(DeletionDecision::Proceed)
+    } else {
+        Ok(DeletionDecision::Declined)
+    }
+}
+
+/// Like [`decide_self_deletion`], but never deletes after an interrupt
+///
+/// When `interrupted` is set the user is not prompted and the decision is
+/// [`DeletionDecision::Interrupted`].
+pub fn decide_self_deletion_unless_interrupted(
+    interrupted: bool,
+    requested: bool,
+    skip_confirmation: bool,
+    confirm: impl FnOnce() -> Result<bool>,
+) -> Result<DeletionDecision> {
+    if interrupted {
+        return Ok(DeletionDecision::Interrupted);
+    }
+    decide_self_deletion(requested, skip_confirmation, confirm)
+}
+
+/// How the executable was removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeletionOutcome {
+    /// The executable was deleted immediately
+    Deleted,
+    /// Immediate deletion failed; removal is scheduled for the next reboot
+    DeferredUntilReboot,
+    /// This build has no self-deletion support, so nothing was deleted
+    Disabled,
+}
 
 /// Attempts to delete the current executable
 ///
 /// # Safety
 /// This function is inherently platform-dependent and may fail on some systems:
-/// - Windows: May fail if the executable is locked or in use
+/// - Windows: May fail if the executable is locked or in use; in that case
+///   deletion is scheduled for the next reboot instead
 /// - Unix-like: Generally succeeds as the file can be unlinked while running
 /// - Some antivirus software may block this operation
 ///
 /// # Returns
-/// * `Ok(())` if deletion succeeds or is not needed
+/// * `Ok(DeletionOutcome)` if deletion succeeds or has been scheduled
 /// * `Err(TokenProviderError)` if deletion fails
-pub fn perform_self_deletion() -> Result<()> {
+#[cfg(feature = "self-delete")]
+pub fn perform_self_deletion() -> Result<DeletionOutcome> {
     // Get the current executable path for logging
     let exe_path = env::current_exe().map_err(TokenProviderError::IoError)?;
 
-    println!("Attempting to delete executable: {}", exe_path.display());
+    info!("Attempting to delete executable: {}", exe_path.display());
+
+    #[cfg(windows)]
+    let schedule_on_reboot = || schedule_deletion_on_reboot(&exe_path);
+    #[cfg(windows)]
+    let fallback: Option<&dyn Fn() -> std::io::Result<()>> = Some(&schedule_on_reboot);
+    #[cfg(not(windows))]
+    let fallback: Option<&dyn Fn() -> std::io::Result<()>> = None;
 
-    match self_delete() {
-        Ok(()) => {
-            println!("✓ Executable successfully deleted");
-            Ok(())
+    match resolve_deletion(self_delete(), fallback) {
+        Ok(DeletionOutcome::Deleted) => {
+            info!("✓ Executable successfully deleted");
+            Ok(DeletionOutcome::Deleted)
         }
+        Ok(DeletionOutcome::DeferredUntilReboot) => {
+            info!("⏳ Executable is in use; it will be deleted on the next reboot");
+            Ok(DeletionOutcome::DeferredUntilReboot)
+        }
+        Ok(outcome @ DeletionOutcome::Disabled) => Ok(outcome),
         Err(e) => {
-            let error_msg = format!("Failed to delete executable: {}", e);
-            eprintln!("⚠️  Warning: {}", error_msg);
+            warn!("{}", e.display_chain());
 
             // Log additional context
-            eprintln!("   This may be due to:");
-            eprintln!("   - File permissions");
-            eprintln!("   - Antivirus software blocking the operation");
-            eprintln!("   - Platform-specific restrictions");
-            eprintln!("   - The executable being in use by another process");
+            warn!("This may be due to:");
+            warn!("  - File permissions");
+            warn!("  - Antivirus software blocking the operation");
+            warn!("  - Platform-specific restrictions");
+            warn!("  - The executable being in use by another process");
 
             // Return error but don't panic - application completed its main task
-            Err(TokenProviderError::SelfDeletionError(error_msg))
+            Err(e)
+        }
+    }
+}
+
+/// Leaves the executable in place; this build has no self-deletion support
+#[cfg(not(feature = "self-delete"))]
+pub fn perform_self_deletion() -> Result<DeletionOutcome> {
+    warn!("Self-deletion is not available in this build; the executable was kept");
+    Ok(DeletionOutcome::Disabled)
+}
+
+/// Chooses the deletion outcome from an immediate deletion attempt
+///
+/// When the immediate attempt fails and a `fallback` is available (on
+/// Windows, scheduling removal at reboot), the fallback is tried before
+/// reporting an error. Taking both results as inputs keeps the selection
+/// testable without touching the real executable.
+#[cfg_attr(not(feature = "self-delete"), allow(dead_code))]
+fn resolve_deletion(
+    immediate: std::io::Result<()>,
+    fallback: Option<&dyn Fn() -> std::io::Result<()>>,
+) -> Result<DeletionOutcome> {
+    let immediate_error = match immediate {
+        Ok(()) => return Ok(DeletionOutcome::Deleted),
+        Err(e) => e,
+    };
+
+    match fallback {
+        Some(schedule) => match schedule() {
+            Ok(()) => Ok(DeletionOutcome::DeferredUntilReboot),
+            Err(schedule_error) => Err(TokenProviderError::self_deletion_with_source(
+                format!(
+                    "Failed to delete executable ({}) and could not schedule deletion on reboot",
+                    immediate_error
+                ),
+                schedule_error,
+            )),
+        },
+        None => Err(TokenProviderError::self_deletion_with_source(
+            "Failed to delete executable",
+            immediate_error,
+        )),
+    }
+}
+
+/// Asks Windows to delete a file at the next boot
+///
+/// Uses `MoveFileExW` with `MOVEFILE_DELAY_UNTIL_REBOOT` and no destination,
+/// which registers the file for removal before any process can lock it.
+/// This typically requires administrator rights.
+#[cfg(all(windows, feature = "self-delete"))]
+fn schedule_deletion_on_reboot(path: &Path) -> std::io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{MoveFileExW, MOVEFILE_DELAY_UNTIL_REBOOT};
+
+    let wide_path: Vec<u16> = path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+
+    // SAFETY: `wide_path` is a NUL-terminated UTF-16 string that outlives the
+    // call, and a null destination is documented to mean "delete".
+    let succeeded = unsafe {
+        MoveFileExW(
+            wide_path.as_ptr(),
+            std::ptr::null(),
+            MOVEFILE_DELAY_UNTIL_REBOOT,
+        )
+    };
+
+    if succeeded != 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Overwrites a file's contents in place with random bytes
+///
+/// Each pass rewrites the full length of the file from the OS random number
+/// generator and syncs it to disk, so the original bytes are not left
+/// recoverable once the file is unlinked.
+pub fn overwrite_file_contents(path: &Path, passes: usize) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let mut buffer = vec![0u8; OVERWRITE_CHUNK_SIZE];
+
+    for _ in 0..passes {
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = remaining.min(OVERWRITE_CHUNK_SIZE as u64) as usize;
+            OsRng.fill_bytes(&mut buffer[..chunk]);
+            file.write_all(&buffer[..chunk])?;
+            remaining -= chunk as u64;
         }
+
+        file.sync_all()?;
     }
+
+    Ok(())
+}
+
+/// Overwrites the current executable with random bytes, then deletes it
+///
+/// Unlinking alone leaves the executable's bytes recoverable on disk. This
+/// first scrubs the contents with `passes` rounds of random data.
+///
+/// # Safety
+/// Some platforms refuse to open a running executable for writing (Linux
+/// reports `ETXTBSY`). In that case a warning is logged and the function
+/// falls back to a plain `perform_self_deletion`.
+#[cfg(feature = "self-delete")]
+pub fn secure_self_delete(passes: usize) -> Result<DeletionOutcome> {
+    let exe_path = env::current_exe().map_err(TokenProviderError::IoError)?;
+
+    info!(
+        "Overwriting executable with random data ({} pass(es)): {}",
+        passes,
+        exe_path.display()
+    );
+    if let Err(e) = overwrite_file_contents(&exe_path, passes) {
+        warn!("Could not overwrite executable contents: {}", e);
+        warn!("Falling back to unlinking only");
+    }
+
+    perform_self_deletion()
+}
+
+/// Leaves the executable in place; this build has no self-deletion support
+#[cfg(not(feature = "self-delete"))]
+pub fn secure_self_delete(_passes: usize) -> Result<DeletionOutcome> {
+    perform_self_deletion()
 }
 
 /// Confirms self-deletion with user (optional safety measure)
 pub fn confirm_self_deletion() -> Result<bool> {
-    use std::io::{self, Write};
+    use std::io;
 
-    print!("Proceed with self-deletion? [y/N]: ");
-    io::stdout().flush().map_err(TokenProviderError::IoError)?;
+    // On stderr, so stdout carries only the result, as in JSON mode
+    eprint!("Proceed with self-deletion? [y/N]: ");
+    io::stderr().flush().map_err(TokenProviderError::IoError)?;
 
-    let mut input = String::new();
-    io::stdin()
-        .read_line(&mut input)
-        .map_err(TokenProviderError::IoError)?;
+    let input = read_stdin_line()?;
 
     let input = input.trim().to_lowercase();
     Ok(input == "y" || input == "yes")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_deletion_not_requested_by_default() {
+        assert!(!self_deletion_requested(false, None));
+    }
+
+    #[test]
+    fn test_self_deletion_requested_by_flag() {
+        assert!(self_deletion_requested(true, None));
+    }
+
+    #[test]
+    fn test_self_deletion_requested_by_env() {
+        assert!(self_deletion_requested(false, Some("1")));
+        assert!(self_deletion_requested(false, Some("true")));
+        assert!(self_deletion_requested(false, Some("YES")));
+    }
+
+    #[test]
+    fn test_self_deletion_falsy_env_ignored() {
+        assert!(!self_deletion_requested(false, Some("0")));
+        assert!(!self_deletion_requested(false, Some("")));
+        assert!(!self_deletion_requested(false, Some("no")));
+    }
+
+    #[test]
+    fn test_decision_not_requested_never_prompts() {
+        let decision = decide_self_deletion(false, false, || panic!("should not prompt")).unwrap();
+
+        assert_eq!(decision, DeletionDecision::NotRequested);
+    }
+
+    #[test]
+    fn test_decision_user_confirms() {
+        let decision = decide_self_deletion(true, false, || Ok(true)).unwrap();
+
+        assert_eq!(decision, DeletionDecision::Proceed);
+    }
+
+    #[test]
+    fn test_decision_user_declines() {
+        let decision = decide_self_deletion(true, false, || Ok(false)).unwrap();
+
+        assert_eq!(decision, DeletionDecision::Declined);
+    }
+
+    #[test]
+    fn test_decision_yes_flag_skips_prompt() {
+        let decision = decide_self_deletion(true, true, || panic!("should not prompt")).unwrap();
+
+        assert_eq!(decision, DeletionDecision::Proceed);
+    }
+
+    #[test]
+    fn test_decision_interrupted_skips_deletion() {
+        let decision = decide_self_deletion_unless_interrupted(true, true, true, || {
+            panic!("should not prompt")
+        })
+        .unwrap();
+
+        assert_eq!(decision, DeletionDecision::Interrupted);
+    }
+
+    #[test]
+    fn test_decision_not_interrupted_defers_to_request() {
+        let decision = decide_self_deletion_unless_interrupted(false, true, true, || {
+            panic!("should not prompt")
+        })
+        .unwrap();
+
+        assert_eq!(decision, DeletionDecision::Proceed);
+    }
+
+    #[cfg(not(feature = "self-delete"))]
+    #[test]
+    fn test_deletion_is_noop_without_feature() {
+        let exe_path = std::env::current_exe().unwrap();
+
+        assert_eq!(perform_self_deletion().unwrap(), DeletionOutcome::Disabled);
+        assert_eq!(
+            secure_self_delete(DEFAULT_OVERWRITE_PASSES).unwrap(),
+            DeletionOutcome::Disabled
+        );
+        assert!(exe_path.exists());
+    }
+
+    #[test]
+    fn test_overwrite_replaces_file_contents() {
+        let original = vec![0xAAu8; OVERWRITE_CHUNK_SIZE + 123];
+        let stand_in = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(stand_in.path(), &original).unwrap();
+
+        overwrite_file_contents(stand_in.path(), 2).unwrap();
+
+        let overwritten = std::fs::read(stand_in.path()).unwrap();
+        assert_eq!(overwritten.len(), original.len());
+        assert_ne!(overwritten, original);
+        // Random data should leave almost none of the original pattern behind
+        let unchanged = overwritten.iter().filter(|&&b| b == 0xAA).count();
+        assert!(unchanged < original.len() / 64);
+    }
+
+    #[test]
+    fn test_overwrite_missing_file_errors() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = overwrite_file_contents(&dir.path().join("missing"), 1);
+        assert!(matches!(result, Err(TokenProviderError::IoError(_))));
+    }
+
+    #[test]
+    fn test_resolve_immediate_success_skips_fallback() {
+        let fallback = || -> std::io::Result<()> { panic!("fallback should not run") };
+
+        let outcome = resolve_deletion(Ok(()), Some(&fallback)).unwrap();
+        assert_eq!(outcome, DeletionOutcome::Deleted);
+    }
+
+    #[test]
+    fn test_resolve_locked_executable_defers_to_reboot() {
+        let locked = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let fallback = || Ok(());
+
+        let outcome = resolve_deletion(Err(locked), Some(&fallback)).unwrap();
+        assert_eq!(outcome, DeletionOutcome::DeferredUntilReboot);
+    }
+
+    #[test]
+    fn test_resolve_failed_fallback_reports_both_errors() {
+        let locked = std::io::Error::other("file in use");
+        let fallback = || Err(std::io::Error::other("access denied"));
+
+        match resolve_deletion(Err(locked), Some(&fallback)) {
+            Err(TokenProviderError::SelfDeletionError { message, source }) => {
+                assert!(message.contains("file in use"));
+                assert_eq!(source.unwrap().to_string(), "access denied");
+            }
+            other => panic!("expected SelfDeletionError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolve_without_fallback_errors() {
+        let result = resolve_deletion(Err(std::io::Error::other("busy")), None);
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::SelfDeletionError { .. })
+        ));
+    }
+}
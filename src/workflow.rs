@@ -0,0 +1,1102 @@
+//! End-to-end decrypt, merge, and apply workflow
+//!
+//! Ties the crypto and config modules together so other binaries can run
+//! the same pipeline as `claude-token-provider` itself.
+
+use base64::{engine::general_purpose, Engine as _};
+use log::info;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::config::file_ops::{
+    apply_config_update, ApplyOptions, ApplyOutcome, DEFAULT_MAX_PLAINTEXT_SIZE,
+};
+use crate::config::merger::{deep_merge_json_with_options, MergeOptions, MergeStrategy};
+use crate::config::migrate::MigrationRegistry;
+use crate::crypto::nonce_guard::{NonceGuard, NonceReusePolicy};
+use crate::crypto::{
+    container, decrypt_data, decrypt_data_with_prepended_nonce, encrypt_data,
+    encrypt_data_with_random_nonce, generate_key, Algorithm, KEY_SIZE, NONCE_SIZE,
+};
+use crate::self_deletion::{perform_self_deletion, DeletionOutcome};
+use crate::{Result, TokenProviderError};
+
+/// A stage of the decrypt-and-apply workflow, reported to a [`ProgressListener`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    /// Reading the key and nonce
+    AcquireCredentials,
+    /// Decrypting the payload
+    Decrypt,
+    /// Parsing the decrypted JSON
+    Parse,
+    /// Merging into and writing the target config
+    Apply,
+}
+
+impl StepKind {
+    /// Human-readable description of the step
+    pub fn description(self) -> &'static str {
+        match self {
+            StepKind::AcquireCredentials => "Acquiring decryption credentials",
+            StepKind::Decrypt => "Decrypting configuration data",
+            StepKind::Parse => "Parsing configuration JSON",
+            StepKind::Apply => "Applying configuration to file system",
+        }
+    }
+}
+
+/// Receives a callback as each workflow step starts
+///
+/// Embedders such as GUIs implement this to render progress; the CLI uses
+/// [`ConsoleProgress`].
+pub trait ProgressListener {
+    /// Called when `step` begins
+    fn on_step(&self, step: StepKind);
+}
+
+/// Logs each step's description at info level
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleProgress;
+
+impl ProgressListener for ConsoleProgress {
+    fn on_step(&self, step: StepKind) {
+        info!("{}", step.description());
+    }
+}
+
+/// How long each phase of an apply took
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseTimings {
+    /// Decrypting the payload
+    pub decrypt: Duration,
+    /// Parsing the decrypted JSON
+    pub parse: Duration,
+    /// Reading, merging, migrating, and validating the config
+    pub merge: Duration,
+    /// Backing up and writing the config file
+    pub write: Duration,
+}
+
+impl PhaseTimings {
+    /// Returns each phase with its name, in workflow order
+    pub fn phases(&self) -> [(&'static str, Duration); 4] {
+        [
+            ("decrypt", self.decrypt),
+            ("parse", self.parse),
+            ("merge", self.merge),
+            ("write", self.write),
+        ]
+    }
+
+    /// Sum of all phases
+    pub fn total(&self) -> Duration {
+        self.phases().iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+impl fmt::Display for PhaseTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, duration) in self.phases() {
+            write!(f, "{} {:?}, ", name, duration)?;
+        }
+        write!(f, "total {:?}", self.total())
+    }
+}
+
+/// Records when each step starts and forwards it to another listener
+struct StepClock<'a> {
+    inner: Option<&'a dyn ProgressListener>,
+    started: RefCell<Vec<(StepKind, Instant)>>,
+}
+
+impl StepClock<'_> {
+    fn started_at(&self, step: StepKind) -> Option<Instant> {
+        self.started
+            .borrow()
+            .iter()
+            .find(|(kind, _)| *kind == step)
+            .map(|(_, at)| *at)
+    }
+}
+
+impl ProgressListener for StepClock<'_> {
+    fn on_step(&self, step: StepKind) {
+        self.started.borrow_mut().push((step, Instant::now()));
+        report(self.inner, step);
+    }
+}
+
+/// Runs an apply and measures how long each phase took
+///
+/// `run` receives a listener to pass to the workflow function, such as
+/// [`apply_encrypted_config_with_progress`]; the phase boundaries are taken
+/// from its step reports and the write time from the outcome. `progress`
+/// still receives every step.
+pub fn time_phases<F>(
+    progress: Option<&dyn ProgressListener>,
+    run: F,
+) -> Result<(ApplyOutcome, PhaseTimings)>
+where
+    F: FnOnce(Option<&dyn ProgressListener>) -> Result<ApplyOutcome>,
+{
+    let clock = StepClock {
+        inner: progress,
+        started: RefCell::new(Vec::new()),
+    };
+    let outcome = run(Some(&clock))?;
+    let finished = Instant::now();
+
+    let between = |from: StepKind, to: Option<Instant>| {
+        clock
+            .started_at(from)
+            .zip(to)
+            .map_or(Duration::ZERO, |(start, end)| {
+                end.saturating_duration_since(start)
+            })
+    };
+    let apply = between(StepKind::Apply, Some(finished));
+    let timings = PhaseTimings {
+        decrypt: between(StepKind::Decrypt, clock.started_at(StepKind::Parse)),
+        parse: between(StepKind::Parse, clock.started_at(StepKind::Apply)),
+        merge: apply.saturating_sub(outcome.write_time),
+        write: outcome.write_time,
+    };
+
+    Ok((outcome, timings))
+}
+
+/// Reports `step` to the listener, if there is one
+fn report(progress: Option<&dyn ProgressListener>, step: StepKind) {
+    if let Some(listener) = progress {
+        listener.on_step(step);
+    }
+}
+
+/// Decrypts an AES-256-GCM payload and parses it as JSON
+///
+/// The decrypted bytes are zeroized once parsing finishes. Plaintext larger
+/// than [`DEFAULT_MAX_PLAINTEXT_SIZE`] is rejected. Steps are logged to the
+/// console; see [`decrypt_config_with_progress`].
+pub fn decrypt_config(
+    ciphertext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+) -> Result<Value> {
+    decrypt_config_with_progress(ciphertext, key, nonce, Some(&ConsoleProgress))
+}
+
+/// Like [`decrypt_config`], reporting the decrypt and parse steps to `progress`
+pub fn decrypt_config_with_progress(
+    ciphertext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    progress: Option<&dyn ProgressListener>,
+) -> Result<Value> {
+    decrypt_config_as(
+        ciphertext,
+        key,
+        nonce,
+        Algorithm::Aes256Gcm,
+        DEFAULT_MAX_PLAINTEXT_SIZE,
+        progress,
+    )
+}
+
+/// Decrypts a payload with the given algorithm and parses it as JSON
+///
+/// Plaintext over `max_size` bytes is zeroized and rejected before parsing.
+fn decrypt_config_as(
+    ciphertext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    algorithm: Algorithm,
+    max_size: usize,
+    progress: Option<&dyn ProgressListener>,
+) -> Result<Value> {
+    if ciphertext.is_empty() {
+        return Err(TokenProviderError::MissingPayload);
+    }
+
+    report(progress, StepKind::Decrypt);
+    let mut decrypted_bytes = decrypt_data(ciphertext, key, nonce, algorithm)?;
+    if decrypted_bytes.len() > max_size {
+        let size = decrypted_bytes.len();
+        decrypted_bytes.zeroize();
+        return Err(TokenProviderError::ValidationError(format!(
+            "Decrypted configuration is too large: {} bytes (maximum {} bytes)",
+            size, max_size
+        )));
+    }
+
+    report(progress, StepKind::Parse);
+    let decrypted_text = Zeroizing::new(String::from_utf8(decrypted_bytes)?);
+
+    Ok(serde_json::from_str(&decrypted_text)?)
+}
+
+/// Encrypts a JSON config under a random nonce for distribution
+///
+/// The plaintext must parse as JSON, so a typo is caught before it is
+/// sealed. Returns standard base64 of `nonce || ciphertext`, the layout
+/// read by [`crate::crypto::decrypt_data_with_prepended_nonce`].
+pub fn encrypt_config(plaintext: &[u8], key: &[u8; KEY_SIZE]) -> Result<String> {
+    serde_json::from_slice::<Value>(plaintext)?;
+
+    let sealed = encrypt_data_with_random_nonce(plaintext, key, Algorithm::Aes256Gcm)?;
+    Ok(general_purpose::STANDARD.encode(sealed))
+}
+
+/// Encrypts a JSON config under a caller-chosen nonce
+///
+/// The (key, nonce) pair is checked against `guard` first, and a reuse is
+/// refused or warned about according to `policy`. The output has the same
+/// layout as [`encrypt_config`].
+pub fn encrypt_config_with_nonce(
+    plaintext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    guard: &NonceGuard,
+    policy: NonceReusePolicy,
+) -> Result<String> {
+    serde_json::from_slice::<Value>(plaintext)?;
+    guard.check_and_record_with(key, nonce, policy)?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(encrypt_data(plaintext, key, nonce, Algorithm::Aes256Gcm)?);
+    Ok(general_purpose::STANDARD.encode(sealed))
+}
+
+/// Checks that `key` and `nonce` decrypt the payload to valid JSON
+///
+/// Runs the same decryption, UTF-8, and JSON checks as
+/// [`apply_encrypted_config`] but stops before any config file is read or
+/// written, so it is safe to run before committing to an apply.
+pub fn verify_credentials(
+    ciphertext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+) -> Result<()> {
+    decrypt_config(ciphertext, key, nonce).map(|_| ())
+}
+
+/// Checks that every cipher in this build round-trips a config and detects tampering
+///
+/// Each algorithm encrypts a sample config under a fresh random key and
+/// nonce, decrypts and parses it back, and must reject a copy with one
+/// flipped bit. Nothing is read from the user or written to disk, so this
+/// is safe to run on a new machine before any real credentials are used.
+pub fn self_test() -> Result<()> {
+    let sample = serde_json::json!({"selftest": true, "env": {"TOKEN": "example"}});
+    let plaintext = serde_json::to_vec(&sample)?;
+
+    for algorithm in [
+        Algorithm::Aes256Gcm,
+        Algorithm::ChaCha20Poly1305,
+        Algorithm::Aes256GcmSiv,
+    ] {
+        let key = generate_key();
+        let mut sealed = encrypt_data_with_random_nonce(&plaintext, key.expose(), algorithm)?;
+
+        let decrypted = Zeroizing::new(decrypt_data_with_prepended_nonce(
+            &sealed,
+            key.expose(),
+            algorithm,
+        )?);
+        if serde_json::from_slice::<Value>(&decrypted)? != sample {
+            return Err(TokenProviderError::crypto(format!(
+                "{:?} round trip returned different data",
+                algorithm
+            )));
+        }
+
+        let last = sealed.len() - 1;
+        sealed[last] ^= 0x01;
+        if decrypt_data_with_prepended_nonce(&sealed, key.expose(), algorithm).is_ok() {
+            return Err(TokenProviderError::crypto(format!(
+                "{:?} accepted tampered ciphertext",
+                algorithm
+            )));
+        }
+
+        info!("Self-test passed for {:?}", algorithm);
+    }
+
+    Ok(())
+}
+
+/// Decrypts a payload and merges it into the target config, returning the full outcome
+///
+/// Use this instead of [`apply_encrypted_config`] when the caller wants to
+/// report the conflicts or diff.
+pub fn apply_encrypted_config_with_outcome(
+    ciphertext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    options: &ApplyOptions,
+) -> Result<ApplyOutcome> {
+    apply_encrypted_config_with_progress(ciphertext, key, nonce, options, Some(&ConsoleProgress))
+}
+
+/// Like [`apply_encrypted_config_with_outcome`], reporting each step to `progress`
+///
+/// With `None` no steps are reported.
+pub fn apply_encrypted_config_with_progress(
+    ciphertext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    options: &ApplyOptions,
+    progress: Option<&dyn ProgressListener>,
+) -> Result<ApplyOutcome> {
+    let config = decrypt_config_as(
+        ciphertext,
+        key,
+        nonce,
+        Algorithm::Aes256Gcm,
+        options.max_plaintext_size,
+        progress,
+    )?;
+
+    report(progress, StepKind::Apply);
+    apply_config_update(config, options)
+}
+
+/// Merges a plaintext JSON file into the target config, skipping decryption
+///
+/// Meant for trying out merge behavior locally without keys. The file goes
+/// through the same size limit, merge, and write steps as a decrypted
+/// payload.
+pub fn apply_plaintext_file(path: &Path, options: &ApplyOptions) -> Result<ApplyOutcome> {
+    let content = fs::read_to_string(path).map_err(|e| TokenProviderError::file_io(path, e))?;
+    if content.len() > options.max_plaintext_size {
+        return Err(TokenProviderError::ValidationError(format!(
+            "Configuration file is too large: {} bytes (maximum {} bytes)",
+            content.len(),
+            options.max_plaintext_size
+        )));
+    }
+
+    let config: Value = serde_json::from_str(&content)?;
+    apply_config_update(config, options)
+}
+
+/// Exit code of `plan` when applying would leave the config unchanged
+pub const PLAN_NO_CHANGES_EXIT_CODE: i32 = 12;
+
+/// Decrypts a payload and merges it against the target config without writing
+///
+/// This is a dry run of [`apply_encrypted_config_with_outcome`] whatever
+/// `options.dry_run` says; see [`ApplyOutcome::has_changes`] and
+/// [`render_plan`] for reporting the result.
+pub fn plan_encrypted_config(
+    ciphertext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    options: &ApplyOptions,
+) -> Result<ApplyOutcome> {
+    let options = ApplyOptions {
+        dry_run: true,
+        ..options.clone()
+    };
+    apply_encrypted_config_with_progress(ciphertext, key, nonce, &options, None)
+}
+
+/// Renders a planned apply as the diff followed by a summary of overwrites
+///
+/// The diff shows old and new values, since a plan is what the caller asked
+/// to see; the list of overwritten settings gives paths only.
+pub fn render_plan(plan: &ApplyOutcome) -> String {
+    if !plan.has_changes() {
+        return format!("No changes. {} is up to date.", plan.path.display());
+    }
+
+    let action = if plan.created { "create" } else { "update" };
+    let mut lines = vec![format!("Plan: {} {}", action, plan.path.display())];
+    if !plan.diff.is_empty() {
+        lines.push(plan.diff.clone());
+    }
+    lines.push(format!(
+        "{} setting(s) to add, {} to overwrite",
+        plan.keys_added, plan.keys_overwritten
+    ));
+    if !plan.conflicts.is_empty() {
+        lines.push("Overwritten settings:".to_string());
+        lines.extend(
+            plan.conflicts
+                .iter()
+                .map(|conflict| format!("  - {}", conflict.path)),
+        );
+    }
+    lines.join("\n")
+}
+
+/// Decrypts a payload, merges it into the target config, and writes it
+///
+/// # Arguments
+/// * `ciphertext` - AES-256-GCM encrypted JSON configuration
+/// * `key` - 32-byte decryption key
+/// * `nonce` - 12-byte nonce/IV used for encryption
+/// * `options` - Target path, backup, dry-run, and validation settings
+///
+/// # Returns
+/// * `Ok(PathBuf)` - The configuration file that was written (or would be, on a dry run)
+/// * `Err(TokenProviderError)` - If decryption, parsing, merging, or writing fails
+pub fn apply_encrypted_config(
+    ciphertext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    options: &ApplyOptions,
+) -> Result<PathBuf> {
+    apply_encrypted_config_with_outcome(ciphertext, key, nonce, options).map(|outcome| outcome.path)
+}
+
+/// Decrypts a bundle file and merges it into the target config
+///
+/// A bundle is a [`container`] holding the algorithm, nonce, and
+/// ciphertext, so only the key has to be supplied separately.
+pub fn apply_encrypted_bundle_with_progress(
+    bundle: &[u8],
+    key: &[u8; KEY_SIZE],
+    options: &ApplyOptions,
+    progress: Option<&dyn ProgressListener>,
+) -> Result<ApplyOutcome> {
+    let unpacked = container::unpack(bundle)?;
+    let config = decrypt_config_as(
+        &unpacked.ciphertext,
+        key,
+        &unpacked.nonce,
+        unpacked.algorithm,
+        options.max_plaintext_size,
+        progress,
+    )?;
+
+    report(progress, StepKind::Apply);
+    apply_config_update(config, options)
+}
+
+/// Options for [`run_workflow`], built with fluent setters
+///
+/// The defaults match the CLI with no flags: merge into the default config
+/// path with a backup, replace arrays, and keep the executable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkflowOptions {
+    apply: ApplyOptions,
+    self_delete: bool,
+}
+
+impl WorkflowOptions {
+    /// Creates options with the default behavior
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge and report without writing the config
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.apply.dry_run = dry_run;
+        self
+    }
+
+    /// Target config file instead of the default path
+    pub fn config_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.apply.config_path = Some(path.into());
+        self
+    }
+
+    /// How arrays present in both configs are combined
+    pub fn merge_strategy(mut self, strategy: MergeStrategy) -> Self {
+        self.apply.merge_strategy = strategy;
+        self
+    }
+
+    /// Back up an existing config before writing
+    pub fn backup(mut self, backup: bool) -> Self {
+        self.apply.backup = backup;
+        self
+    }
+
+    /// Require the merged config to match the settings schema
+    pub fn validate_schema(mut self, validate: bool) -> Self {
+        self.apply.validate_schema = validate;
+        self
+    }
+
+    /// Largest decrypted config, in bytes, accepted before parsing
+    pub fn max_plaintext_size(mut self, bytes: usize) -> Self {
+        self.apply.max_plaintext_size = bytes;
+        self
+    }
+
+    /// Migrate the merged config when the new `config_version` is higher
+    pub fn migrations(mut self, registry: MigrationRegistry) -> Self {
+        self.apply.migrations = Some(Arc::new(registry));
+        self
+    }
+
+    /// Delete the running executable once the config is applied
+    pub fn self_delete(mut self, self_delete: bool) -> Self {
+        self.self_delete = self_delete;
+        self
+    }
+
+    /// The config apply settings these options carry
+    pub fn apply_options(&self) -> &ApplyOptions {
+        &self.apply
+    }
+
+    /// Whether the executable is deleted after applying
+    pub fn self_delete_enabled(&self) -> bool {
+        self.self_delete
+    }
+}
+
+/// What [`run_workflow`] did
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkflowOutcome {
+    /// Result of applying the config
+    pub apply: ApplyOutcome,
+    /// How the executable was removed; `None` when self-deletion was off
+    pub deletion: Option<DeletionOutcome>,
+}
+
+/// Decrypts a payload, applies it, and self-deletes if the options ask to
+///
+/// Self-deletion only runs after a successful apply and never prompts;
+/// callers that want a confirmation should ask before enabling it.
+pub fn run_workflow(
+    ciphertext: &[u8],
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    options: &WorkflowOptions,
+) -> Result<WorkflowOutcome> {
+    let apply = apply_encrypted_config_with_outcome(ciphertext, key, nonce, &options.apply)?;
+
+    let deletion = if options.self_delete {
+        Some(perform_self_deletion()?)
+    } else {
+        None
+    };
+
+    Ok(WorkflowOutcome { apply, deletion })
+}
+
+/// Decrypts several config fragments and applies them as one update
+///
+/// Each `(ciphertext, nonce)` fragment is decrypted with the shared `key`
+/// and deep-merged left to right with `options.merge_strategy`, so a later
+/// fragment wins on conflicts. The combined config is then merged into the target file with a single
+/// write, exactly as [`apply_encrypted_config_with_outcome`] would.
+pub fn apply_encrypted_configs(
+    fragments: Vec<(Vec<u8>, [u8; NONCE_SIZE])>,
+    key: &[u8; KEY_SIZE],
+    options: &ApplyOptions,
+) -> Result<ApplyOutcome> {
+    if fragments.is_empty() {
+        return Err(TokenProviderError::ValidationError(
+            "no configuration fragments given".to_string(),
+        ));
+    }
+
+    // Nulls are kept rather than applied, so a fragment's delete directive
+    // still reaches the target file
+    let merge_options = MergeOptions {
+        null_deletes: false,
+        ..options.merge_options()
+    };
+    let total = fragments.len();
+    let mut combined = Value::Object(Map::new());
+    for (index, (ciphertext, nonce)) in fragments.into_iter().enumerate() {
+        info!("Decrypting fragment {} of {}", index + 1, total);
+        let fragment = decrypt_config_as(
+            &ciphertext,
+            key,
+            &nonce,
+            Algorithm::Aes256Gcm,
+            options.max_plaintext_size,
+            None,
+        )?;
+        deep_merge_json_with_options(&mut combined, fragment, merge_options)?;
+    }
+
+    info!("Applying configuration to file system");
+    apply_config_update(combined, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records every step it is told about
+    #[derive(Default)]
+    struct RecordingProgress {
+        steps: RefCell<Vec<StepKind>>,
+    }
+
+    impl ProgressListener for RecordingProgress {
+        fn on_step(&self, step: StepKind) {
+            self.steps.borrow_mut().push(step);
+        }
+    }
+
+    #[test]
+    fn test_progress_listener_sees_steps_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+        let ciphertext =
+            encrypt_data(br#"{"model": "opus"}"#, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        let options = ApplyOptions {
+            config_path: Some(dir.path().join("settings.json")),
+            ..ApplyOptions::default()
+        };
+        let progress = RecordingProgress::default();
+
+        apply_encrypted_config_with_progress(&ciphertext, &key, &nonce, &options, Some(&progress))
+            .unwrap();
+
+        assert_eq!(
+            progress.steps.into_inner(),
+            vec![StepKind::Decrypt, StepKind::Parse, StepKind::Apply]
+        );
+    }
+
+    #[test]
+    fn test_apply_bundle_uses_embedded_nonce_and_algorithm() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [1u8; KEY_SIZE];
+        let nonce = [9u8; NONCE_SIZE];
+        let algorithm = Algorithm::ChaCha20Poly1305;
+        let ciphertext = encrypt_data(br#"{"model": "opus"}"#, &key, &nonce, algorithm).unwrap();
+        let bundle = container::pack(&nonce, algorithm, &ciphertext);
+        let options = ApplyOptions {
+            config_path: Some(dir.path().join("settings.json")),
+            ..ApplyOptions::default()
+        };
+
+        let outcome = apply_encrypted_bundle_with_progress(&bundle, &key, &options, None).unwrap();
+
+        assert_eq!(outcome.config, serde_json::json!({"model": "opus"}));
+    }
+
+    #[test]
+    fn test_time_phases_records_all_four_phases() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+        let ciphertext =
+            encrypt_data(br#"{"model": "opus"}"#, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        let options = ApplyOptions {
+            config_path: Some(dir.path().join("settings.json")),
+            ..ApplyOptions::default()
+        };
+        let progress = RecordingProgress::default();
+
+        let (outcome, timings) = time_phases(Some(&progress), |listener| {
+            apply_encrypted_config_with_progress(&ciphertext, &key, &nonce, &options, listener)
+        })
+        .unwrap();
+
+        let names: Vec<_> = timings.phases().iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["decrypt", "parse", "merge", "write"]);
+        assert!(timings.phases().iter().all(|(_, d)| *d >= Duration::ZERO));
+        assert_eq!(timings.write, outcome.write_time);
+        assert!(timings.write > Duration::ZERO);
+        assert_eq!(
+            timings.total(),
+            timings.decrypt + timings.parse + timings.merge + timings.write
+        );
+        // The wrapped listener still sees every step
+        assert_eq!(
+            progress.steps.into_inner(),
+            vec![StepKind::Decrypt, StepKind::Parse, StepKind::Apply]
+        );
+    }
+
+    #[test]
+    fn test_workflow_options_defaults_match_cli() {
+        let options = WorkflowOptions::new();
+
+        assert_eq!(options.apply_options(), &ApplyOptions::default());
+        assert!(options.apply_options().backup);
+        assert_eq!(
+            options.apply_options().merge_strategy,
+            MergeStrategy::ReplaceArrays
+        );
+        assert!(!options.self_delete_enabled());
+    }
+
+    #[test]
+    fn test_workflow_options_fully_customized() {
+        let options = WorkflowOptions::new()
+            .dry_run(true)
+            .config_path("/tmp/profile/settings.json")
+            .merge_strategy(MergeStrategy::ConcatUniqueArrays)
+            .backup(false)
+            .validate_schema(true)
+            .self_delete(true);
+
+        assert_eq!(
+            options.apply_options(),
+            &ApplyOptions {
+                config_path: Some(PathBuf::from("/tmp/profile/settings.json")),
+                backup: false,
+                dry_run: true,
+                validate_schema: true,
+                merge_strategy: MergeStrategy::ConcatUniqueArrays,
+                ..ApplyOptions::default()
+            }
+        );
+        assert!(options.self_delete_enabled());
+    }
+
+    #[test]
+    fn test_run_workflow_applies_with_strategy() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        std::fs::write(&config_path, r#"{"allow": ["a"]}"#).unwrap();
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+        let ciphertext = encrypt_data(
+            br#"{"allow": ["a", "b"]}"#,
+            &key,
+            &nonce,
+            Algorithm::Aes256Gcm,
+        )
+        .unwrap();
+        let options = WorkflowOptions::new()
+            .config_path(&config_path)
+            .merge_strategy(MergeStrategy::ConcatUniqueArrays);
+
+        let outcome = run_workflow(&ciphertext, &key, &nonce, &options).unwrap();
+
+        assert_eq!(
+            outcome.apply.config,
+            serde_json::json!({"allow": ["a", "b"]})
+        );
+        assert_eq!(outcome.deletion, None);
+    }
+
+    #[test]
+    fn test_run_workflow_runs_migrations() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        std::fs::write(&config_path, r#"{"config_version": 1, "timeout": 1000}"#).unwrap();
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+        let ciphertext = encrypt_data(
+            br#"{"config_version": 2}"#,
+            &key,
+            &nonce,
+            Algorithm::Aes256Gcm,
+        )
+        .unwrap();
+        let registry = MigrationRegistry::new().with(1, 2, |config| {
+            if let Some(timeout) = config.as_object_mut().unwrap().remove("timeout") {
+                config["timeout_ms"] = timeout;
+            }
+            Ok(())
+        });
+        let options = WorkflowOptions::new()
+            .config_path(&config_path)
+            .migrations(registry);
+
+        let outcome = run_workflow(&ciphertext, &key, &nonce, &options).unwrap();
+
+        assert_eq!(
+            outcome.apply.config,
+            serde_json::json!({"config_version": 2, "timeout_ms": 1000})
+        );
+    }
+
+    #[test]
+    fn test_progress_stops_at_failed_step() {
+        let progress = RecordingProgress::default();
+
+        let result = decrypt_config_with_progress(
+            b"not a ciphertext",
+            &[1u8; KEY_SIZE],
+            &[2u8; NONCE_SIZE],
+            Some(&progress),
+        );
+
+        assert!(result.is_err());
+        assert_eq!(progress.steps.into_inner(), vec![StepKind::Decrypt]);
+    }
+
+    #[test]
+    fn test_plan_reports_changes_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        std::fs::write(&config_path, r#"{"model": "sonnet"}"#).unwrap();
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+        let ciphertext =
+            encrypt_data(br#"{"model": "opus"}"#, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            ..ApplyOptions::default()
+        };
+
+        let plan = plan_encrypted_config(&ciphertext, &key, &nonce, &options).unwrap();
+
+        assert!(plan.has_changes());
+        assert_eq!(
+            render_plan(&plan),
+            format!(
+                "Plan: update {}\n\
+                 - model: \"sonnet\"\n\
+                 + model: \"opus\"\n\
+                 0 setting(s) to add, 1 to overwrite\n\
+                 Overwritten settings:\n  \
+                 - model",
+                config_path.display()
+            )
+        );
+        assert_eq!(
+            std::fs::read_to_string(&config_path).unwrap(),
+            r#"{"model": "sonnet"}"#
+        );
+    }
+
+    #[test]
+    fn test_self_test_passes() {
+        assert!(self_test().is_ok());
+    }
+
+    #[test]
+    fn test_decrypt_config_rejects_empty_payload() {
+        let result = decrypt_config(&[], &[0u8; KEY_SIZE], &[0u8; NONCE_SIZE]);
+
+        assert!(matches!(result, Err(TokenProviderError::MissingPayload)));
+    }
+
+    #[test]
+    fn test_apply_plaintext_file_merges_into_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("settings.json");
+        let merge_path = dir.path().join("local.json");
+        std::fs::write(&config_path, r#"{"model": "sonnet", "theme": "dark"}"#).unwrap();
+        std::fs::write(&merge_path, r#"{"model": "opus", "env": {"DEBUG": "1"}}"#).unwrap();
+        let options = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            backup: false,
+            ..ApplyOptions::default()
+        };
+
+        let outcome = apply_plaintext_file(&merge_path, &options).unwrap();
+
+        let written: Value =
+            serde_json::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+        assert_eq!(
+            written,
+            serde_json::json!({"model": "opus", "theme": "dark", "env": {"DEBUG": "1"}})
+        );
+        assert_eq!(outcome.keys_overwritten, 1);
+
+        std::fs::write(&merge_path, "{ not json").unwrap();
+        assert!(matches!(
+            apply_plaintext_file(&merge_path, &options),
+            Err(TokenProviderError::JsonError(_))
+        ));
+    }
+
+    #[test]
+    fn test_apply_enforces_max_plaintext_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+        let plaintext = br#"{"model": "opus"}"#;
+        let ciphertext = encrypt_data(plaintext, &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+        let config_path = dir.path().join("settings.json");
+
+        let fits = ApplyOptions {
+            config_path: Some(config_path.clone()),
+            max_plaintext_size: plaintext.len(),
+            ..ApplyOptions::default()
+        };
+        let outcome =
+            apply_encrypted_config_with_progress(&ciphertext, &key, &nonce, &fits, None).unwrap();
+        assert_eq!(outcome.config, serde_json::json!({"model": "opus"}));
+
+        std::fs::remove_file(&config_path).unwrap();
+        let too_small = ApplyOptions {
+            max_plaintext_size: plaintext.len() - 1,
+            ..fits
+        };
+        let progress = RecordingProgress::default();
+        let result = apply_encrypted_config_with_progress(
+            &ciphertext,
+            &key,
+            &nonce,
+            &too_small,
+            Some(&progress),
+        );
+
+        match result {
+            Err(TokenProviderError::ValidationError(message)) => {
+                assert!(message.contains("too large"));
+            }
+            other => panic!("expected a size error, got {:?}", other),
+        }
+        // Rejected before parsing, and nothing was written
+        assert_eq!(progress.steps.into_inner(), vec![StepKind::Decrypt]);
+        assert!(!config_path.exists());
+    }
+
+    #[test]
+    fn test_decrypt_config_rejects_non_utf8() {
+        let key = [1u8; KEY_SIZE];
+        let nonce = [2u8; NONCE_SIZE];
+        let ciphertext = encrypt_data(&[0xff, 0xfe], &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+
+        let result = decrypt_config(&ciphertext, &key, &nonce);
+        assert!(matches!(result, Err(TokenProviderError::InvalidUtf8(_))));
+    }
+
+    #[test]
+    fn test_encrypt_config_roundtrip() {
+        let key = [3u8; KEY_SIZE];
+        let plaintext = br#"{"env": {"TOKEN": "abc"}}"#;
+
+        let encoded = encrypt_config(plaintext, &key).unwrap();
+        let sealed = general_purpose::STANDARD.decode(encoded).unwrap();
+        let decrypted =
+            decrypt_data_with_prepended_nonce(&sealed, &key, Algorithm::Aes256Gcm).unwrap();
+
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_apply_encrypted_configs_later_fragments_win() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [4u8; KEY_SIZE];
+        let fragment = |json: &str, nonce_byte: u8| {
+            let nonce = [nonce_byte; NONCE_SIZE];
+            let ciphertext =
+                encrypt_data(json.as_bytes(), &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+            (ciphertext, nonce)
+        };
+        let fragments = vec![
+            fragment(r#"{"model": "base", "env": {"A": "1", "B": "1"}}"#, 1),
+            fragment(r#"{"model": "override", "env": {"B": "2"}}"#, 2),
+            fragment(r#"{"env": {"B": "3", "TOKEN": "secret"}}"#, 3),
+        ];
+        let options = ApplyOptions {
+            config_path: Some(dir.path().join("settings.json")),
+            ..ApplyOptions::default()
+        };
+
+        let outcome = apply_encrypted_configs(fragments, &key, &options).unwrap();
+
+        assert_eq!(
+            outcome.config,
+            serde_json::json!({
+                "model": "override",
+                "env": {"A": "1", "B": "3", "TOKEN": "secret"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_apply_encrypted_configs_merges_fragments_with_strategy() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = [4u8; KEY_SIZE];
+        let fragment = |json: &str, nonce_byte: u8| {
+            let nonce = [nonce_byte; NONCE_SIZE];
+            let ciphertext =
+                encrypt_data(json.as_bytes(), &key, &nonce, Algorithm::Aes256Gcm).unwrap();
+            (ciphertext, nonce)
+        };
+        let config_path = dir.path().join("settings.json");
+        std::fs::write(&config_path, r#"{"permissions": {"allow": ["Read"]}}"#).unwrap();
+        let options = ApplyOptions {
+            config_path: Some(config_path),
+            merge_strategy: MergeStrategy::ConcatArrays,
+            ..ApplyOptions::default()
+        };
+
+        let outcome = apply_encrypted_configs(
+            vec![
+                fragment(r#"{"permissions": {"allow": ["Edit"]}}"#, 1),
+                fragment(r#"{"permissions": {"allow": ["Bash"]}}"#, 2),
+            ],
+            &key,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            outcome.config,
+            serde_json::json!({"permissions": {"allow": ["Read", "Edit", "Bash"]}})
+        );
+
+        let strict = ApplyOptions {
+            merge_strategy: MergeStrategy::StrictTypes,
+            dry_run: true,
+            ..options
+        };
+        let result = apply_encrypted_configs(
+            vec![
+                fragment(r#"{"model": {"name": "opus"}}"#, 3),
+                fragment(r#"{"model": "sonnet"}"#, 4),
+            ],
+            &key,
+            &strict,
+        );
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_apply_encrypted_configs_rejects_empty_list() {
+        let result =
+            apply_encrypted_configs(Vec::new(), &[4u8; KEY_SIZE], &ApplyOptions::default());
+
+        assert!(matches!(
+            result,
+            Err(TokenProviderError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_encrypt_config_with_nonce_uses_nonce_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let guard = NonceGuard::new(dir.path().join("nonce_history"));
+        let key = [3u8; KEY_SIZE];
+        let nonce = [8u8; NONCE_SIZE];
+        let plaintext = br#"{"env": {"TOKEN": "abc"}}"#;
+
+        let encoded =
+            encrypt_config_with_nonce(plaintext, &key, &nonce, &guard, NonceReusePolicy::Refuse)
+                .unwrap();
+        let sealed = general_purpose::STANDARD.decode(encoded).unwrap();
+        assert_eq!(&sealed[..NONCE_SIZE], &nonce);
+        let decrypted =
+            decrypt_data_with_prepended_nonce(&sealed, &key, Algorithm::Aes256Gcm).unwrap();
+        assert_eq!(decrypted, plaintext);
+
+        let reused =
+            encrypt_config_with_nonce(plaintext, &key, &nonce, &guard, NonceReusePolicy::Refuse);
+        assert!(matches!(
+            reused,
+            Err(TokenProviderError::ValidationError(_))
+        ));
+        assert!(
+            encrypt_config_with_nonce(plaintext, &key, &nonce, &guard, NonceReusePolicy::Warn)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_encrypt_config_rejects_invalid_json() {
+        let result = encrypt_config(b"{not json", &[3u8; KEY_SIZE]);
+
+        assert!(matches!(result, Err(TokenProviderError::JsonError(_))));
+    }
+}
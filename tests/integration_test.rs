@@ -0,0 +1,356 @@
+//! End-to-end tests for the decrypt-merge-apply pipeline
+
+use claude_token_provider::config::file_ops::ApplyOptions;
+use claude_token_provider::crypto::{container, encrypt_data, Algorithm, KEY_SIZE, NONCE_SIZE};
+use claude_token_provider::workflow::{apply_encrypted_config, verify_credentials};
+use claude_token_provider::TokenProviderError;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const KEY: [u8; KEY_SIZE] = [7u8; KEY_SIZE];
+const NONCE: [u8; NONCE_SIZE] = [9u8; NONCE_SIZE];
+
+fn encrypt_json(value: &Value) -> Vec<u8> {
+    let plaintext = serde_json::to_vec(value).unwrap();
+    encrypt_data(&plaintext, &KEY, &NONCE, Algorithm::Aes256Gcm).unwrap()
+}
+
+#[test]
+fn test_pipeline_merges_into_existing_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join(".claude").join("settings.json");
+    fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+    fs::write(
+        &config_path,
+        r#"{"env": {"EXISTING": "1"}, "theme": "dark"}"#,
+    )
+    .unwrap();
+
+    let ciphertext = encrypt_json(&json!({"env": {"TOKEN": "abc"}}));
+    let options = ApplyOptions {
+        config_path: Some(config_path.clone()),
+        ..ApplyOptions::default()
+    };
+
+    let written = apply_encrypted_config(&ciphertext, &KEY, &NONCE, &options).unwrap();
+    assert_eq!(written, config_path);
+
+    let merged: Value = serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    assert_eq!(
+        merged,
+        json!({"env": {"EXISTING": "1", "TOKEN": "abc"}, "theme": "dark"})
+    );
+}
+
+#[test]
+fn test_pipeline_creates_missing_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("nested").join("settings.json");
+
+    let ciphertext = encrypt_json(&json!({"permissions": {"allow": []}}));
+    let options = ApplyOptions {
+        config_path: Some(config_path.clone()),
+        ..ApplyOptions::default()
+    };
+
+    apply_encrypted_config(&ciphertext, &KEY, &NONCE, &options).unwrap();
+
+    let written: Value = serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    assert_eq!(written, json!({"permissions": {"allow": []}}));
+}
+
+#[test]
+fn test_pipeline_wrong_key_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("settings.json");
+
+    let ciphertext = encrypt_json(&json!({"env": {"TOKEN": "abc"}}));
+    let options = ApplyOptions {
+        config_path: Some(config_path.clone()),
+        ..ApplyOptions::default()
+    };
+
+    let result = apply_encrypted_config(&ciphertext, &[0u8; KEY_SIZE], &NONCE, &options);
+    assert!(matches!(
+        result,
+        Err(TokenProviderError::CryptoError { .. })
+    ));
+    assert!(!config_path.exists());
+}
+
+#[test]
+fn test_verify_accepts_correct_credentials() {
+    let ciphertext = encrypt_json(&json!({"env": {"TOKEN": "abc"}}));
+
+    assert!(verify_credentials(&ciphertext, &KEY, &NONCE).is_ok());
+}
+
+#[test]
+fn test_verify_tampered_payload_reports_crypto_error_and_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut ciphertext = encrypt_json(&json!({"env": {"TOKEN": "abc"}}));
+    ciphertext[0] ^= 0x01;
+
+    let result = verify_credentials(&ciphertext, &KEY, &NONCE);
+
+    assert!(matches!(
+        result,
+        Err(TokenProviderError::CryptoError { .. })
+    ));
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_decrypt_subcommand_prints_json_without_applying() {
+    let dir = tempfile::tempdir().unwrap();
+    let payload_path = dir.path().join("payload.bin");
+    fs::write(
+        &payload_path,
+        encrypt_json(&json!({"env": {"TOKEN": "abc"}})),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-token-provider"))
+        .args([
+            "decrypt",
+            "--key",
+            &hex::encode(KEY),
+            "--nonce",
+            &hex::encode(NONCE),
+        ])
+        .arg("--payload")
+        .arg(&payload_path)
+        .env("HOME", dir.path())
+        .env_remove("SELF_DELETE")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let printed: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(printed, json!({"env": {"TOKEN": "abc"}}));
+    assert!(!dir.path().join(".claude").exists());
+}
+
+fn run_check(config_path: &std::path::Path) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_claude-token-provider"))
+        .arg("check")
+        .arg("--config-path")
+        .arg(config_path)
+        .env_remove("SELF_DELETE")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_check_subcommand_accepts_valid_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("settings.json");
+    fs::write(&config_path, r#"{"model": "opus"}"#).unwrap();
+
+    let output = run_check(&config_path);
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(&config_path).unwrap(),
+        r#"{"model": "opus"}"#
+    );
+}
+
+#[test]
+fn test_check_subcommand_rejects_invalid_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("settings.json");
+    fs::write(&config_path, "{ not json").unwrap();
+
+    let output = run_check(&config_path);
+
+    assert_eq!(output.status.code(), Some(5), "{:?}", output);
+}
+
+#[test]
+fn test_check_subcommand_reports_missing_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("settings.json");
+
+    let output = run_check(&config_path);
+
+    assert_eq!(output.status.code(), Some(6), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No configuration file found"));
+    assert!(!config_path.exists());
+}
+
+#[test]
+fn test_json_format_reports_subcommand_success() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("settings.json");
+    fs::write(&config_path, r#"{"model": "opus"}"#).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-token-provider"))
+        .args(["check", "--format", "json", "--config-path"])
+        .arg(&config_path)
+        .env_remove("SELF_DELETE")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["status"], "ok");
+    assert_eq!(report["command"], "check");
+    assert_eq!(report["valid"], true);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-token-provider"))
+        .args(["selftest", "--format", "json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        report,
+        json!({"status": "ok", "command": "selftest", "passed": true})
+    );
+}
+
+#[test]
+fn test_json_format_keeps_self_delete_prompt_off_stdout() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("settings.json");
+    let merge_path = dir.path().join("update.json");
+    fs::write(&merge_path, r#"{"model": "opus"}"#).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_claude-token-provider"))
+        .args(["--format", "json", "--self-delete", "--merge-file"])
+        .arg(&merge_path)
+        .arg("--config-path")
+        .arg(&config_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"n\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Proceed with self-deletion?"));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["status"], "ok");
+    assert_eq!(report["keys_added"], 1);
+}
+
+#[test]
+fn test_show_config_path_prints_override_and_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("profile").join("settings.json");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-token-provider"))
+        .arg("--show-config-path")
+        .arg("--config-path")
+        .arg(&config_path)
+        .env("HOME", dir.path())
+        .env_remove("SELF_DELETE")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim_end(),
+        config_path.display().to_string()
+    );
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_bundle_option_applies_config_with_only_a_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let bundle_path = dir.path().join("config.bundle");
+    let config_path = dir.path().join("settings.json");
+    let ciphertext = encrypt_json(&json!({"env": {"TOKEN": "abc"}}));
+    fs::write(
+        &bundle_path,
+        container::pack(&NONCE, Algorithm::Aes256Gcm, &ciphertext),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-token-provider"))
+        .args(["--quiet", "--key", &hex::encode(KEY)])
+        .arg("--bundle")
+        .arg(&bundle_path)
+        .arg("--config-path")
+        .arg(&config_path)
+        .env("HOME", dir.path())
+        .env_remove("SELF_DELETE")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let written: Value = serde_json::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    assert_eq!(written, json!({"env": {"TOKEN": "abc"}}));
+}
+
+fn run_plan(payload_path: &std::path::Path, config_path: &std::path::Path) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_claude-token-provider"))
+        .args([
+            "plan",
+            "--key",
+            &hex::encode(KEY),
+            "--nonce",
+            &hex::encode(NONCE),
+        ])
+        .arg("--payload")
+        .arg(payload_path)
+        .arg("--config-path")
+        .arg(config_path)
+        .env_remove("SELF_DELETE")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_plan_subcommand_prints_diff_and_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let payload_path = dir.path().join("payload.bin");
+    let config_path = dir.path().join("settings.json");
+    let existing = r#"{"model": "sonnet", "theme": "dark"}"#;
+    fs::write(&config_path, existing).unwrap();
+    fs::write(
+        &payload_path,
+        encrypt_json(&json!({"model": "opus", "env": {"TOKEN": "abc"}})),
+    )
+    .unwrap();
+
+    let output = run_plan(&payload_path, &config_path);
+
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        format!(
+            "Plan: update {}\n\
+             - model: \"sonnet\"\n\
+             + model: \"opus\"\n\
+             + env: {{\"TOKEN\":\"abc\"}}\n\
+             1 setting(s) to add, 1 to overwrite\n\
+             Overwritten settings:\n  \
+             - model\n",
+            config_path.display()
+        )
+    );
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), existing);
+}
+
+#[test]
+fn test_plan_subcommand_exits_12_when_up_to_date() {
+    let dir = tempfile::tempdir().unwrap();
+    let payload_path = dir.path().join("payload.bin");
+    let config_path = dir.path().join("settings.json");
+    fs::write(&config_path, r#"{"model": "opus"}"#).unwrap();
+    fs::write(&payload_path, encrypt_json(&json!({"model": "opus"}))).unwrap();
+
+    let output = run_plan(&payload_path, &config_path);
+
+    assert_eq!(output.status.code(), Some(12), "{:?}", output);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("No changes."));
+}